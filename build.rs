@@ -0,0 +1,140 @@
+//! Embeds the static assets. Each asset gets a content hash for its URL,
+//! and the compressible ones get precompressed gzip and Brotli variants.
+
+use std::env;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::hash::Hasher;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+const STATICS_PATH: &str = "src/statics";
+
+/// The service worker has its own route, which injects the version.
+const SERVICE_WORKER_PATH: &str = "sw.js";
+
+/// Only the stylesheet and the WOFF2 fonts are used from the Font Awesome distribution.
+const FONT_AWESOME_PREFIX: &str = "fontawesome-free-";
+const FONT_AWESOME_STYLESHEET_PATH: &str = "css/all.css";
+const FONT_AWESOME_FONTS_PATH: &str = "webfonts/";
+
+/// These are compressed already.
+const INCOMPRESSIBLE_EXTENSIONS: &[&str] = &["png", "woff2"];
+
+fn main() -> Result<(), Box<dyn Error>> {
+    println!("cargo:rerun-if-changed={}", STATICS_PATH);
+
+    let statics_path = Path::new(&env::var("CARGO_MANIFEST_DIR")?).join(STATICS_PATH);
+    let out_path = PathBuf::from(env::var("OUT_DIR")?).join("assets");
+    let mut paths = Vec::new();
+    walk(&statics_path, &mut paths)?;
+    paths.sort();
+
+    let mut registry = String::from("pub static ASSETS: &[Asset] = &[\n");
+    for path in paths {
+        let relative_path = path.strip_prefix(&statics_path)?.to_string_lossy().replace('\\', "/");
+        if !is_embedded(&relative_path) {
+            continue;
+        }
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let content = fs::read(&path)?;
+        let mut hasher = seahash::SeaHasher::new();
+        hasher.write(&content);
+
+        let (gzip, brotli) = if is_compressible(&relative_path) {
+            let compressed_path = out_path.join(&relative_path);
+            fs::create_dir_all(compressed_path.parent().unwrap())?;
+            (
+                write_compressed(&compressed_path, "gz", gzip(&content)?, content.len())?,
+                write_compressed(&compressed_path, "br", brotli(&content)?, content.len())?,
+            )
+        } else {
+            (None, None)
+        };
+
+        writeln!(
+            registry,
+            "    Asset {{ path: {:?}, hash: \"{:016x}\", content: include_bytes!({:?}), gzip: {}, brotli: {} }},",
+            relative_path,
+            hasher.finish(),
+            path,
+            include_option(gzip),
+            include_option(brotli),
+        )?;
+    }
+    registry.push_str("];\n");
+    fs::write(PathBuf::from(env::var("OUT_DIR")?).join("assets.rs"), registry)?;
+    Ok(())
+}
+
+fn walk(path: &Path, paths: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(&path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_embedded(relative_path: &str) -> bool {
+    if relative_path == SERVICE_WORKER_PATH {
+        return false;
+    }
+    if relative_path.starts_with(FONT_AWESOME_PREFIX) {
+        let inner_path = relative_path.splitn(2, '/').nth(1).unwrap_or_default();
+        return inner_path == FONT_AWESOME_STYLESHEET_PATH
+            || (inner_path.starts_with(FONT_AWESOME_FONTS_PATH) && inner_path.ends_with(".woff2"));
+    }
+    true
+}
+
+fn is_compressible(relative_path: &str) -> bool {
+    !INCOMPRESSIBLE_EXTENSIONS
+        .iter()
+        .any(|extension| relative_path.ends_with(&format!(".{}", extension)))
+}
+
+fn gzip(content: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(content)?;
+    Ok(encoder.finish()?)
+}
+
+fn brotli(content: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut compressed = Vec::new();
+    {
+        let mut encoder = brotli::CompressorWriter::new(&mut compressed, 4096, 11, 22);
+        encoder.write_all(content)?;
+    }
+    Ok(compressed)
+}
+
+/// Writes the compressed variant next to the original path, unless it doesn't save anything.
+fn write_compressed(
+    path: &Path,
+    extension: &str,
+    compressed: Vec<u8>,
+    original_len: usize,
+) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    if compressed.len() >= original_len {
+        return Ok(None);
+    }
+    let path = PathBuf::from(format!("{}.{}", path.display(), extension));
+    fs::write(&path, compressed)?;
+    Ok(Some(path))
+}
+
+fn include_option(path: Option<PathBuf>) -> String {
+    match path {
+        Some(path) => format!("Some(include_bytes!({:?}))", path),
+        None => "None".into(),
+    }
+}
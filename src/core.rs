@@ -1,5 +1,13 @@
+pub mod auth;
 pub mod bus;
+pub mod control;
+pub mod datetime;
 pub mod db;
+pub mod logging;
 pub mod message;
+pub mod metrics;
+pub mod staleness;
+pub mod tags;
 pub mod thread;
 pub mod value;
+pub mod writes;
@@ -0,0 +1,103 @@
+//! Password hashing and random tokens.
+
+use std::num::NonZeroU32;
+
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, pbkdf2};
+
+use crate::prelude::*;
+
+const ALGORITHM_NAME: &str = "pbkdf2-sha256";
+const ITERATIONS: u32 = 100_000;
+const SALT_LENGTH: usize = 16;
+const TOKEN_LENGTH: usize = 32;
+
+/// Hashes the password into a string which is then stored in the settings.
+///
+/// The format is `pbkdf2-sha256$<iterations>$<salt>$<hash>`.
+pub fn hash_password(password: &str) -> Result<String> {
+    let mut salt = [0_u8; SALT_LENGTH];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| "failed to generate a salt")?;
+    let mut hash = [0_u8; digest::SHA256_OUTPUT_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(ITERATIONS).unwrap(),
+        &salt,
+        password.as_bytes(),
+        &mut hash,
+    );
+    Ok(format!(
+        "{}${}${}${}",
+        ALGORITHM_NAME,
+        ITERATIONS,
+        base64::encode(&salt),
+        base64::encode(&hash),
+    ))
+}
+
+/// Verifies the password against the hash produced by `hash_password`.
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    let parts: Vec<&str> = password_hash.split('$').collect();
+    if parts.len() != 4 || parts[0] != ALGORITHM_NAME {
+        error!("Unsupported password hash format.");
+        return false;
+    }
+    let (iterations, salt, hash) = match (
+        parts[1].parse::<u32>().ok().and_then(NonZeroU32::new),
+        base64::decode(parts[2]),
+        base64::decode(parts[3]),
+    ) {
+        (Some(iterations), Ok(salt), Ok(hash)) => (iterations, salt, hash),
+        _ => {
+            error!("Malformed password hash.");
+            return false;
+        }
+    };
+    pbkdf2::verify(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        &salt,
+        password.as_bytes(),
+        &hash,
+    )
+    .is_ok()
+}
+
+/// Generates a random URL-safe token.
+pub fn generate_token() -> Result<String> {
+    let mut token = [0_u8; TOKEN_LENGTH];
+    SystemRandom::new()
+        .fill(&mut token)
+        .map_err(|_| "failed to generate a token")?;
+    Ok(base64::encode_config(&token, base64::URL_SAFE_NO_PAD))
+}
+
+/// Hashes a token in order to store it in the database.
+/// Tokens are long and random, thus a single SHA-256 is enough.
+pub fn hash_token(token: &str) -> String {
+    base64::encode(digest::digest(&digest::SHA256, token.as_bytes()).as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_password_ok() -> Result {
+        assert!(verify_password("secret", &hash_password("secret")?));
+        Ok(())
+    }
+
+    #[test]
+    fn verify_wrong_password_fails() -> Result {
+        assert!(!verify_password("wrong", &hash_password("secret")?));
+        Ok(())
+    }
+
+    #[test]
+    fn verify_malformed_hash_fails() {
+        assert!(!verify_password("secret", "secret"));
+    }
+}
@@ -3,13 +3,17 @@
 //! The bus implements many-producer-many-consumer queue and allows each service
 //! to listen to each other service.
 
+use crate::core::metrics::METRICS;
+use crate::core::tags;
 use crate::prelude::*;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
+/// Bus handle. It's cloneable, so that services may subscribe after the dispatcher is spawned.
+#[derive(Clone)]
 pub struct Bus {
-    /// Service message inbox senders.
-    service_txs: Vec<Sender>,
+    /// Service message inboxes.
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
 
     /// The bus message inbox sender.
     tx: Sender,
@@ -23,11 +27,12 @@ pub struct Bus {
 impl Bus {
     pub fn new(message_counter: Arc<AtomicU64>) -> Self {
         let (tx, rx) = crossbeam::channel::unbounded::<Message>();
+        METRICS.register_queue("system::bus", tx.clone());
         Self {
             tx,
             rx,
             message_counter,
-            service_txs: Vec::new(),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
@@ -37,22 +42,36 @@ impl Bus {
     }
 
     /// Get a new receiver to subscribe to the bus.
-    pub fn add_rx(&mut self) -> Receiver {
+    /// The name is used to report the subscriber queue depth.
+    pub fn add_rx(&mut self, name: &str) -> Receiver {
+        self.add_rx_tagged(name, Vec::new())
+    }
+
+    /// Get a new receiver which gets only the messages of the sensors with any of the tags.
+    /// It gets all the messages if the tags are empty.
+    pub fn add_rx_tagged(&mut self, name: &str, tags: Vec<String>) -> Receiver {
         let (tx, rx) = crossbeam::channel::unbounded();
-        self.service_txs.push(tx);
+        METRICS.register_queue(name, tx.clone());
+        self.subscribers.lock().unwrap().push(Subscriber { tx, tags });
         rx
     }
 
     /// Spawn the bus dispatcher thread.
-    pub fn spawn(self) -> Result {
+    pub fn spawn(&self) -> Result {
         info!("Spawning message bus…");
+        let bus = self.clone();
         thread::Builder::new().name("system::bus".into()).spawn(move || {
-            for message in &self.rx {
+            let _guard = DispatcherGuard::new();
+            for mut message in &bus.rx {
+                tags::apply_overrides(&mut message.sensor);
                 Self::log_message(&message);
-                for tx in self.service_txs.iter() {
-                    message.clone().send_and_forget(&tx);
-                }
-                let number = self.message_counter.fetch_add(1, Ordering::Relaxed);
+                // A stopped service drops its receiver, thus the sender gets disconnected and unsubscribed.
+                bus.subscribers
+                    .lock()
+                    .unwrap()
+                    .retain(|subscriber| !subscriber.accepts(&message) || subscriber.tx.send(message.clone()).is_ok());
+                METRICS.record_dispatch();
+                let number = bus.message_counter.fetch_add(1, Ordering::Relaxed);
                 debug!("Dispatched (#{}) {}", number, &message.sensor.id);
             }
             unreachable!();
@@ -68,6 +87,35 @@ impl Bus {
     }
 }
 
+/// Reports the dispatcher thread as alive until it exits, including by a panic.
+struct DispatcherGuard;
+
+impl DispatcherGuard {
+    fn new() -> Self {
+        METRICS.set_dispatcher_alive(true);
+        DispatcherGuard
+    }
+}
+
+impl Drop for DispatcherGuard {
+    fn drop(&mut self) {
+        METRICS.set_dispatcher_alive(false);
+    }
+}
+
+struct Subscriber {
+    tx: Sender,
+
+    /// Sensor tags the subscriber is interested in, empty means all the sensors.
+    tags: Vec<String>,
+}
+
+impl Subscriber {
+    fn accepts(&self, message: &Message) -> bool {
+        self.tags.is_empty() || self.tags.iter().any(|tag| message.sensor.has_tag(tag))
+    }
+}
+
 impl Message {
     /// Send the message via the specified sender and log and ignore any errors.
     pub fn send_and_forget(self, tx: &Sender) {
@@ -76,3 +124,19 @@ impl Message {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tagged_subscriber_ok() {
+        let (tx, _rx) = crossbeam::channel::unbounded();
+        let subscriber = Subscriber {
+            tx,
+            tags: vec!["energy".into(), "heating".into()],
+        };
+        assert!(subscriber.accepts(&Message::new("youless::power").tag("energy")));
+        assert!(!subscriber.accepts(&Message::new("clock")));
+    }
+}
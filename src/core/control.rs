@@ -0,0 +1,40 @@
+//! Describes how a sensor may be controlled from the web interface.
+
+use crate::prelude::*;
+
+/// Input which is rendered for a controllable sensor.
+/// Submitting it sends a `Write` message with the new value to the sensor.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum Control {
+    /// On/off switch of a boolean sensor.
+    Toggle,
+
+    /// Range input of a numeric sensor.
+    Slider {
+        min: f64,
+        max: f64,
+
+        #[serde(default = "default_step")]
+        step: f64,
+    },
+
+    /// Number input of a numeric sensor.
+    Number {
+        #[serde(default)]
+        min: Option<f64>,
+
+        #[serde(default)]
+        max: Option<f64>,
+
+        #[serde(default = "default_step")]
+        step: f64,
+    },
+
+    /// Choice of one of the states of a text sensor.
+    Select { options: Vec<String> },
+}
+
+const fn default_step() -> f64 {
+    1.0
+}
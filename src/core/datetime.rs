@@ -0,0 +1,170 @@
+//! Time zone and format of the displayed timestamps.
+//!
+//! The timestamps are stored as Unix time, so the time zone only matters for the web interface
+//! and the scripts, which look at the wall-clock time.
+
+use crate::prelude::*;
+use crate::settings::{Clock, DateOrder, DateTimeFormat};
+use chrono_tz::Tz;
+use lazy_static::lazy_static;
+use std::sync::RwLock;
+
+lazy_static! {
+    /// Process-wide display settings, taken from the settings.
+    static ref DISPLAY: RwLock<Display> = RwLock::new(Display::default());
+}
+
+#[derive(Default, Clone, Copy)]
+struct Display {
+    /// `None` stands for the system time zone.
+    timezone: Option<Tz>,
+
+    format: DateTimeFormat,
+}
+
+/// Replaces the display settings, for instance, when the settings get applied.
+pub fn set_display(timezone: Option<Tz>, format: DateTimeFormat) {
+    *DISPLAY.write().unwrap() = Display { timezone, format };
+}
+
+/// Returns the name of the display time zone, for example: `Europe/Amsterdam`.
+/// Returns `None` for the system time zone.
+pub fn timezone_name() -> Option<&'static str> {
+    DISPLAY.read().unwrap().timezone.map(|timezone| timezone.name())
+}
+
+pub fn datetime_format() -> DateTimeFormat {
+    DISPLAY.read().unwrap().format
+}
+
+/// Converts the timestamp into the display time zone.
+pub fn to_display<T: TimeZone>(datetime: &DateTime<T>) -> DateTime<FixedOffset> {
+    convert(DISPLAY.read().unwrap().timezone, datetime)
+}
+
+/// Interprets the wall-clock time in the display time zone.
+pub fn from_display(datetime: &NaiveDateTime) -> Option<DateTime<Local>> {
+    match DISPLAY.read().unwrap().timezone {
+        Some(timezone) => timezone
+            .from_local_datetime(datetime)
+            .earliest()
+            .map(|datetime| datetime.with_timezone(&Local)),
+        None => Local.from_local_datetime(datetime).earliest(),
+    }
+}
+
+/// Formats the timestamp for the web interface, for example: `Oct 19, 14:03:05`.
+pub fn format<T: TimeZone>(datetime: &DateTime<T>) -> String {
+    let display = *DISPLAY.read().unwrap();
+    convert(display.timezone, datetime)
+        .format(&display.format.strftime())
+        .to_string()
+}
+
+fn convert<T: TimeZone>(timezone: Option<Tz>, datetime: &DateTime<T>) -> DateTime<FixedOffset> {
+    match timezone {
+        Some(timezone) => fix(datetime.with_timezone(&timezone)),
+        None => fix(datetime.with_timezone(&Local)),
+    }
+}
+
+fn fix<T: TimeZone>(datetime: DateTime<T>) -> DateTime<FixedOffset> {
+    let offset = datetime.offset().fix();
+    datetime.with_timezone(&offset)
+}
+
+impl DateTimeFormat {
+    /// Returns the [`strftime`](https://docs.rs/chrono/0.4.0/chrono/format/strftime/index.html) format.
+    pub fn strftime(&self) -> String {
+        let date = match self.date_order {
+            DateOrder::DayMonthYear => "%d %b,",
+            DateOrder::MonthDayYear => "%b %d,",
+            DateOrder::YearMonthDay => "%Y-%m-%d",
+        };
+        let time = match self.clock {
+            Clock::H24 => "%H:%M:%S",
+            Clock::H12 => "%-I:%M:%S %p",
+        };
+        format!("{} {}", date, time)
+    }
+
+    /// Returns the `strftime` format of a date alone, for example: `Oct 19`.
+    pub fn date_strftime(&self) -> &'static str {
+        match self.date_order {
+            DateOrder::DayMonthYear => "%d %b",
+            DateOrder::MonthDayYear => "%b %d",
+            DateOrder::YearMonthDay => "%Y-%m-%d",
+        }
+    }
+
+    /// Returns the `strftime` format of a whole hour, for example: `14:00`.
+    pub fn hour_strftime(&self) -> &'static str {
+        match self.clock {
+            Clock::H24 => "%H:00",
+            Clock::H12 => "%-I %p",
+        }
+    }
+
+    /// Returns the [Moment.js format](https://momentjs.com/docs/#/displaying/format/) of the chart tooltips.
+    pub fn moment_tooltip_format(&self) -> String {
+        let date = match self.date_order {
+            DateOrder::DayMonthYear => "DD MMM",
+            DateOrder::MonthDayYear => "MMM DD",
+            DateOrder::YearMonthDay => "YYYY-MM-DD",
+        };
+        let time = match self.clock {
+            Clock::H24 => "HH:mm:ss.SSS",
+            Clock::H12 => "h:mm:ss.SSS A",
+        };
+        format!("{} {}", date, time)
+    }
+
+    /// Returns the Moment.js formats of the chart axis labels by time unit.
+    pub fn moment_display_formats(&self) -> [(&'static str, &'static str); 4] {
+        match self.clock {
+            Clock::H24 => [
+                ("millisecond", "HH:mm:ss.SSS"),
+                ("second", "HH:mm:ss"),
+                ("minute", "HH:mm"),
+                ("hour", "HH"),
+            ],
+            Clock::H12 => [
+                ("millisecond", "h:mm:ss.SSS A"),
+                ("second", "h:mm:ss A"),
+                ("minute", "h:mm A"),
+                ("hour", "h A"),
+            ],
+        }
+    }
+
+    pub fn is_hour12(&self) -> bool {
+        self.clock == Clock::H12
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_ok() {
+        let datetime = Utc.ymd(2020, 7, 1).and_hms(12, 30, 0);
+        let converted = convert(Some(chrono_tz::Europe::Amsterdam), &datetime);
+        assert_eq!(converted.to_rfc3339(), "2020-07-01T14:30:00+02:00");
+        assert_eq!(converted, datetime);
+    }
+
+    #[test]
+    fn strftime_ok() {
+        let datetime = Utc.ymd(2020, 7, 1).and_hms(14, 30, 5);
+        let format = DateTimeFormat {
+            clock: Clock::H12,
+            date_order: DateOrder::DayMonthYear,
+        };
+        assert_eq!(datetime.format(&format.strftime()).to_string(), "01 Jul, 2:30:05 PM");
+        assert_eq!(
+            datetime.format(&DateTimeFormat::default().strftime()).to_string(),
+            "Jul 01, 14:30:05"
+        );
+    }
+}
@@ -1,13 +1,15 @@
 //! Database interface.
 
+use self::api_token::{ApiToken, Scope};
 use crate::prelude::*;
 use chrono::prelude::*;
 use rusqlite::types::FromSql;
 use rusqlite::{params, Row};
-use rusqlite::{OptionalExtension, NO_PARAMS};
+use rusqlite::{OptionalExtension, TransactionBehavior, NO_PARAMS};
 use std::path::Path;
 use std::sync::{Arc, Mutex, MutexGuard};
 
+pub mod api_token;
 pub mod reading;
 pub mod sensor;
 pub mod thread;
@@ -23,7 +25,9 @@ const DATABASE_SCRIPT: &str = r#"
         title TEXT DEFAULT NULL,
         room_title TEXT DEFAULT NULL, -- renamed to `location`
         value JSON NOT NULL,
-        expires_at INTEGER NOT NULL -- deprecated and unused
+        expires_at INTEGER NOT NULL, -- deprecated and unused
+        control JSON DEFAULT NULL,
+        tags JSON NOT NULL DEFAULT '[]'
     );
 
     CREATE TABLE IF NOT EXISTS readings (
@@ -40,8 +44,32 @@ const DATABASE_SCRIPT: &str = r#"
         value JSON NOT NULL,
         expires_at INTEGER NULL -- unix time, milliseconds
     );
+
+    CREATE TABLE IF NOT EXISTS sessions (
+        token_hash TEXT NOT NULL PRIMARY KEY,
+        user_name TEXT NOT NULL,
+        csrf_token TEXT NOT NULL,
+        expires_at INTEGER NOT NULL -- unix time, milliseconds
+    );
+
+    CREATE TABLE IF NOT EXISTS api_tokens (
+        name TEXT NOT NULL PRIMARY KEY,
+        token_hash TEXT NOT NULL UNIQUE,
+        scopes JSON NOT NULL,
+        created_at INTEGER NOT NULL, -- unix time, milliseconds
+        last_used_at INTEGER NULL -- unix time, milliseconds
+    );
+
+    CREATE TABLE IF NOT EXISTS dashboards (
+        slug TEXT NOT NULL PRIMARY KEY,
+        dashboard JSON NOT NULL
+    );
 "#;
 
+/// Columns which have been added to `sensors` later, they're added to an older database by the migration.
+const ADDED_SENSOR_COLUMNS: &[(&str, &str)] =
+    &[("control", "JSON DEFAULT NULL"), ("tags", "JSON NOT NULL DEFAULT '[]'")];
+
 /// Wraps `rusqlite::Connection` and provides the high-level database methods.
 #[derive(Clone)]
 pub struct Connection {
@@ -55,6 +83,7 @@ impl Connection {
         };
         // language=sql
         connection.connection()?.execute_batch(DATABASE_SCRIPT)?;
+        migrate(&connection.connection()?)?;
         Ok(connection)
     }
 
@@ -75,6 +104,86 @@ impl Connection {
             .collect()
     }
 
+    /// Selects the latest readings for the sensors in the specified location, with the tag
+    /// and with sensor IDs matching the [`GLOB`](https://www.sqlite.org/lang_corefunc.html#glob) pattern.
+    pub fn select_actuals_by(
+        &self,
+        location: Option<&str>,
+        pattern: Option<&str>,
+        tag: Option<&str>,
+    ) -> Result<Vec<(Sensor, Reading)>> {
+        self.connection()?
+            .prepare_cached(
+                // language=sql
+                r#"
+                -- noinspection SqlResolve @ routine/"json_each"
+                SELECT * FROM sensors
+                WHERE
+                    (?1 IS NULL OR room_title = ?1)
+                    AND (?2 IS NULL OR sensor_id GLOB ?2)
+                    AND (?3 IS NULL OR EXISTS (SELECT 1 FROM json_each(tags) WHERE json_each.value = ?3))
+                ORDER BY room_title, sensor_id
+                "#,
+            )?
+            .query_map(params![location, pattern, tag], get_sensor_reading)?
+            .map(|r| r.map_err(Into::into))
+            .collect()
+    }
+
+    /// Selects the latest readings for the sensors matching the search query.
+    pub fn search_actuals(&self, query: &SensorQuery) -> Result<Vec<(Sensor, Reading)>> {
+        self.connection()?
+            .prepare_cached(&format!(
+                // language=sql
+                r#"
+                -- noinspection SqlResolve @ routine/"json_extract"
+                SELECT * FROM sensors
+                WHERE
+                    (?1 IS NULL OR instr(lower(sensor_id), ?1) OR instr(lower(title), ?1) OR instr(lower(room_title), ?1))
+                    AND (?2 IS NULL OR json_extract(value, '$.type') = ?2)
+                    AND (?3 IS NULL OR EXISTS (SELECT 1 FROM json_each(tags) WHERE json_each.value = ?3))
+                ORDER BY {}
+                "#,
+                query.order.to_sql(),
+            ))?
+            .query_map(
+                params![query.search.map(str::to_lowercase), query.value_type, query.tag],
+                get_sensor_reading,
+            )?
+            .map(|r| r.map_err(Into::into))
+            .collect()
+    }
+
+    /// Selects the distinct tags of the sensors.
+    pub fn select_tags(&self) -> Result<Vec<String>> {
+        self.connection()?
+            .prepare_cached(
+                // language=sql
+                r#"
+                -- noinspection SqlResolve @ routine/"json_each"
+                SELECT DISTINCT json_each.value AS tag FROM sensors, json_each(sensors.tags) ORDER BY tag
+                "#,
+            )?
+            .query_map(NO_PARAMS, |row| row.get(0))?
+            .map(|r| r.map_err(Into::into))
+            .collect()
+    }
+
+    /// Selects the distinct value types of the sensors, for example: `Temperature`.
+    pub fn select_value_types(&self) -> Result<Vec<String>> {
+        self.connection()?
+            .prepare_cached(
+                // language=sql
+                r#"
+                -- noinspection SqlResolve @ routine/"json_extract"
+                SELECT DISTINCT json_extract(value, '$.type') AS type FROM sensors ORDER BY type
+                "#,
+            )?
+            .query_map(NO_PARAMS, |row| row.get(0))?
+            .map(|r| r.map_err(Into::into))
+            .collect()
+    }
+
     /// Selects the database size.
     pub fn select_size(&self) -> Result<u64> {
         Ok(self
@@ -90,6 +199,14 @@ impl Connection {
             .map(|v| v as u64)?)
     }
 
+    /// Checks that the database is writable by beginning and rolling back a write transaction.
+    pub fn check_writable(&self) -> Result {
+        self.connection()?
+            .transaction_with_behavior(TransactionBehavior::Immediate)?
+            .rollback()?;
+        Ok(())
+    }
+
     /// Selects the specified sensor.
     pub fn select_sensor(&self, sensor_id: &str) -> Result<Option<(Sensor, Reading)>> {
         Ok(self
@@ -108,6 +225,40 @@ impl Connection {
         Ok(())
     }
 
+    /// Deletes the single reading. If it's the latest one, the previous reading becomes the sensor value,
+    /// or the value becomes `None` if there're no readings left.
+    /// Returns whether the reading has existed.
+    pub fn delete_reading(&self, sensor_id: &str, timestamp: &DateTime<Local>) -> Result<bool> {
+        let sensor_pk = hash_sensor_id(sensor_id);
+        let timestamp = timestamp.timestamp_millis();
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction()?;
+        let is_deleted = transaction
+            // language=sql
+            .prepare_cached(r"DELETE FROM readings WHERE sensor_fk = ?1 AND timestamp = ?2")?
+            .execute(params![sensor_pk, timestamp])?
+            != 0;
+        transaction
+            // language=sql
+            .prepare_cached(
+                r#"
+                UPDATE sensors SET
+                    timestamp = COALESCE(
+                        (SELECT timestamp FROM readings WHERE sensor_fk = ?1 ORDER BY timestamp DESC LIMIT 1),
+                        timestamp
+                    ),
+                    value = COALESCE(
+                        (SELECT value FROM readings WHERE sensor_fk = ?1 ORDER BY timestamp DESC LIMIT 1),
+                        ?3
+                    )
+                WHERE pk = ?1 AND timestamp = ?2
+                "#,
+            )?
+            .execute(params![sensor_pk, timestamp, serde_json::to_string(&Value::None)?])?;
+        transaction.commit()?;
+        Ok(is_deleted)
+    }
+
     /// Selects the specified sensor readings within the specified period.
     pub fn select_values<T: FromSql>(
         &self,
@@ -135,6 +286,341 @@ impl Connection {
             .collect()
     }
 
+    /// Selects the latest numeric value of the sensor as of each of the timestamps,
+    /// `None` if there was no reading yet. It's used to find increments of a cumulative counter.
+    pub fn select_values_at(&self, sensor_id: &str, timestamps: &[DateTime<Local>]) -> Result<Vec<Option<f64>>> {
+        let connection = self.connection()?;
+        // language=sql
+        let mut statement = connection.prepare_cached(
+            r#"
+            -- noinspection SqlResolve @ routine/"json_extract"
+            SELECT json_extract(value, '$.value') as value
+            FROM readings
+            WHERE sensor_fk = ?1 AND timestamp <= ?2
+            ORDER BY timestamp DESC
+            LIMIT 1
+            "#,
+        )?;
+        let sensor_pk = hash_sensor_id(sensor_id);
+        timestamps
+            .iter()
+            .map(|timestamp| -> Result<Option<f64>> {
+                Ok(statement
+                    .query_row(params![sensor_pk, timestamp.timestamp_millis()], |row| {
+                        row.get::<_, f64>("value")
+                    })
+                    .optional()?)
+            })
+            .collect()
+    }
+
+    /// Selects the specified sensor readings within the specified period, `until` is exclusive.
+    pub fn select_values_between<T: FromSql>(
+        &self,
+        sensor_id: &str,
+        since: &DateTime<Local>,
+        until: &DateTime<Local>,
+    ) -> Result<Vec<(DateTime<Local>, T)>> {
+        self.connection()?
+            // language=sql
+            .prepare_cached(
+                r#"
+                -- noinspection SqlResolve @ routine/"json_extract"
+                SELECT timestamp, json_extract(value, '$.value') as value
+                FROM readings
+                WHERE sensor_fk = ?1 AND timestamp >= ?2 AND timestamp < ?3
+                ORDER BY timestamp
+                "#,
+            )?
+            .query_map(
+                params![
+                    hash_sensor_id(sensor_id),
+                    since.timestamp_millis(),
+                    until.timestamp_millis(),
+                ],
+                |row| -> rusqlite::Result<(DateTime<Local>, T)> {
+                    Ok((Local.timestamp_millis(row.get("timestamp")?), row.get::<_, T>("value")?))
+                },
+            )?
+            .map(|r| r.map_err(Into::into))
+            .collect()
+    }
+
+    /// Selects the specified sensor readings within the specified period, one page at a time.
+    pub fn select_readings(
+        &self,
+        sensor_id: &str,
+        since: &DateTime<Local>,
+        until: &DateTime<Local>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Reading>> {
+        self.connection()?
+            // language=sql
+            .prepare_cached(
+                r#"
+                SELECT timestamp, value FROM readings
+                WHERE sensor_fk = ?1 AND timestamp >= ?2 AND timestamp < ?3
+                ORDER BY timestamp
+                LIMIT ?4 OFFSET ?5
+                "#,
+            )?
+            .query_map(
+                params![
+                    hash_sensor_id(sensor_id),
+                    since.timestamp_millis(),
+                    until.timestamp_millis(),
+                    limit,
+                    offset,
+                ],
+                get_reading,
+            )?
+            .map(|r| r.map_err(Into::into))
+            .collect()
+    }
+
+    /// Selects the state changes of the sensor within the period, starting with the state as of `since`.
+    /// The changes are downsampled to the last one within each of `max_changes` equal slots of the period,
+    /// so that a flapping sensor doesn't produce an arbitrary number of them.
+    pub fn select_state_changes(
+        &self,
+        sensor_id: &str,
+        since: &DateTime<Local>,
+        until: &DateTime<Local>,
+        max_changes: u32,
+    ) -> Result<Vec<Reading>> {
+        self.connection()?
+            // language=sql
+            .prepare_cached(
+                r#"
+                WITH ranged AS (
+                    SELECT
+                        MAX(timestamp, ?2) AS clamped_timestamp,
+                        value,
+                        LAG(value) OVER (ORDER BY timestamp) AS previous_value
+                    FROM readings
+                    WHERE
+                        sensor_fk = ?1
+                        AND timestamp >= COALESCE(
+                            (SELECT MAX(timestamp) FROM readings WHERE sensor_fk = ?1 AND timestamp <= ?2),
+                            ?2
+                        )
+                        AND timestamp < ?3
+                )
+                SELECT MAX(clamped_timestamp) AS timestamp, value
+                FROM ranged
+                WHERE previous_value IS NULL OR previous_value <> value
+                GROUP BY (clamped_timestamp - ?2) * ?4 / (?3 - ?2)
+                ORDER BY timestamp
+                "#,
+            )?
+            .query_map(
+                params![
+                    hash_sensor_id(sensor_id),
+                    since.timestamp_millis(),
+                    until.timestamp_millis(),
+                    max_changes,
+                ],
+                get_reading,
+            )?
+            .map(|r| r.map_err(Into::into))
+            .collect()
+    }
+
+    /// Sums up how long the sensor has had each of its values within the period,
+    /// starting with the value as of `since`.
+    pub fn select_state_durations(
+        &self,
+        sensor_id: &str,
+        since: &DateTime<Local>,
+        until: &DateTime<Local>,
+    ) -> Result<Vec<(Value, chrono::Duration)>> {
+        self.connection()?
+            // language=sql
+            .prepare_cached(
+                r#"
+                WITH spans AS (
+                    SELECT
+                        value,
+                        MAX(timestamp, ?2) AS span_since,
+                        COALESCE(LEAD(timestamp) OVER (ORDER BY timestamp), ?3) AS span_until
+                    FROM readings
+                    WHERE
+                        sensor_fk = ?1
+                        AND timestamp >= COALESCE(
+                            (SELECT MAX(timestamp) FROM readings WHERE sensor_fk = ?1 AND timestamp <= ?2),
+                            ?2
+                        )
+                        AND timestamp < ?3
+                )
+                SELECT value, SUM(span_until - span_since) AS millis
+                FROM spans
+                GROUP BY value
+                "#,
+            )?
+            .query_map(
+                params![
+                    hash_sensor_id(sensor_id),
+                    since.timestamp_millis(),
+                    until.timestamp_millis()
+                ],
+                |row| {
+                    Ok((
+                        serde_json::from_str(&row.get::<_, String>("value")?).unwrap(),
+                        chrono::Duration::milliseconds(row.get("millis")?),
+                    ))
+                },
+            )?
+            .map(|r| r.map_err(Into::into))
+            .collect()
+    }
+
+    /// Counts the sensor readings by value within the period.
+    pub fn select_value_counts(
+        &self,
+        sensor_id: &str,
+        since: &DateTime<Local>,
+        until: &DateTime<Local>,
+    ) -> Result<Vec<(Value, u64)>> {
+        self.connection()?
+            // language=sql
+            .prepare_cached(
+                r#"
+                SELECT value, COUNT(*) AS count FROM readings
+                WHERE sensor_fk = ?1 AND timestamp >= ?2 AND timestamp < ?3
+                GROUP BY value
+                "#,
+            )?
+            .query_map(
+                params![
+                    hash_sensor_id(sensor_id),
+                    since.timestamp_millis(),
+                    until.timestamp_millis()
+                ],
+                |row| {
+                    Ok((
+                        serde_json::from_str(&row.get::<_, String>("value")?).unwrap(),
+                        row.get::<_, i64>("count")? as u64,
+                    ))
+                },
+            )?
+            .map(|r| r.map_err(Into::into))
+            .collect()
+    }
+
+    /// Selects the specified sensor readings, the newest first, one page at a time.
+    /// The period is optionally limited on either side.
+    pub fn select_latest_readings(
+        &self,
+        sensor_id: &str,
+        since: Option<&DateTime<Local>>,
+        until: Option<&DateTime<Local>>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Reading>> {
+        self.connection()?
+            // language=sql
+            .prepare_cached(
+                r#"
+                SELECT timestamp, value FROM readings
+                WHERE sensor_fk = ?1 AND (?2 IS NULL OR timestamp >= ?2) AND (?3 IS NULL OR timestamp < ?3)
+                ORDER BY timestamp DESC
+                LIMIT ?4 OFFSET ?5
+                "#,
+            )?
+            .query_map(
+                params![
+                    hash_sensor_id(sensor_id),
+                    since.map(DateTime::timestamp_millis),
+                    until.map(DateTime::timestamp_millis),
+                    limit,
+                    offset,
+                ],
+                get_reading,
+            )?
+            .map(|r| r.map_err(Into::into))
+            .collect()
+    }
+
+    /// Selects the readings which follow the specified timestamp, the oldest first.
+    /// It's meant to walk through the readings chunk by chunk.
+    pub fn select_readings_after(
+        &self,
+        sensor_id: &str,
+        after: Option<&DateTime<Local>>,
+        until: Option<&DateTime<Local>>,
+        limit: u32,
+    ) -> Result<Vec<Reading>> {
+        self.connection()?
+            // language=sql
+            .prepare_cached(
+                r#"
+                SELECT timestamp, value FROM readings
+                WHERE sensor_fk = ?1 AND (?2 IS NULL OR timestamp > ?2) AND (?3 IS NULL OR timestamp < ?3)
+                ORDER BY timestamp
+                LIMIT ?4
+                "#,
+            )?
+            .query_map(
+                params![
+                    hash_sensor_id(sensor_id),
+                    after.map(DateTime::timestamp_millis),
+                    until.map(DateTime::timestamp_millis),
+                    limit,
+                ],
+                get_reading,
+            )?
+            .map(|r| r.map_err(Into::into))
+            .collect()
+    }
+
+    /// Selects the specified sensor readings within the specified period
+    /// aggregated into buckets of the specified size.
+    pub fn select_buckets(
+        &self,
+        sensor_id: &str,
+        since: &DateTime<Local>,
+        until: &DateTime<Local>,
+        bucket: &chrono::Duration,
+    ) -> Result<Vec<Bucket>> {
+        self.connection()?
+            // language=sql
+            .prepare_cached(
+                r#"
+                -- noinspection SqlResolve @ routine/"json_extract"
+                SELECT
+                    timestamp / ?4 * ?4 AS bucket,
+                    AVG(json_extract(value, '$.value')) AS mean,
+                    MIN(json_extract(value, '$.value')) AS min,
+                    MAX(json_extract(value, '$.value')) AS max,
+                    COUNT(*) AS count
+                FROM readings
+                WHERE sensor_fk = ?1 AND timestamp >= ?2 AND timestamp < ?3
+                GROUP BY bucket
+                ORDER BY bucket
+                "#,
+            )?
+            .query_map(
+                params![
+                    hash_sensor_id(sensor_id),
+                    since.timestamp_millis(),
+                    until.timestamp_millis(),
+                    bucket.num_milliseconds().max(1),
+                ],
+                |row| -> rusqlite::Result<Bucket> {
+                    Ok(Bucket {
+                        timestamp: Local.timestamp_millis(row.get("bucket")?),
+                        mean: row.get("mean")?,
+                        min: row.get("min")?,
+                        max: row.get("max")?,
+                        count: row.get::<_, i64>("count")? as u64,
+                    })
+                },
+            )?
+            .map(|r| r.map_err(Into::into))
+            .collect()
+    }
+
     pub fn select_sensor_count(&self) -> Result<u64> {
         Ok(self
             .connection()?
@@ -200,11 +686,244 @@ impl Connection {
     }
 }
 
+/// Web interface session.
+impl Connection {
+    pub fn insert_session(
+        &self,
+        token_hash: &str,
+        user_name: &str,
+        csrf_token: &str,
+        expires_at: &DateTime<Local>,
+    ) -> Result {
+        self.connection()?
+            // language=sql
+            .prepare_cached(
+                r#"
+                INSERT INTO sessions (token_hash, user_name, csrf_token, expires_at)
+                VALUES (?1, ?2, ?3, ?4)
+                "#,
+            )?
+            .execute(params![
+                token_hash,
+                user_name,
+                csrf_token,
+                expires_at.timestamp_millis()
+            ])?;
+        Ok(())
+    }
+
+    /// Selects the user name and the CSRF token of the active session.
+    pub fn select_session(&self, token_hash: &str) -> Result<Option<(String, String)>> {
+        Ok(self
+            .connection()?
+            // language=sql
+            .prepare_cached(
+                r#"
+                SELECT user_name, csrf_token FROM sessions
+                WHERE token_hash = ?1 AND expires_at >= ?2
+                "#,
+            )?
+            .query_row(params![token_hash, Local::now().timestamp_millis()], |row| {
+                Ok((row.get("user_name")?, row.get("csrf_token")?))
+            })
+            .optional()?)
+    }
+
+    pub fn delete_session(&self, token_hash: &str) -> Result {
+        self.connection()?
+            // language=sql
+            .prepare_cached(r"DELETE FROM sessions WHERE token_hash = ?1 OR expires_at < ?2")?
+            .execute(params![token_hash, Local::now().timestamp_millis()])?;
+        Ok(())
+    }
+}
+
+/// API tokens.
+impl Connection {
+    pub fn insert_api_token(&self, name: &str, token_hash: &str, scopes: &[Scope]) -> Result {
+        self.connection()?
+            // language=sql
+            .prepare_cached(
+                r#"
+                INSERT INTO api_tokens (name, token_hash, scopes, created_at)
+                VALUES (?1, ?2, ?3, ?4)
+                "#,
+            )?
+            .execute(params![
+                name,
+                token_hash,
+                serde_json::to_string(scopes)?,
+                Local::now().timestamp_millis(),
+            ])?;
+        Ok(())
+    }
+
+    pub fn select_api_tokens(&self) -> Result<Vec<ApiToken>> {
+        self.connection()?
+            // language=sql
+            .prepare_cached(r"SELECT * FROM api_tokens ORDER BY name")?
+            .query_map(NO_PARAMS, get_api_token)?
+            .map(|r| r.map_err(Into::into))
+            .collect()
+    }
+
+    /// Selects the token by its hash and marks it as just used.
+    pub fn use_api_token(&self, token_hash: &str) -> Result<Option<ApiToken>> {
+        let connection = self.connection()?;
+        let now = Local::now().timestamp_millis();
+        // language=sql
+        connection
+            .prepare_cached(r"UPDATE api_tokens SET last_used_at = ?2 WHERE token_hash = ?1")?
+            .execute(params![token_hash, now])?;
+        Ok(connection
+            // language=sql
+            .prepare_cached(r"SELECT * FROM api_tokens WHERE token_hash = ?1")?
+            .query_row(params![token_hash], get_api_token)
+            .optional()?)
+    }
+
+    /// Deletes the token and returns whether it existed.
+    pub fn delete_api_token(&self, name: &str) -> Result<bool> {
+        Ok(self
+            .connection()?
+            // language=sql
+            .prepare_cached(r"DELETE FROM api_tokens WHERE name = ?1")?
+            .execute(params![name])?
+            != 0)
+    }
+}
+
+/// Dashboards which are edited in the web interface.
+impl Connection {
+    pub fn upsert_dashboard<V: Serialize>(&self, slug: &str, dashboard: &V) -> Result {
+        self.connection()?
+            // language=sql
+            .prepare_cached(
+                r#"
+                -- noinspection SqlResolve @ any/"excluded"
+                INSERT INTO dashboards (slug, dashboard) VALUES (?1, ?2)
+                ON CONFLICT (slug) DO UPDATE SET dashboard = excluded.dashboard
+                "#,
+            )?
+            .execute(params![slug, serde_json::to_string(dashboard)?])?;
+        Ok(())
+    }
+
+    pub fn select_dashboards<V: DeserializeOwned>(&self) -> Result<Vec<(String, V)>> {
+        self.connection()?
+            // language=sql
+            .prepare_cached(r"SELECT slug, CAST(dashboard AS TEXT) FROM dashboards ORDER BY slug")?
+            .query_map(NO_PARAMS, |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .map(|r| -> Result<(String, V)> {
+                let (slug, dashboard) = r?;
+                Ok((slug, serde_json::from_str(&dashboard)?))
+            })
+            .collect()
+    }
+
+    pub fn delete_dashboard(&self, slug: &str) -> Result {
+        self.connection()?
+            // language=sql
+            .prepare_cached(r"DELETE FROM dashboards WHERE slug = ?1")?
+            .execute(params![slug])?;
+        Ok(())
+    }
+}
+
+/// Sensor search criteria.
+#[derive(Default)]
+pub struct SensorQuery<'a> {
+    /// Case-insensitive substring of the sensor ID, title or location.
+    pub search: Option<&'a str>,
+
+    /// Value type, for example: `Temperature`.
+    pub value_type: Option<&'a str>,
+
+    pub tag: Option<&'a str>,
+    pub order: SensorOrder,
+}
+
+/// Sensor sort order.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SensorOrder {
+    /// By location and then by sensor ID.
+    Location,
+
+    /// Alphabetically by title, or by sensor ID if there's no title.
+    Title,
+
+    /// The most recently updated first.
+    LastUpdated,
+}
+
+impl Default for SensorOrder {
+    fn default() -> Self {
+        SensorOrder::Location
+    }
+}
+
+impl SensorOrder {
+    /// Returns the `ORDER BY` expression.
+    fn to_sql(self) -> &'static str {
+        match self {
+            SensorOrder::Location => "room_title, sensor_id",
+            SensorOrder::Title => "COALESCE(title, sensor_id) COLLATE NOCASE, sensor_id",
+            SensorOrder::LastUpdated => "timestamp DESC, sensor_id",
+        }
+    }
+}
+
+impl std::str::FromStr for SensorOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "location" => Ok(SensorOrder::Location),
+            "title" => Ok(SensorOrder::Title),
+            "updated" => Ok(SensorOrder::LastUpdated),
+            _ => Err(format!("unknown sort order: `{}`", s)),
+        }
+    }
+}
+
+/// Aggregated readings within a time bucket.
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct Bucket {
+    /// Bucket start.
+    pub timestamp: DateTime<Local>,
+
+    /// Average value, `None` for non-numeric sensors.
+    pub mean: Option<f64>,
+
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+
+    /// Number of readings in the bucket.
+    pub count: u64,
+}
+
 /// Hashes the sensor ID, hash is then used for a sensor primary key.
 pub fn hash_sensor_id(sensor_id: &str) -> i64 {
     signed_seahash(sensor_id.as_bytes())
 }
 
+/// Adds the columns which have appeared since the tables were created.
+fn migrate(connection: &rusqlite::Connection) -> Result {
+    for (column, definition) in ADDED_SENSOR_COLUMNS {
+        let exists = connection
+            // language=sql
+            .prepare_cached("SELECT 1 FROM pragma_table_info('sensors') WHERE name = ?1")?
+            .exists(params![column])?;
+        if !exists {
+            // language=sql
+            connection.execute_batch(&format!("ALTER TABLE sensors ADD COLUMN {} {}", column, definition))?;
+        }
+    }
+    Ok(())
+}
+
 /// Returns SeaHash of the buffer as a signed integer, because SQLite wants signed integers.
 fn signed_seahash(buffer: &[u8]) -> i64 {
     seahash::hash(buffer) as i64
@@ -216,6 +935,10 @@ fn get_sensor(row: &Row) -> rusqlite::Result<Sensor> {
         id: row.get("sensor_id")?,
         title: row.get("title")?,
         location: row.get("room_title")?,
+        control: row
+            .get::<_, Option<String>>("control")?
+            .and_then(|control| serde_json::from_str(&control).ok()),
+        tags: serde_json::from_str(&row.get::<_, String>("tags")?).unwrap_or_default(),
     })
 }
 
@@ -231,6 +954,18 @@ fn get_sensor_reading(row: &Row) -> rusqlite::Result<(Sensor, Reading)> {
     Ok((get_sensor(row)?, get_reading(row)?))
 }
 
+/// Builds an `ApiToken` instance based on the database row.
+fn get_api_token(row: &Row) -> rusqlite::Result<ApiToken> {
+    Ok(ApiToken {
+        name: row.get("name")?,
+        scopes: serde_json::from_str(&row.get::<_, String>("scopes")?).expect("deserialization"),
+        created_at: Local.timestamp_millis(row.get("created_at")?),
+        last_used_at: row
+            .get::<_, Option<i64>>("last_used_at")?
+            .map(|last_used_at| Local.timestamp_millis(last_used_at)),
+    })
+}
+
 /// Selects a single `i64` value, used with single-integer `SELECT`s.
 #[inline(always)]
 fn get_i64(row: &Row) -> rusqlite::Result<i64> {
@@ -243,19 +978,26 @@ impl Message {
         let sensor_pk = hash_sensor_id(&self.sensor.id);
         let timestamp = self.reading.timestamp.timestamp_millis();
         let value = serde_json::to_string(&self.reading.value)?;
+        let control = match self.sensor.control {
+            Some(ref control) => Some(serde_json::to_string(control)?),
+            None => None,
+        };
+        let tags = serde_json::to_string(&self.sensor.tags)?;
 
         connection
             .prepare_cached(
                 // language=sql
                 r#"
                     -- noinspection SqlResolve @ any/"excluded"
-                    INSERT INTO sensors (pk, sensor_id, title, timestamp, room_title, value, expires_at)
-                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0)
+                    INSERT INTO sensors (pk, sensor_id, title, timestamp, room_title, value, expires_at, control, tags)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0, ?7, ?8)
                     ON CONFLICT (pk) DO UPDATE SET
                         timestamp = excluded.timestamp,
                         title = excluded.title,
                         room_title = excluded.room_title,
-                        value = excluded.value
+                        value = excluded.value,
+                        control = excluded.control,
+                        tags = excluded.tags
                 "#,
             )?
             .execute(params![
@@ -265,6 +1007,8 @@ impl Message {
                 timestamp,
                 self.sensor.location,
                 value,
+                control,
+                tags,
             ])?;
 
         connection
@@ -292,6 +1036,7 @@ impl From<Message> for (Sensor, Reading) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::control::Control;
     use chrono::Duration;
 
     #[test]
@@ -301,6 +1046,88 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn select_state_changes_starts_with_earlier_state() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        for (millis, value) in &[(500, true), (1500, true), (2000, false), (2500, false), (5000, true)] {
+            Message::new("test::door")
+                .value(Value::Boolean(*value))
+                .timestamp(Local.timestamp_millis(*millis))
+                .upsert_into(&*db.connection()?)?;
+        }
+        let changes = db.select_state_changes(
+            "test::door",
+            &Local.timestamp_millis(1000),
+            &Local.timestamp_millis(3000),
+            100,
+        )?;
+        assert_eq!(
+            changes,
+            vec![
+                Reading {
+                    timestamp: Local.timestamp_millis(1000),
+                    value: Value::Boolean(true),
+                },
+                Reading {
+                    timestamp: Local.timestamp_millis(2000),
+                    value: Value::Boolean(false),
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn select_state_durations_ok() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        for (millis, value) in &[(500, true), (2000, false), (2500, true)] {
+            Message::new("test::door")
+                .value(Value::Boolean(*value))
+                .timestamp(Local.timestamp_millis(*millis))
+                .upsert_into(&*db.connection()?)?;
+        }
+        let mut durations = db.select_state_durations(
+            "test::door",
+            &Local.timestamp_millis(1000),
+            &Local.timestamp_millis(3000),
+        )?;
+        durations.sort_by_key(|(_, duration)| *duration);
+        assert_eq!(
+            durations,
+            vec![
+                (Value::Boolean(false), chrono::Duration::milliseconds(500)),
+                (Value::Boolean(true), chrono::Duration::milliseconds(1500)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn select_values_at_ok() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        for (millis, joules) in &[(1000, 3600.0), (2000, 5400.0)] {
+            Message::new("youless::consumption::low")
+                .value(Value::Energy(*joules))
+                .timestamp(Local.timestamp_millis(*millis))
+                .upsert_into(&*db.connection()?)?;
+        }
+        let values = db.select_values_at(
+            "youless::consumption::low",
+            &[
+                Local.timestamp_millis(0),
+                Local.timestamp_millis(1500),
+                Local.timestamp_millis(2000),
+            ],
+        )?;
+        assert_eq!(values, vec![None, Some(3600.0), Some(5400.0)]);
+        Ok(())
+    }
+
+    #[test]
+    fn check_writable_ok() -> Result {
+        Connection::open_and_initialize(":memory:")?.check_writable()
+    }
+
     #[test]
     fn double_upsert_keeps_one_reading() -> Result {
         let message = Message::new("test")
@@ -338,6 +1165,35 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn select_sensor_control_ok() -> Result {
+        let message = Message::new("test")
+            .value(Value::Temperature(21.0))
+            .control(Control::Slider {
+                min: 5.0,
+                max: 25.0,
+                step: 0.5,
+            });
+        let db = Connection::open_and_initialize(":memory:")?;
+        message.upsert_into(&*db.connection()?)?;
+        assert_eq!(db.select_sensor("test")?.unwrap().0.control, message.sensor.control);
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_adds_columns() -> Result {
+        let connection = rusqlite::Connection::open_in_memory()?;
+        // language=sql
+        connection.execute_batch(
+            "CREATE TABLE sensors (pk INTEGER NOT NULL PRIMARY KEY, sensor_id TEXT NOT NULL UNIQUE, value JSON NOT NULL)",
+        )?;
+        migrate(&connection)?;
+        migrate(&connection)?;
+        // language=sql
+        connection.execute_batch("SELECT control, tags FROM sensors")?;
+        Ok(())
+    }
+
     #[test]
     fn select_last_reading_returns_newer_reading() -> Result {
         let db = Connection::open_and_initialize(":memory:")?;
@@ -391,6 +1247,214 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn select_actuals_by_pattern_ok() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        let message = Message::new("test::1").value(Value::Counter(42));
+        message.upsert_into(&*db.connection()?)?;
+        Message::new("other::1")
+            .value(Value::Counter(43))
+            .upsert_into(&*db.connection()?)?;
+        assert_eq!(
+            db.select_actuals_by(None, Some("test::*"), None)?,
+            vec![(message.sensor, message.reading)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn search_actuals_ok() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        Message::new("test::temperature")
+            .value(Value::Temperature(21.0))
+            .sensor_title("Bedroom Temperature")
+            .timestamp(Local.timestamp_millis(1))
+            .upsert_into(&*db.connection()?)?;
+        Message::new("test::humidity")
+            .value(Value::Rh(40.0))
+            .location("Bedroom")
+            .timestamp(Local.timestamp_millis(2))
+            .upsert_into(&*db.connection()?)?;
+        Message::new("other::counter")
+            .value(Value::Counter(1))
+            .timestamp(Local.timestamp_millis(3))
+            .upsert_into(&*db.connection()?)?;
+
+        let search = |query: SensorQuery| -> Result<Vec<String>> {
+            Ok(db
+                .search_actuals(&query)?
+                .into_iter()
+                .map(|(sensor, _)| sensor.id)
+                .collect())
+        };
+        assert_eq!(
+            search(SensorQuery {
+                search: Some("BEDROOM"),
+                order: SensorOrder::LastUpdated,
+                ..Default::default()
+            })?,
+            vec!["test::humidity", "test::temperature"]
+        );
+        assert_eq!(
+            search(SensorQuery {
+                value_type: Some("Counter"),
+                ..Default::default()
+            })?,
+            vec!["other::counter"]
+        );
+        assert_eq!(db.select_value_types()?, vec!["Counter", "Rh", "Temperature"]);
+        Ok(())
+    }
+
+    #[test]
+    fn select_actuals_by_tag_ok() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        let message = Message::new("youless::power").tag("energy").tag("meter");
+        message.upsert_into(&*db.connection()?)?;
+        Message::new("tado::1::humidity")
+            .tag("heating")
+            .upsert_into(&*db.connection()?)?;
+        assert_eq!(
+            db.select_actuals_by(None, None, Some("meter"))?,
+            vec![(message.sensor, message.reading)]
+        );
+        assert_eq!(db.select_tags()?, vec!["energy", "heating", "meter"]);
+        Ok(())
+    }
+
+    #[test]
+    fn select_readings_paginated_ok() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        for timestamp in 1..=3 {
+            Message::new("test")
+                .value(Value::Counter(timestamp))
+                .timestamp(Local.timestamp_millis(timestamp as i64))
+                .upsert_into(&*db.connection()?)?;
+        }
+        let readings = db.select_readings("test", &Local.timestamp_millis(0), &Local.timestamp_millis(10), 1, 1)?;
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].value, Value::Counter(2));
+        Ok(())
+    }
+
+    #[test]
+    fn select_latest_readings_ok() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        for i in 1..=3 {
+            Message::new("test")
+                .value(Value::Counter(i))
+                .timestamp(Local.timestamp_millis(i as i64))
+                .upsert_into(&*db.connection()?)?;
+        }
+        let readings = db.select_latest_readings("test", None, None, 2, 1)?;
+        assert_eq!(readings.len(), 2);
+        assert_eq!(readings[0].value, Value::Counter(2));
+        assert_eq!(readings[1].value, Value::Counter(1));
+        let readings = db.select_latest_readings("test", Some(&Local.timestamp_millis(2)), None, 10, 0)?;
+        assert_eq!(readings.len(), 2);
+        assert_eq!(readings[1].value, Value::Counter(2));
+        Ok(())
+    }
+
+    #[test]
+    fn delete_reading_ok() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        for i in 1..=2 {
+            Message::new("test")
+                .value(Value::Counter(i))
+                .timestamp(Local.timestamp_millis(i as i64))
+                .upsert_into(&*db.connection()?)?;
+        }
+        assert!(db.delete_reading("test", &Local.timestamp_millis(2))?);
+        assert!(!db.delete_reading("test", &Local.timestamp_millis(2))?);
+        let (_, reading) = db.select_sensor("test")?.unwrap();
+        assert_eq!(reading.value, Value::Counter(1));
+        assert_eq!(db.select_sensor_reading_count("test")?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn delete_last_reading_resets_value() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        Message::new("test")
+            .value(Value::Counter(1))
+            .timestamp(Local.timestamp_millis(1))
+            .upsert_into(&*db.connection()?)?;
+        assert!(db.delete_reading("test", &Local.timestamp_millis(1))?);
+        let (_, reading) = db.select_sensor("test")?.unwrap();
+        assert_eq!(reading.value, Value::None);
+        assert_eq!(db.select_sensor_reading_count("test")?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn select_buckets_ok() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        for (timestamp, celsius) in &[(0, 1.0), (500, 3.0), (1000, 5.0)] {
+            Message::new("test")
+                .value(Value::Temperature(*celsius))
+                .timestamp(Local.timestamp_millis(*timestamp))
+                .upsert_into(&*db.connection()?)?;
+        }
+        let buckets = db.select_buckets(
+            "test",
+            &Local.timestamp_millis(0),
+            &Local.timestamp_millis(2000),
+            &Duration::seconds(1),
+        )?;
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].mean, Some(2.0));
+        assert_eq!(buckets[0].count, 2);
+        assert_eq!(buckets[1].max, Some(5.0));
+        Ok(())
+    }
+
+    #[test]
+    fn select_session_ok() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        db.insert_session("hash", "user", "csrf", &(Local::now() + Duration::minutes(1)))?;
+        assert_eq!(db.select_session("hash")?, Some(("user".into(), "csrf".into())));
+        Ok(())
+    }
+
+    #[test]
+    fn select_expired_session_returns_none() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        db.insert_session("hash", "user", "csrf", &(Local::now() - Duration::minutes(1)))?;
+        assert_eq!(db.select_session("hash")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn use_api_token_ok() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        let scopes = vec![Scope::Ingest { prefix: "esp::".into() }];
+        db.insert_api_token("esp", "hash", &scopes)?;
+        let token = db.use_api_token("hash")?.unwrap();
+        assert_eq!(token.scopes, scopes);
+        assert!(token.last_used_at.is_some());
+        assert_eq!(db.use_api_token("wrong")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn delete_api_token_ok() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        db.insert_api_token("esp", "hash", &[])?;
+        assert!(db.delete_api_token("esp")?);
+        assert!(db.select_api_tokens()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn upsert_dashboard_ok() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        db.upsert_dashboard("climate", &1)?;
+        db.upsert_dashboard("climate", &2)?;
+        assert_eq!(db.select_dashboards::<i32>()?, vec![("climate".to_string(), 2)]);
+        Ok(())
+    }
+
     #[test]
     fn get_set_user_data_ok() -> Result {
         let db = Connection::open_and_initialize(":memory:")?;
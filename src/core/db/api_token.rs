@@ -0,0 +1,127 @@
+use std::str::FromStr;
+
+use glob::Pattern;
+
+use crate::prelude::*;
+
+/// Token which lets a script or a device call the web API.
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct ApiToken {
+    /// Human-readable unique token name, for example: `esp-kitchen`.
+    pub name: String,
+
+    pub scopes: Vec<Scope>,
+
+    pub created_at: DateTime<Local>,
+
+    /// When the token was last accepted by the web server.
+    pub last_used_at: Option<DateTime<Local>>,
+}
+
+/// Defines what an API token may do.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Scope {
+    /// Allows reading the sensors with IDs matching the `GLOB` pattern.
+    Read { pattern: String },
+
+    /// Allows pushing readings of the sensors with IDs starting with the prefix.
+    Ingest { prefix: String },
+
+    /// Allows sending `Write` messages to the sensors with IDs matching the `GLOB` pattern.
+    Write { pattern: String },
+}
+
+impl ApiToken {
+    /// Checks whether the token allows reading the sensor.
+    pub fn can_read(&self, sensor_id: &str) -> bool {
+        self.scopes.iter().any(|scope| match scope {
+            Scope::Read { pattern } => is_match(pattern, sensor_id),
+            _ => false,
+        })
+    }
+
+    /// Checks whether the token allows sending the message.
+    pub fn allows(&self, message: &Message) -> bool {
+        self.scopes.iter().any(|scope| match (scope, &message.type_) {
+            (Scope::Ingest { prefix }, MessageType::ReadLogged)
+            | (Scope::Ingest { prefix }, MessageType::ReadNonLogged) => message.sensor.id.starts_with(prefix),
+            (Scope::Write { pattern }, MessageType::Write) => is_match(pattern, &message.sensor.id),
+            _ => false,
+        })
+    }
+}
+
+/// Formats the scope the same way it's parsed, for example: `read:buienradar::*`.
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scope::Read { pattern } => write!(f, "read:{}", pattern),
+            Scope::Ingest { prefix } => write!(f, "ingest:{}", prefix),
+            Scope::Write { pattern } => write!(f, "write:{}", pattern),
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        let (kind, argument) = match (parts.next(), parts.next()) {
+            (Some(kind), Some(argument)) => (kind, argument.to_string()),
+            _ => return Err(format!("`{}` is not in the `kind:argument` format", s)),
+        };
+        match kind {
+            "read" => Ok(Scope::Read { pattern: argument }),
+            "ingest" => Ok(Scope::Ingest { prefix: argument }),
+            "write" => Ok(Scope::Write { pattern: argument }),
+            _ => Err(format!("unknown scope kind: `{}`", kind)),
+        }
+    }
+}
+
+fn is_match(pattern: &str, sensor_id: &str) -> bool {
+    Pattern::new(pattern).map_or(false, |pattern| pattern.matches(sensor_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_scope_ok() -> Result {
+        assert_eq!(
+            "read:buienradar::*".parse::<Scope>()?,
+            Scope::Read {
+                pattern: "buienradar::*".into()
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn can_read_ok() {
+        let token = token(vec![Scope::Read {
+            pattern: "buienradar::*".into(),
+        }]);
+        assert!(token.can_read("buienradar::6240::temperature"));
+        assert!(!token.can_read("tado::home::temperature"));
+    }
+
+    #[test]
+    fn ingest_does_not_allow_write() {
+        let token = token(vec![Scope::Ingest { prefix: "esp::".into() }]);
+        assert!(token.allows(&Message::new("esp::temperature")));
+        assert!(!token.allows(&Message::new("esp::relay").type_(MessageType::Write)));
+    }
+
+    fn token(scopes: Vec<Scope>) -> ApiToken {
+        ApiToken {
+            name: "test".into(),
+            scopes,
+            created_at: Local::now(),
+            last_used_at: None,
+        }
+    }
+}
@@ -1,4 +1,7 @@
-#[derive(PartialEq, Debug, Clone)]
+use crate::core::control::Control;
+use serde::Serialize;
+
+#[derive(PartialEq, Debug, Clone, Serialize)]
 pub struct Sensor {
     /// Sensor ID, for example: `buienradar::6240::feel_temperature`.
     pub id: String,
@@ -8,4 +11,16 @@ pub struct Sensor {
 
     /// Human-readable location title.
     pub location: String,
+
+    /// Input which lets a user send `Write` messages to the sensor, if it's controllable.
+    pub control: Option<Control>,
+
+    /// Arbitrary tags, for example: `energy`, `heating`, `outdoor` or `battery`.
+    pub tags: Vec<String>,
+}
+
+impl Sensor {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|sensor_tag| sensor_tag == tag)
+    }
 }
@@ -1,3 +1,4 @@
+use crate::core::metrics::METRICS;
 use crate::prelude::*;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
@@ -8,7 +9,7 @@ const COMMIT_INTERVAL_MILLIS: u64 = 1000;
 /// Spawn the persistence thread.
 pub fn spawn(db: Connection, bus: &mut Bus) -> Result {
     info!("Spawning readings persistence…");
-    let rx = bus.add_rx();
+    let rx = bus.add_rx("system::persistence");
     let buffer = Arc::new(Mutex::new(Vec::<Message>::new()));
 
     {
@@ -41,10 +42,14 @@ pub fn spawn(db: Connection, bus: &mut Bus) -> Result {
 
             // Now `messages` is a clone, thus we can perform a slow operation.
             let start_time = Instant::now();
-            if let Err(error) = upsert_messages(&db, messages) {
-                error!("could not upsert the messages: {}", error);
+            let batch_size = messages.len();
+            match upsert_messages(&db, messages) {
+                Ok(()) => METRICS.record_commit(),
+                Err(error) => error!("could not upsert the messages: {}", error),
             }
-            info!("Took {:.1?}.", start_time.elapsed());
+            let duration = start_time.elapsed();
+            METRICS.record_persistence(batch_size, duration);
+            info!("Took {:.1?}.", duration);
         })?;
 
     Ok(())
@@ -0,0 +1,202 @@
+//! Keeps the recent log records in memory, so that they can be viewed in the web interface.
+
+use crate::prelude::*;
+use lazy_static::lazy_static;
+use log::{LevelFilter, Log, Metadata, Record};
+use simplelog::{Config, SharedLogger};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Log targets which are too chatty to be logged.
+pub const IGNORED_TARGETS: &[&str] = &["launch_", "rustls", "reqwest", "h2", "hyper"];
+
+lazy_static! {
+    /// Process-wide log buffer.
+    pub static ref LOG_BUFFER: LogBuffer = LogBuffer::default();
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LogRecord {
+    /// Sequential record ID, used to fetch only the new records.
+    pub id: u64,
+
+    pub timestamp: DateTime<Local>,
+
+    #[serde(serialize_with = "serialize_level")]
+    pub level: LogLevel,
+
+    /// Thread name, which is the service ID for the service threads.
+    pub thread: Option<String>,
+
+    pub target: String,
+    pub message: String,
+}
+
+/// Ring buffer of the recent log records.
+#[derive(Default)]
+pub struct LogBuffer {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    capacity: usize,
+    last_id: u64,
+    records: VecDeque<LogRecord>,
+}
+
+impl LogBuffer {
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.capacity = capacity;
+        while inner.records.len() > capacity {
+            inner.records.pop_front();
+        }
+    }
+
+    /// Appends the record and drops the oldest one, if the buffer is full.
+    pub fn push(&self, level: LogLevel, thread: Option<String>, target: String, message: String) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.capacity == 0 {
+            return;
+        }
+        if inner.records.len() == inner.capacity {
+            inner.records.pop_front();
+        }
+        inner.last_id += 1;
+        let id = inner.last_id;
+        inner.records.push_back(LogRecord {
+            id,
+            timestamp: Local::now(),
+            level,
+            thread,
+            target,
+            message,
+        });
+    }
+
+    /// Returns the records newer than `after_id`, which match the filter, oldest first.
+    pub fn select(&self, thread: Option<&str>, level: LogLevel, after_id: u64) -> Vec<LogRecord> {
+        self.inner
+            .lock()
+            .unwrap()
+            .records
+            .iter()
+            .filter(|record| {
+                record.id > after_id
+                    && record.level <= level
+                    && thread.map_or(true, |thread| record.thread.as_deref() == Some(thread))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the distinct thread names, sorted.
+    pub fn threads(&self) -> Vec<String> {
+        let mut threads: Vec<String> = self
+            .inner
+            .lock()
+            .unwrap()
+            .records
+            .iter()
+            .filter_map(|record| record.thread.clone())
+            .collect();
+        threads.sort();
+        threads.dedup();
+        threads
+    }
+
+    /// Returns the number of the buffered error records of the thread.
+    pub fn error_count(&self, thread: &str) -> usize {
+        self.inner
+            .lock()
+            .unwrap()
+            .records
+            .iter()
+            .filter(|record| record.level == LogLevel::Error && record.thread.as_deref() == Some(thread))
+            .count()
+    }
+}
+
+/// Logger which writes the records into `LOG_BUFFER`.
+pub struct BufferLogger {
+    level: LevelFilter,
+}
+
+impl BufferLogger {
+    pub fn new(level: LevelFilter, capacity: usize) -> Box<Self> {
+        LOG_BUFFER.set_capacity(capacity);
+        Box::new(BufferLogger { level })
+    }
+}
+
+impl Log for BufferLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+            && !IGNORED_TARGETS
+                .iter()
+                .any(|target| metadata.target().starts_with(target))
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            LOG_BUFFER.push(
+                record.level(),
+                thread::current().name().map(ToString::to_string),
+                record.target().into(),
+                record.args().to_string(),
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl SharedLogger for BufferLogger {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+fn serialize_level<S: serde::Serializer>(level: &LogLevel, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_drops_oldest() {
+        let buffer = LogBuffer::default();
+        buffer.set_capacity(2);
+        for message in &["a", "b", "c"] {
+            buffer.push(LogLevel::Info, None, "test".into(), message.to_string());
+        }
+        let records = buffer.select(None, LogLevel::Trace, 0);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].message, "b");
+        assert_eq!(records[1].id, 3);
+    }
+
+    #[test]
+    fn select_filters_ok() {
+        let buffer = LogBuffer::default();
+        buffer.set_capacity(10);
+        buffer.push(LogLevel::Error, Some("clock".into()), "test".into(), "failed".into());
+        buffer.push(LogLevel::Info, Some("clock".into()), "test".into(), "ticked".into());
+        buffer.push(LogLevel::Error, Some("tado".into()), "test".into(), "failed".into());
+        assert_eq!(buffer.select(Some("clock"), LogLevel::Warn, 0).len(), 1);
+        assert_eq!(buffer.select(None, LogLevel::Info, 1).len(), 2);
+        assert_eq!(buffer.threads(), vec!["clock".to_string(), "tado".to_string()]);
+        assert_eq!(buffer.error_count("tado"), 1);
+    }
+}
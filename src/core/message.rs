@@ -1,5 +1,6 @@
 //! Describes a sensor reading and related structures.
 
+use crate::core::control::Control;
 use crate::prelude::*;
 
 const DEFAULT_LOCATION: &str = "Home";
@@ -19,7 +20,7 @@ pub struct Message {
 }
 
 /// Message type.
-#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize, Serialize)]
 pub enum Type {
     /// Normal persistently stored sensor reading. The most frequently used message type.
     ReadLogged,
@@ -40,6 +41,8 @@ impl Message {
                 id: sensor_id.into(),
                 title: None,
                 location: DEFAULT_LOCATION.into(),
+                control: None,
+                tags: Vec::new(),
             },
             reading: Reading {
                 timestamp: Local::now(),
@@ -73,6 +76,21 @@ impl Message {
         self
     }
 
+    /// Declares the sensor controllable from the web interface.
+    pub fn control(mut self, control: Control) -> Self {
+        self.sensor.control = Some(control);
+        self
+    }
+
+    /// Adds the tag to the sensor, unless it's already there.
+    pub fn tag<S: Into<String>>(mut self, tag: S) -> Self {
+        let tag = tag.into();
+        if !self.sensor.has_tag(&tag) {
+            self.sensor.tags.push(tag);
+        }
+        self
+    }
+
     pub fn timestamp<T: Into<DateTime<Local>>>(mut self, timestamp: T) -> Self {
         self.reading.timestamp = timestamp.into();
         self
@@ -0,0 +1,241 @@
+//! Internal metrics, they're exposed to [Prometheus](https://prometheus.io/) by the web server.
+
+use crate::prelude::*;
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    /// Process-wide metrics.
+    pub static ref METRICS: Metrics = Metrics::default();
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    /// Message queues by their names: the bus inbox and the subscriber inboxes.
+    queues: Mutex<Vec<(String, Sender)>>,
+
+    persistence_batch_count: AtomicU64,
+    persisted_message_count: AtomicU64,
+    persistence_micros: AtomicU64,
+    last_persistence_batch_size: AtomicU64,
+
+    /// Time of the last successful commit of the persistence executor.
+    last_commit_at: Mutex<Option<Instant>>,
+
+    /// Time of the last message dispatched by the bus.
+    last_dispatch_at: Mutex<Option<Instant>>,
+
+    /// Tells whether the bus dispatcher thread is running.
+    is_dispatcher_alive: AtomicBool,
+
+    /// Iteration statistics by service ID.
+    services: Mutex<HashMap<String, ServiceStats>>,
+}
+
+/// Iteration statistics of a service.
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct ServiceStats {
+    pub iteration_count: u64,
+    pub failure_count: u64,
+
+    /// Finish time of the last successful iteration.
+    pub last_success_at: Option<DateTime<Local>>,
+
+    pub last_error: Option<ServiceError>,
+
+    /// Total time spent on the iterations.
+    #[serde(skip)]
+    pub total_duration: Duration,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ServiceError {
+    pub message: String,
+    pub timestamp: DateTime<Local>,
+}
+
+impl ServiceStats {
+    pub fn average_duration(&self) -> Option<Duration> {
+        if self.iteration_count == 0 {
+            None
+        } else {
+            Some(Duration::from_nanos(
+                (self.total_duration.as_nanos() / u128::from(self.iteration_count)) as u64,
+            ))
+        }
+    }
+}
+
+impl Metrics {
+    /// Registers the queue to report its depth. It replaces a queue of a restarted service.
+    pub fn register_queue(&self, name: &str, tx: Sender) {
+        let mut queues = self.queues.lock().unwrap();
+        queues.retain(|(queue_name, _)| queue_name != name);
+        queues.push((name.into(), tx));
+    }
+
+    /// Returns the numbers of pending messages by queue name.
+    pub fn queue_depths(&self) -> Vec<(String, usize)> {
+        self.queues
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, tx)| (name.clone(), tx.len()))
+            .collect()
+    }
+
+    /// Records a persisted batch of messages.
+    pub fn record_persistence(&self, batch_size: usize, duration: Duration) {
+        self.persistence_batch_count.fetch_add(1, Ordering::Relaxed);
+        self.persisted_message_count
+            .fetch_add(batch_size as u64, Ordering::Relaxed);
+        self.persistence_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.last_persistence_batch_size
+            .store(batch_size as u64, Ordering::Relaxed);
+    }
+
+    pub fn persistence_batch_count(&self) -> u64 {
+        self.persistence_batch_count.load(Ordering::Relaxed)
+    }
+
+    pub fn persisted_message_count(&self) -> u64 {
+        self.persisted_message_count.load(Ordering::Relaxed)
+    }
+
+    /// Total time spent on persisting the messages.
+    pub fn persistence_duration(&self) -> Duration {
+        Duration::from_micros(self.persistence_micros.load(Ordering::Relaxed))
+    }
+
+    pub fn last_persistence_batch_size(&self) -> u64 {
+        self.last_persistence_batch_size.load(Ordering::Relaxed)
+    }
+
+    pub fn record_commit(&self) {
+        *self.last_commit_at.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Time elapsed since the last successful commit, if any.
+    pub fn since_last_commit(&self) -> Option<Duration> {
+        self.last_commit_at.lock().unwrap().map(|instant| instant.elapsed())
+    }
+
+    pub fn record_dispatch(&self) {
+        *self.last_dispatch_at.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Time elapsed since the last dispatched message, if any.
+    pub fn since_last_dispatch(&self) -> Option<Duration> {
+        self.last_dispatch_at.lock().unwrap().map(|instant| instant.elapsed())
+    }
+
+    pub fn set_dispatcher_alive(&self, is_alive: bool) {
+        self.is_dispatcher_alive.store(is_alive, Ordering::Relaxed);
+    }
+
+    pub fn is_dispatcher_alive(&self) -> bool {
+        self.is_dispatcher_alive.load(Ordering::Relaxed)
+    }
+
+    /// Makes the service appear in the metrics before it has failed.
+    pub fn register_service(&self, service_id: &str) {
+        self.services.lock().unwrap().entry(service_id.into()).or_default();
+    }
+
+    /// Records a finished service iteration along with its error, if any.
+    pub fn record_service_iteration(&self, service_id: &str, duration: Duration, error: Option<String>) {
+        let mut services = self.services.lock().unwrap();
+        let stats = services.entry(service_id.into()).or_default();
+        stats.iteration_count += 1;
+        stats.total_duration += duration;
+        match error {
+            Some(message) => {
+                stats.failure_count += 1;
+                stats.last_error = Some(ServiceError {
+                    message,
+                    timestamp: Local::now(),
+                });
+            }
+            None => stats.last_success_at = Some(Local::now()),
+        }
+    }
+
+    /// Records an error which has happened outside of an iteration, for example, while spawning the service.
+    pub fn record_service_error(&self, service_id: &str, message: String) {
+        self.services
+            .lock()
+            .unwrap()
+            .entry(service_id.into())
+            .or_default()
+            .last_error = Some(ServiceError {
+            message,
+            timestamp: Local::now(),
+        });
+    }
+
+    pub fn service_stats(&self, service_id: &str) -> ServiceStats {
+        self.services
+            .lock()
+            .unwrap()
+            .get(service_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns the failed iteration counts sorted by service ID.
+    pub fn service_failures(&self) -> Vec<(String, u64)> {
+        let mut failures: Vec<(String, u64)> = self
+            .services
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(service_id, stats)| (service_id.clone(), stats.failure_count))
+            .collect();
+        failures.sort();
+        failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_persistence_ok() {
+        let metrics = Metrics::default();
+        metrics.record_persistence(3, Duration::from_millis(5));
+        metrics.record_persistence(2, Duration::from_millis(1));
+        assert_eq!(metrics.persistence_batch_count(), 2);
+        assert_eq!(metrics.persisted_message_count(), 5);
+        assert_eq!(metrics.last_persistence_batch_size(), 2);
+        assert_eq!(metrics.persistence_duration(), Duration::from_millis(6));
+    }
+
+    #[test]
+    fn service_failures_ok() {
+        let metrics = Metrics::default();
+        metrics.register_service("b");
+        metrics.record_service_iteration("a", Duration::from_millis(1), Some("error".into()));
+        metrics.record_service_iteration("a", Duration::from_millis(1), Some("error".into()));
+        assert_eq!(
+            metrics.service_failures(),
+            vec![("a".to_string(), 2), ("b".to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn service_stats_ok() {
+        let metrics = Metrics::default();
+        metrics.record_service_iteration("a", Duration::from_millis(2), None);
+        metrics.record_service_iteration("a", Duration::from_millis(4), Some("timeout".into()));
+        let stats = metrics.service_stats("a");
+        assert_eq!(stats.iteration_count, 2);
+        assert_eq!(stats.failure_count, 1);
+        assert!(stats.last_success_at.is_some());
+        assert_eq!(stats.last_error.unwrap().message, "timeout");
+        assert_eq!(stats.average_duration(), Some(Duration::from_millis(3)));
+    }
+}
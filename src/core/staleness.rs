@@ -0,0 +1,117 @@
+//! Detects the sensors which have missed their expected updates.
+
+use crate::prelude::*;
+use crate::settings::ExpectedUpdate;
+use chrono::Duration;
+use glob::Pattern;
+use lazy_static::lazy_static;
+use std::sync::RwLock;
+
+/// Suffix of the sensors which report whether the other sensors are stale.
+pub const IS_STALE_SUFFIX: &str = "::is_stale";
+
+lazy_static! {
+    /// Process-wide expected update intervals, taken from the settings.
+    static ref EXPECTED_UPDATES: RwLock<Vec<(Pattern, Duration)>> = RwLock::new(Vec::new());
+}
+
+/// Replaces the expected update intervals, for instance, when the settings get applied.
+pub fn set_expected_updates(expected_updates: &[ExpectedUpdate]) -> Result {
+    *EXPECTED_UPDATES.write().unwrap() = compile(expected_updates)?;
+    Ok(())
+}
+
+/// Checks whether the sensor has missed its expected update interval.
+pub fn is_stale(sensor_id: &str, reading: &Reading) -> bool {
+    is_stale_at(&EXPECTED_UPDATES.read().unwrap(), sensor_id, reading, Local::now())
+}
+
+fn compile(expected_updates: &[ExpectedUpdate]) -> Result<Vec<(Pattern, Duration)>> {
+    expected_updates
+        .iter()
+        .map(|expected_update| {
+            Ok((
+                Pattern::new(&expected_update.pattern)?,
+                Duration::minutes(expected_update.interval_minutes),
+            ))
+        })
+        .collect()
+}
+
+fn is_stale_at(
+    expected_updates: &[(Pattern, Duration)],
+    sensor_id: &str,
+    reading: &Reading,
+    now: DateTime<Local>,
+) -> bool {
+    // The staleness sensors themselves only get updated when the staleness changes.
+    if sensor_id.ends_with(IS_STALE_SUFFIX) {
+        return false;
+    }
+    expected_updates
+        .iter()
+        .find(|(pattern, _)| pattern.matches(sensor_id))
+        .map_or(false, |(_, interval)| now - reading.timestamp > *interval)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expected_updates() -> Result<Vec<(Pattern, Duration)>> {
+        compile(&[
+            ExpectedUpdate {
+                pattern: "buienradar::*::temperature".into(),
+                interval_minutes: 60,
+            },
+            ExpectedUpdate {
+                pattern: "buienradar::*".into(),
+                interval_minutes: 15,
+            },
+        ])
+    }
+
+    fn reading(minutes_ago: i64) -> Reading {
+        Reading {
+            timestamp: Local.timestamp_millis(1_566_424_128_000) - Duration::minutes(minutes_ago),
+            value: Value::Counter(1),
+        }
+    }
+
+    #[test]
+    fn first_matching_interval_applies() -> Result {
+        let now = Local.timestamp_millis(1_566_424_128_000);
+        let expected_updates = expected_updates()?;
+        assert!(!is_stale_at(
+            &expected_updates,
+            "buienradar::6240::temperature",
+            &reading(30),
+            now
+        ));
+        assert!(is_stale_at(
+            &expected_updates,
+            "buienradar::6240::rain",
+            &reading(30),
+            now
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn unmatched_sensor_is_never_stale() -> Result {
+        let now = Local.timestamp_millis(1_566_424_128_000);
+        assert!(!is_stale_at(
+            &expected_updates()?,
+            "clock::counter",
+            &reading(1000),
+            now
+        ));
+        assert!(!is_stale_at(
+            &expected_updates()?,
+            "buienradar::6240::rain::is_stale",
+            &reading(1000),
+            now
+        ));
+        Ok(())
+    }
+}
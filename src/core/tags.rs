@@ -0,0 +1,68 @@
+//! User-defined sensor tags, which override the tags set by the services.
+
+use crate::prelude::*;
+use crate::settings::SensorTags;
+use glob::Pattern;
+use lazy_static::lazy_static;
+use std::sync::RwLock;
+
+lazy_static! {
+    /// Process-wide tag overrides, taken from the settings.
+    static ref TAG_OVERRIDES: RwLock<Vec<(Pattern, Vec<String>)>> = RwLock::new(Vec::new());
+}
+
+/// Replaces the tag overrides, for instance, when the settings get applied.
+pub fn set_tag_overrides(sensor_tags: &[SensorTags]) -> Result {
+    *TAG_OVERRIDES.write().unwrap() = compile(sensor_tags)?;
+    Ok(())
+}
+
+/// Replaces the sensor tags with the ones of the first matching override, if any.
+pub fn apply_overrides(sensor: &mut Sensor) {
+    apply(&TAG_OVERRIDES.read().unwrap(), sensor);
+}
+
+fn compile(sensor_tags: &[SensorTags]) -> Result<Vec<(Pattern, Vec<String>)>> {
+    sensor_tags
+        .iter()
+        .map(|sensor_tags| Ok((Pattern::new(&sensor_tags.pattern)?, sensor_tags.tags.clone())))
+        .collect()
+}
+
+fn apply(overrides: &[(Pattern, Vec<String>)], sensor: &mut Sensor) {
+    if let Some((_, tags)) = overrides.iter().find(|(pattern, _)| pattern.matches(&sensor.id)) {
+        sensor.tags = tags.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_matching_override_applies() -> Result {
+        let overrides = compile(&[
+            SensorTags {
+                pattern: "youless::gas".into(),
+                tags: vec!["heating".into()],
+            },
+            SensorTags {
+                pattern: "youless::*".into(),
+                tags: vec!["energy".into(), "meter".into()],
+            },
+        ])?;
+
+        let mut message = Message::new("youless::gas").tag("energy");
+        apply(&overrides, &mut message.sensor);
+        assert_eq!(message.sensor.tags, vec!["heating".to_string()]);
+
+        let mut message = Message::new("youless::power");
+        apply(&overrides, &mut message.sensor);
+        assert_eq!(message.sensor.tags, vec!["energy".to_string(), "meter".to_string()]);
+
+        let mut message = Message::new("tado::1::humidity").tag("heating");
+        apply(&overrides, &mut message.sensor);
+        assert_eq!(message.sensor.tags, vec!["heating".to_string()]);
+        Ok(())
+    }
+}
@@ -1,5 +1,95 @@
+use crate::core::metrics::METRICS;
 use crate::prelude::*;
-use std::time::Duration;
+use lazy_static::lazy_static;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    /// Current generation of each service. It's incremented whenever the service gets stopped.
+    static ref GENERATIONS: Mutex<HashMap<String, u64>> = Mutex::new(HashMap::new());
+
+    /// IDs of the paused services.
+    static ref PAUSED: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+
+    /// Generations of the services which have failed while running, by service ID.
+    static ref FAILED: Mutex<HashMap<String, u64>> = Mutex::new(HashMap::new());
+}
+
+/// Lets a service thread find out that the service has been stopped or restarted.
+#[derive(Clone)]
+pub struct Lifetime {
+    service_id: String,
+    generation: u64,
+}
+
+impl Lifetime {
+    /// Returns the lifetime of the current service generation.
+    pub fn current(service_id: &str) -> Self {
+        Lifetime {
+            service_id: service_id.into(),
+            generation: *GENERATIONS.lock().unwrap().entry(service_id.into()).or_insert(0),
+        }
+    }
+
+    /// Stops the service threads. They exit at their next iteration.
+    pub fn stop(service_id: &str) {
+        *GENERATIONS.lock().unwrap().entry(service_id.into()).or_insert(0) += 1;
+    }
+
+    /// Pauses the service. Its threads keep running, but skip the iterations until it's resumed.
+    pub fn pause(service_id: &str) {
+        PAUSED.lock().unwrap().insert(service_id.into());
+    }
+
+    pub fn resume(service_id: &str) {
+        PAUSED.lock().unwrap().remove(service_id);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        PAUSED.lock().unwrap().contains(&self.service_id)
+    }
+
+    pub fn is_alive(&self) -> bool {
+        GENERATIONS
+            .lock()
+            .unwrap()
+            .get(&self.service_id)
+            .map_or(true, |generation| *generation == self.generation)
+    }
+
+    /// Marks the service generation as failed, for example, when it can't start in the background.
+    pub fn mark_failed(&self) {
+        FAILED.lock().unwrap().insert(self.service_id.clone(), self.generation);
+    }
+
+    /// Whether the service generation has failed. Restarting the service starts a new generation.
+    pub fn has_failed(&self) -> bool {
+        FAILED.lock().unwrap().get(&self.service_id) == Some(&self.generation)
+    }
+
+    /// Returns the guard which marks the service as failed, if the thread exits while the service is alive.
+    /// For example, when the thread panics.
+    pub fn guard(&self) -> LivenessGuard {
+        LivenessGuard(self.clone())
+    }
+}
+
+/// Held by a service thread for as long as it runs.
+pub struct LivenessGuard(Lifetime);
+
+impl Drop for LivenessGuard {
+    fn drop(&mut self) {
+        if self.0.is_alive() {
+            error!(
+                "[{}] The thread has exited{}.",
+                self.0.service_id,
+                if thread::panicking() { " with a panic" } else { "" },
+            );
+            self.0.mark_failed();
+        }
+    }
+}
 
 /// Spawns a service thread which just periodically invokes the `loop_` function.
 /// This is a frequently repeated pattern in the services.
@@ -8,11 +98,73 @@ where
     F: Fn() -> Result,
     F: Send + 'static,
 {
-    thread::Builder::new().name(service_id.clone()).spawn(move || loop {
-        if let Err(error) = loop_() {
-            error!("[{}] The iteration has failed: {}", service_id, error.to_string());
+    METRICS.register_service(&service_id);
+    let lifetime = Lifetime::current(&service_id);
+    thread::Builder::new().name(service_id.clone()).spawn(move || {
+        let _guard = lifetime.guard();
+        while lifetime.is_alive() {
+            if !lifetime.is_paused() {
+                let start_time = Instant::now();
+                let error = loop_().err().map(|error| error.to_string());
+                if let Some(ref error) = error {
+                    error!("[{}] The iteration has failed: {}", service_id, error);
+                }
+                METRICS.record_service_iteration(&service_id, start_time.elapsed(), error);
+            }
+            thread::sleep(interval);
         }
-        thread::sleep(interval);
+        info!("[{}] Stopped.", service_id);
     })?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop_ok() {
+        let lifetime = Lifetime::current("test::stop_ok");
+        assert!(lifetime.is_alive());
+        Lifetime::stop("test::stop_ok");
+        assert!(!lifetime.is_alive());
+        assert!(Lifetime::current("test::stop_ok").is_alive());
+    }
+
+    #[test]
+    fn panicked_thread_failed() -> Result {
+        spawn_service_loop("test::panicked_thread_failed".into(), Duration::from_millis(1), || {
+            panic!("test")
+        })?;
+        let lifetime = Lifetime::current("test::panicked_thread_failed");
+        for _ in 0..100 {
+            if lifetime.has_failed() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(lifetime.has_failed());
+        Lifetime::stop("test::panicked_thread_failed");
+        assert!(!Lifetime::current("test::panicked_thread_failed").has_failed());
+        Ok(())
+    }
+
+    #[test]
+    fn stopped_thread_not_failed() {
+        let lifetime = Lifetime::current("test::stopped_thread_not_failed");
+        let guard = lifetime.guard();
+        Lifetime::stop("test::stopped_thread_not_failed");
+        drop(guard);
+        assert!(!lifetime.has_failed());
+    }
+
+    #[test]
+    fn pause_ok() {
+        let lifetime = Lifetime::current("test::pause_ok");
+        assert!(!lifetime.is_paused());
+        Lifetime::pause("test::pause_ok");
+        assert!(lifetime.is_paused());
+        Lifetime::resume("test::pause_ok");
+        assert!(!lifetime.is_paused());
+    }
+}
@@ -0,0 +1,68 @@
+//! Outcomes of the `Write` messages.
+//!
+//! A service which applies a write reports its outcome, so that the web interface can show an error
+//! instead of waiting for a new state which never comes.
+
+use crate::prelude::*;
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::time::Duration;
+
+type Outcome = std::result::Result<(), String>;
+
+lazy_static! {
+    /// Writes which someone waits for, by sensor ID and timestamp.
+    static ref PENDING: Mutex<HashMap<(String, i64), crossbeam::channel::Sender<Outcome>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Sends the `Write` message and waits for the service to report the outcome.
+/// Returns `None` if nothing has been reported within the timeout, not every service reports.
+pub fn send_and_wait(message: Message, tx: &Sender, timeout: Duration) -> Option<Outcome> {
+    let key = key(&message);
+    let (outcome_tx, outcome_rx) = crossbeam::channel::bounded(1);
+    PENDING.lock().unwrap().insert(key.clone(), outcome_tx);
+    message.send_and_forget(tx);
+    let outcome = outcome_rx.recv_timeout(timeout).ok();
+    PENDING.lock().unwrap().remove(&key);
+    outcome
+}
+
+/// Reports the outcome of the `Write` message to whoever waits for it, if anyone.
+pub fn report(message: &Message, result: &Result) {
+    if let Some(outcome_tx) = PENDING.lock().unwrap().remove(&key(message)) {
+        let _ = outcome_tx.send(result.as_ref().map(|_| ()).map_err(|error| error.to_string()));
+    }
+}
+
+fn key(message: &Message) -> (String, i64) {
+    (message.sensor.id.clone(), message.reading.timestamp.timestamp_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reported_error_ok() {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let reporter = thread::spawn(move || {
+            let message = rx.recv().unwrap();
+            report(&message, &Err("offline".into()));
+        });
+        let outcome = send_and_wait(
+            Message::new("test::writes::error").type_(MessageType::Write),
+            &tx,
+            Duration::from_secs(5),
+        );
+        reporter.join().unwrap();
+        assert_eq!(outcome, Some(Err("offline".into())));
+    }
+
+    #[test]
+    fn unreported_none() {
+        let (tx, _rx) = crossbeam::channel::unbounded();
+        let message = Message::new("test::writes::unreported").type_(MessageType::Write);
+        assert_eq!(send_and_wait(message, &tx, Duration::from_millis(10)), None);
+    }
+}
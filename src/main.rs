@@ -7,9 +7,10 @@ use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
 
 use log::LevelFilter;
-use simplelog::{ConfigBuilder, TermLogger, TerminalMode, ThreadLogMode};
+use simplelog::{CombinedLogger, ConfigBuilder, TermLogger, TerminalMode, ThreadLogMode};
 use structopt::StructOpt;
 
+use crate::core::logging::{BufferLogger, IGNORED_TARGETS};
 use crate::prelude::*;
 
 mod core;
@@ -30,6 +31,10 @@ struct Opt {
     #[structopt(short = "v", long = "verbose", conflicts_with = "silent")]
     verbose: bool,
 
+    /// Number of the recent log records shown in the web interface
+    #[structopt(long = "log-buffer-size", default_value = "1000")]
+    log_buffer_size: usize,
+
     /// Database URL
     #[structopt(long, env = "MYIOT_DB", default_value = "my-iot.sqlite3")]
     db: String,
@@ -45,6 +50,37 @@ struct Opt {
     /// Prints version information
     #[structopt(short = "V", long = "version")]
     pub version: bool,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Reads a password from the standard input and prints its hash for the settings
+    HashPassword,
+
+    /// Reads a token from the standard input and prints its hash for the settings
+    HashToken,
+
+    /// Creates an API token and prints it
+    AddToken {
+        /// Unique token name
+        name: String,
+
+        /// Token scope: `read:<pattern>`, `ingest:<prefix>` or `write:<pattern>`
+        #[structopt(long = "scope", required = true)]
+        scopes: Vec<core::db::api_token::Scope>,
+    },
+
+    /// Lists the API tokens
+    ListTokens,
+
+    /// Revokes the API token
+    RevokeToken {
+        /// Token name
+        name: String,
+    },
 }
 
 /// Entry point.
@@ -55,12 +91,18 @@ fn main() -> Result {
         println!("{}", crate_version!());
         return Ok(());
     }
+    if let Some(command) = opt.command {
+        return run_command(command, &opt.db);
+    }
 
-    init_logging(opt.silent, opt.verbose)?;
+    init_logging(opt.silent, opt.verbose, opt.log_buffer_size)?;
 
     info!("Reading the settings…");
-    let settings = settings::read(opt.settings)?;
+    let settings = settings::read(opt.settings.clone())?;
     debug!("Settings: {:?}", &settings);
+    core::staleness::set_expected_updates(&settings.expected_updates)?;
+    core::tags::set_tag_overrides(&settings.sensor_tags)?;
+    core::datetime::set_display(settings.timezone, settings.datetime_format);
 
     info!("Opening the database…");
     let db = Connection::open_and_initialize(&opt.db)?;
@@ -68,40 +110,98 @@ fn main() -> Result {
     info!("Starting services…");
     let message_counter = Arc::new(AtomicU64::new(0));
     let mut bus = Bus::new(message_counter.clone());
-    bus.add_tx()
-        .send(Message::new("my-iot::start").type_(MessageType::ReadNonLogged))?;
+    let tx = bus.add_tx();
+    tx.send(Message::new("my-iot::start").type_(MessageType::ReadNonLogged))?;
     core::db::thread::spawn(db.clone(), &mut bus)?;
     services::db::Db.spawn("system::db".into(), &mut bus, db.clone())?;
-    services::spawn_all(&settings, &opt.service_ids, &mut bus, &db)?;
+    services::staleness::Staleness.spawn("system::staleness".into(), &mut bus, db.clone())?;
+    let supervisor = services::Supervisor::new(bus.clone(), db.clone(), opt.service_ids);
+    supervisor.spawn_all(&settings)?;
+    let event_hub = web::EventHub::default();
+    event_hub.spawn(&mut bus)?;
     bus.spawn()?;
 
-    info!("Starting web server on port {}…", settings.http_port);
-    web::start_server(&settings, db, message_counter)
+    info!(
+        "Starting web server on {} port {}…",
+        settings.http_addresses.join(", "),
+        settings.http_port,
+    );
+    web::start_server(&settings, opt.settings, db, message_counter, tx, event_hub, supervisor)
 }
 
-fn init_logging(silent: bool, verbose: bool) -> Result {
-    TermLogger::init(
-        if silent {
-            LevelFilter::Warn
-        } else if verbose {
-            LevelFilter::Debug
-        } else {
-            LevelFilter::Info
-        },
-        ConfigBuilder::new()
-            .set_thread_level(LevelFilter::Error)
-            .set_target_level(LevelFilter::Error)
-            .set_location_level(LevelFilter::Debug)
-            .set_thread_mode(ThreadLogMode::Names)
-            .set_time_format_str("%F %T%.3f")
-            .set_time_to_local(true)
-            .add_filter_ignore_str("launch_")
-            .add_filter_ignore_str("rustls")
-            .add_filter_ignore_str("reqwest")
-            .add_filter_ignore_str("h2")
-            .add_filter_ignore_str("hyper")
-            .build(),
-        TerminalMode::Stderr,
-    )?;
+/// Runs the management command instead of starting the services.
+fn run_command(command: Command, db_path: &str) -> Result {
+    match command {
+        Command::HashPassword => {
+            let mut password = String::new();
+            std::io::stdin().read_line(&mut password)?;
+            println!(
+                "{}",
+                core::auth::hash_password(password.trim_end_matches(&['\r', '\n'][..]))?
+            );
+        }
+        Command::HashToken => {
+            let mut token = String::new();
+            std::io::stdin().read_line(&mut token)?;
+            println!("{}", core::auth::hash_token(token.trim_end_matches(&['\r', '\n'][..])));
+        }
+        Command::AddToken { name, scopes } => {
+            let token = core::auth::generate_token()?;
+            Connection::open_and_initialize(db_path)?.insert_api_token(
+                &name,
+                &core::auth::hash_token(&token),
+                &scopes,
+            )?;
+            println!("{}", token);
+        }
+        Command::ListTokens => {
+            for token in Connection::open_and_initialize(db_path)?.select_api_tokens()? {
+                println!(
+                    "{}\t{}\t{}",
+                    token.name,
+                    token
+                        .scopes
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    token
+                        .last_used_at
+                        .map_or_else(|| "never used".into(), |last_used_at| last_used_at.to_rfc3339()),
+                );
+            }
+        }
+        Command::RevokeToken { name } => {
+            if !Connection::open_and_initialize(db_path)?.delete_api_token(&name)? {
+                return Err(format!("there's no token named `{}`", name).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn init_logging(silent: bool, verbose: bool, log_buffer_size: usize) -> Result {
+    let level = if silent {
+        LevelFilter::Warn
+    } else if verbose {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Info
+    };
+    let mut config = ConfigBuilder::new();
+    config
+        .set_thread_level(LevelFilter::Error)
+        .set_target_level(LevelFilter::Error)
+        .set_location_level(LevelFilter::Debug)
+        .set_thread_mode(ThreadLogMode::Names)
+        .set_time_format_str("%F %T%.3f")
+        .set_time_to_local(true);
+    for target in IGNORED_TARGETS.iter() {
+        config.add_filter_ignore_str(*target);
+    }
+    CombinedLogger::init(vec![
+        TermLogger::new(level, config.build(), TerminalMode::Stderr),
+        BufferLogger::new(level, log_buffer_size),
+    ])?;
     Ok(())
 }
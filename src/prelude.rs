@@ -14,7 +14,7 @@ pub use structopt::clap::crate_version;
 pub use crate::core::bus::Bus;
 pub use crate::core::db::{reading::Reading, sensor::Sensor, Connection};
 pub use crate::core::message::{Message, Type as MessageType};
-pub use crate::core::thread::spawn_service_loop;
+pub use crate::core::thread::{spawn_service_loop, Lifetime};
 pub use crate::core::value::{PointOfTheCompass, Value};
 
 pub type Result<T = (), E = Box<dyn Error>> = std::result::Result<T, E>;
@@ -1,8 +1,11 @@
+use crate::core::metrics::{ServiceStats, METRICS};
 use crate::prelude::*;
-use crate::settings::{Service, Settings};
+use crate::settings::{self, Service, Settings};
 use lazy_static::lazy_static;
 use reqwest::header::{HeaderMap, HeaderValue};
 use reqwest::Method;
+use std::collections::HashSet;
+use std::sync::Mutex;
 use std::time::Duration;
 
 pub mod buienradar;
@@ -12,6 +15,7 @@ pub mod openweather;
 pub mod rhai;
 pub mod ring;
 pub mod solar;
+pub mod staleness;
 pub mod tado;
 pub mod telegram;
 pub mod youless;
@@ -21,36 +25,231 @@ lazy_static! {
     static ref CLIENT: Client = build_client().expect("Failed to build a client");
 }
 
-/// Spawn all the configured services.
-pub fn spawn_all(settings: &Settings, service_ids: &Option<Vec<String>>, bus: &mut Bus, db: &Connection) -> Result {
-    for (service_id, service) in settings.services.iter() {
-        if let Some(service_ids) = service_ids {
-            if !service_ids.contains(service_id) {
-                warn!("`{}` is not included in the `--service-id` option", service_id);
+/// Spawns the configured services and restarts them when the settings change.
+#[derive(Clone)]
+pub struct Supervisor {
+    bus: Bus,
+    db: Connection,
+
+    /// Only the specified services are run, if any.
+    service_ids: Option<Vec<String>>,
+
+    /// Settings of the running services.
+    running: Arc<Mutex<HashMap<String, Service>>>,
+
+    /// All the configured services, including the disabled ones.
+    configured: Arc<Mutex<HashMap<String, Service>>>,
+
+    /// IDs of the services which have failed to spawn.
+    spawn_failures: Arc<Mutex<HashSet<String>>>,
+}
+
+/// State of a configured service.
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+pub enum ServiceState {
+    Running,
+    Paused,
+
+    /// The service has failed to spawn, or its thread has exited. It's retried when restarted.
+    Failed,
+
+    /// The service is excluded with the `--service-id` option.
+    Disabled,
+}
+
+/// Service status as it's shown on the services page.
+#[derive(Serialize)]
+pub struct ServiceStatus {
+    pub service_id: String,
+
+    #[serde(rename = "type")]
+    pub type_: &'static str,
+
+    pub state: ServiceState,
+
+    #[serde(flatten)]
+    pub stats: ServiceStats,
+}
+
+/// Differences between the running services and the services in the settings.
+#[derive(Default, Debug, PartialEq)]
+pub struct ServiceChanges {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl ServiceChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl Supervisor {
+    pub fn new(bus: Bus, db: Connection, service_ids: Option<Vec<String>>) -> Self {
+        Supervisor {
+            bus,
+            db,
+            service_ids,
+            running: Arc::new(Mutex::new(HashMap::new())),
+            configured: Arc::new(Mutex::new(HashMap::new())),
+            spawn_failures: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Spawn all the configured services.
+    pub fn spawn_all(&self, settings: &Settings) -> Result {
+        *self.configured.lock().unwrap() = settings.services.clone();
+        for service_id in settings.services.keys() {
+            self.spawn(service_id, settings);
+        }
+        Ok(())
+    }
+
+    /// Compares the running services with the settings.
+    pub fn diff(&self, settings: &Settings) -> Result<ServiceChanges> {
+        let running = self.running.lock().unwrap();
+        let mut changes = ServiceChanges::default();
+        for (service_id, service) in settings.services.iter() {
+            if !self.is_enabled(service_id) {
                 continue;
             }
+            match running.get(service_id) {
+                None => changes.added.push(service_id.clone()),
+                Some(running) if !is_same(running, service)? => changes.changed.push(service_id.clone()),
+                Some(_) => {}
+            }
+        }
+        for service_id in running.keys() {
+            if !settings.services.contains_key(service_id) {
+                changes.removed.push(service_id.clone());
+            }
         }
+        changes.added.sort();
+        changes.changed.sort();
+        changes.removed.sort();
+        Ok(changes)
+    }
+
+    /// Stops the removed and changed services and spawns the added and changed ones.
+    pub fn apply(&self, settings: &Settings) -> Result<ServiceChanges> {
+        let changes = self.diff(settings)?;
+        *self.configured.lock().unwrap() = settings.services.clone();
+        for service_id in changes.removed.iter().chain(changes.changed.iter()) {
+            info!("Stopping service `{}`…", service_id);
+            Lifetime::stop(service_id);
+            self.running.lock().unwrap().remove(service_id);
+        }
+        for service_id in changes.added.iter().chain(changes.changed.iter()) {
+            self.spawn(service_id, settings);
+        }
+        Ok(changes)
+    }
+
+    /// Stops the service and spawns it again with the same settings. It also resumes a paused service.
+    /// Returns `false` if there's no such running and configured service.
+    pub fn restart(&self, service_id: &str) -> bool {
+        let services = self.configured.lock().unwrap().clone();
+        let service = match services.get(service_id) {
+            Some(service) if self.running.lock().unwrap().contains_key(service_id) => service.clone(),
+            _ => return false,
+        };
+        info!("Restarting service `{}`…", service_id);
+        Lifetime::stop(service_id);
+        Lifetime::resume(service_id);
+        self.spawn_service(service_id, service, &services);
+        true
+    }
+
+    /// Pauses or resumes the running service. Returns `false` if there's no such running service.
+    pub fn set_paused(&self, service_id: &str, is_paused: bool) -> bool {
+        if !self.running.lock().unwrap().contains_key(service_id) {
+            return false;
+        }
+        if is_paused {
+            info!("Pausing service `{}`…", service_id);
+            Lifetime::pause(service_id);
+        } else {
+            info!("Resuming service `{}`…", service_id);
+            Lifetime::resume(service_id);
+        }
+        true
+    }
+
+    /// Returns the statuses of the configured services sorted by service ID.
+    pub fn statuses(&self) -> Vec<ServiceStatus> {
+        let configured = self.configured.lock().unwrap();
+        let running = self.running.lock().unwrap();
+        let spawn_failures = self.spawn_failures.lock().unwrap();
+        let mut statuses: Vec<ServiceStatus> = configured
+            .iter()
+            .map(|(service_id, service)| ServiceStatus {
+                service_id: service_id.clone(),
+                type_: service.type_name(),
+                state: if !running.contains_key(service_id) {
+                    ServiceState::Disabled
+                } else if spawn_failures.contains(service_id) || Lifetime::current(service_id).has_failed() {
+                    ServiceState::Failed
+                } else if Lifetime::current(service_id).is_paused() {
+                    ServiceState::Paused
+                } else {
+                    ServiceState::Running
+                },
+                stats: METRICS.service_stats(service_id),
+            })
+            .collect();
+        statuses.sort_by(|lhs, rhs| lhs.service_id.cmp(&rhs.service_id));
+        statuses
+    }
+
+    fn is_enabled(&self, service_id: &str) -> bool {
+        self.service_ids
+            .as_ref()
+            .map_or(true, |service_ids| service_ids.iter().any(|id| id == service_id))
+    }
 
+    fn spawn(&self, service_id: &str, settings: &Settings) {
+        if !self.is_enabled(service_id) {
+            warn!("`{}` is not included in the `--service-id` option", service_id);
+            return;
+        }
+        self.spawn_service(service_id, settings.services[service_id].clone(), &settings.services);
+    }
+
+    /// Spawns the service and logs any errors.
+    fn spawn_service(&self, service_id: &str, service: Service, services: &HashMap<String, Service>) {
         info!("Spawning service `{}`…", service_id);
+        METRICS.register_service(service_id);
         debug!("Settings `{}`: {:?}", service_id, service);
+        let mut bus = self.bus.clone();
         if let Err(error) = {
-            let service_id = service_id.clone();
+            let service_id = service_id.to_string();
             match service.clone() {
-                Service::Buienradar(buienradar) => buienradar.spawn(service_id, bus),
-                Service::Clock(clock) => clock.spawn(service_id, bus),
-                Service::OpenWeather(openweather) => openweather.spawn(service_id, bus),
-                Service::Rhai(rhai) => rhai.spawn(service_id, bus, settings.services.clone()),
-                Service::Solar(solar) => solar.spawn(service_id, bus),
-                Service::Tado(tado) => tado.spawn(service_id, bus),
-                Service::Telegram(telegram) => telegram.spawn(service_id, bus),
-                Service::YouLess(youless) => youless.spawn(service_id, bus),
-                Service::Ring(ring) => ring.spawn(service_id, bus, db),
+                Service::Buienradar(buienradar) => buienradar.spawn(service_id, &mut bus),
+                Service::Clock(clock) => clock.spawn(service_id, &mut bus),
+                Service::OpenWeather(openweather) => openweather.spawn(service_id, &mut bus),
+                Service::Rhai(rhai) => rhai.spawn(service_id, &mut bus, services.clone()),
+                Service::Solar(solar) => solar.spawn(service_id, &mut bus),
+                Service::Tado(tado) => tado.spawn(service_id, &mut bus),
+                Service::Telegram(telegram) => telegram.spawn(service_id, &mut bus),
+                Service::YouLess(youless) => youless.spawn(service_id, &mut bus),
+                Service::Ring(ring) => ring.spawn(service_id, &mut bus, &self.db),
             }
         } {
             error!("Failed to spawn `{}`: {}", service_id, error.to_string());
+            METRICS.record_service_error(service_id, error.to_string());
+            self.spawn_failures.lock().unwrap().insert(service_id.into());
+        } else {
+            self.spawn_failures.lock().unwrap().remove(service_id);
         }
+        // A failed service is still remembered, so that it's retried once its settings are fixed.
+        self.running.lock().unwrap().insert(service_id.into(), service);
     }
-    Ok(())
+}
+
+/// Compares the service settings including the secrets.
+fn is_same(lhs: &Service, rhs: &Service) -> Result<bool> {
+    settings::secret::reveal(|| -> Result<bool> { Ok(serde_json::to_value(lhs)? == serde_json::to_value(rhs)?) })
 }
 
 /// Builds an HTTP client to use with a service.
@@ -87,3 +286,87 @@ where
     debug!("Finished {}.", url);
     Ok(response)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU64;
+
+    #[test]
+    fn diff_ok() -> Result {
+        let supervisor = Supervisor::new(
+            Bus::new(Arc::new(AtomicU64::new(0))),
+            Connection::open_and_initialize(":memory:")?,
+            None,
+        );
+        supervisor.spawn_all(&crate::settings::parse(&[r#"
+            [services.diff_ok_changed]
+            type = "Clock"
+            [services.diff_ok_removed]
+            type = "Clock"
+        "#
+        .to_string()])?)?;
+        let changes = supervisor.diff(&crate::settings::parse(&[r#"
+            [services.diff_ok_changed]
+            type = "Clock"
+            interval_millis = 60000
+            [services.diff_ok_added]
+            type = "Clock"
+        "#
+        .to_string()])?)?;
+        assert_eq!(
+            changes,
+            ServiceChanges {
+                added: vec!["diff_ok_added".into()],
+                changed: vec!["diff_ok_changed".into()],
+                removed: vec!["diff_ok_removed".into()],
+            }
+        );
+        Lifetime::stop("diff_ok_changed");
+        Lifetime::stop("diff_ok_removed");
+        Ok(())
+    }
+
+    #[test]
+    fn statuses_ok() -> Result {
+        let supervisor = Supervisor::new(
+            Bus::new(Arc::new(AtomicU64::new(0))),
+            Connection::open_and_initialize(":memory:")?,
+            None,
+        );
+        supervisor.spawn_all(&crate::settings::parse(&[r#"
+            [services.statuses_ok]
+            type = "Clock"
+        "#
+        .to_string()])?)?;
+        assert!(supervisor.set_paused("statuses_ok", true));
+        assert!(!supervisor.set_paused("statuses_ok_missing", true));
+        let statuses = supervisor.statuses();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].type_, "Clock");
+        assert_eq!(statuses[0].state, ServiceState::Paused);
+        assert!(supervisor.restart("statuses_ok"));
+        assert_eq!(supervisor.statuses()[0].state, ServiceState::Running);
+        Lifetime::stop("statuses_ok");
+        Ok(())
+    }
+
+    #[test]
+    fn restart_unconfigured_false() -> Result {
+        let supervisor = Supervisor::new(
+            Bus::new(Arc::new(AtomicU64::new(0))),
+            Connection::open_and_initialize(":memory:")?,
+            None,
+        );
+        supervisor.spawn_all(&crate::settings::parse(&[r#"
+            [services.restart_unconfigured_false]
+            type = "Clock"
+        "#
+        .to_string()])?)?;
+        supervisor.configured.lock().unwrap().clear();
+        assert!(!supervisor.restart("restart_unconfigured_false"));
+        assert!(!supervisor.restart("restart_unconfigured_false_missing"));
+        Lifetime::stop("restart_unconfigured_false");
+        Ok(())
+    }
+}
@@ -48,6 +48,7 @@ impl Buienradar {
         if let Some(temperature) = measurement.temperature {
             tx.send(
                 Message::new(format!("{}::temperature", sensor_prefix))
+                    .tag("outdoor")
                     .type_(MessageType::ReadLogged)
                     .value(Value::Temperature(temperature))
                     .timestamp(measurement.timestamp)
@@ -58,6 +59,7 @@ impl Buienradar {
         if let Some(temperature) = measurement.ground_temperature {
             tx.send(
                 Message::new(format!("{}::temperature::ground", sensor_prefix))
+                    .tag("outdoor")
                     .type_(MessageType::ReadLogged)
                     .value(Value::Temperature(temperature))
                     .timestamp(measurement.timestamp)
@@ -68,6 +70,7 @@ impl Buienradar {
         if let Some(temperature) = measurement.feel_temperature {
             tx.send(
                 Message::new(format!("{}::temperature::feel", sensor_prefix))
+                    .tag("outdoor")
                     .type_(MessageType::ReadLogged)
                     .value(Value::Temperature(temperature))
                     .timestamp(measurement.timestamp)
@@ -78,6 +81,7 @@ impl Buienradar {
         if let Some(bft) = measurement.wind_speed_bft {
             tx.send(
                 Message::new(format!("{}::wind::force", sensor_prefix))
+                    .tag("outdoor")
                     .type_(MessageType::ReadLogged)
                     .value(Value::Bft(bft))
                     .timestamp(measurement.timestamp)
@@ -88,6 +92,7 @@ impl Buienradar {
         if let Some(point) = measurement.wind_direction {
             tx.send(
                 Message::new(format!("{}::wind::direction", sensor_prefix))
+                    .tag("outdoor")
                     .type_(MessageType::ReadLogged)
                     .value(Value::WindDirection(point))
                     .timestamp(measurement.timestamp)
@@ -97,6 +102,7 @@ impl Buienradar {
         }
         if let Some(watts) = measurement.sun_power {
             Message::new(format!("{}::sun::power", sensor_prefix))
+                .tag("outdoor")
                 .value(Value::Power(watts))
                 .timestamp(measurement.timestamp)
                 .sensor_title("Sun Power per ㎡")
@@ -105,6 +111,7 @@ impl Buienradar {
         }
         if let Some(speed) = measurement.wind_speed {
             Message::new(format!("{}::wind::speed", sensor_prefix))
+                .tag("outdoor")
                 .value(Value::Speed(speed))
                 .timestamp(measurement.timestamp)
                 .sensor_title("Wind Speed")
@@ -113,6 +120,7 @@ impl Buienradar {
         }
         if let Some(speed) = measurement.wind_gusts {
             Message::new(format!("{}::wind::gusts", sensor_prefix))
+                .tag("outdoor")
                 .value(Value::Speed(speed))
                 .timestamp(measurement.timestamp)
                 .sensor_title("Wind Gusts")
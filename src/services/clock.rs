@@ -20,14 +20,18 @@ impl Clock {
     pub fn spawn(self, service_id: String, bus: &mut Bus) -> Result {
         let interval = Duration::from_millis(self.interval_millis);
         let tx = bus.add_tx();
+        let lifetime = Lifetime::current(&service_id);
 
         thread::Builder::new().name(service_id.clone()).spawn(move || {
+            let _guard = lifetime.guard();
             let mut counter = 1;
-            loop {
-                Message::new(&service_id)
-                    .value(Value::Counter(counter))
-                    .send_and_forget(&tx);
-                counter += 1;
+            while lifetime.is_alive() {
+                if !lifetime.is_paused() {
+                    Message::new(&service_id)
+                        .value(Value::Counter(counter))
+                        .send_and_forget(&tx);
+                    counter += 1;
+                }
                 thread::sleep(interval);
             }
         })?;
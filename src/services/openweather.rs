@@ -1,5 +1,6 @@
 use crate::prelude::*;
 use crate::services::{deserialize_timestamp, CLIENT};
+use crate::settings::Secret;
 use reqwest::Url;
 use std::time::Duration;
 
@@ -12,9 +13,9 @@ pub struct OpenWeather {
 
 #[derive(Deserialize, Debug, Clone, Serialize)]
 struct Secrets {
-    api_key: String,
-    latitude: f64,
-    longitude: f64,
+    api_key: Secret<String>,
+    latitude: Secret<f64>,
+    longitude: Secret<f64>,
 }
 
 /// <https://openweathermap.org/current>
@@ -31,7 +32,7 @@ impl OpenWeather {
                 &[
                     ("units", "metric"),
                     ("lang", "en"),
-                    ("appid", &self.secrets.api_key),
+                    ("appid", self.secrets.api_key.as_str()),
                     ("lat", &self.secrets.latitude.to_string()),
                     ("lon", &self.secrets.longitude.to_string()),
                 ],
@@ -43,24 +44,28 @@ impl OpenWeather {
         let sensor_prefix = format!("{}::{}", service_id, response.city_id);
 
         Message::new(format!("{}::temperature", sensor_prefix))
+            .tag("outdoor")
             .value(Value::Temperature(response.main.temperature))
             .timestamp(response.timestamp)
             .sensor_title("Temperature")
             .location(&response.city_name)
             .send_and_forget(tx);
         Message::new(format!("{}::temperature::feel", sensor_prefix))
+            .tag("outdoor")
             .value(Value::Temperature(response.main.feel_temperature))
             .timestamp(response.timestamp)
             .sensor_title("Feel Temperature")
             .location(&response.city_name)
             .send_and_forget(tx);
         Message::new(format!("{}::temperature::min", sensor_prefix))
+            .tag("outdoor")
             .value(Value::Temperature(response.main.temperature_min))
             .timestamp(response.timestamp)
             .sensor_title("Minimal Temperature")
             .location(&response.city_name)
             .send_and_forget(tx);
         Message::new(format!("{}::temperature::max", sensor_prefix))
+            .tag("outdoor")
             .value(Value::Temperature(response.main.temperature_max))
             .timestamp(response.timestamp)
             .sensor_title("Maximal Temperature")
@@ -68,6 +73,7 @@ impl OpenWeather {
             .send_and_forget(tx);
 
         Message::new(format!("{}::wind::speed", sensor_prefix))
+            .tag("outdoor")
             .value(Value::Speed(response.wind.speed))
             .timestamp(response.timestamp)
             .sensor_title("Wind Speed")
@@ -75,6 +81,7 @@ impl OpenWeather {
             .send_and_forget(tx);
         if let Some(speed) = response.wind.gusts {
             Message::new(format!("{}::wind::gusts", sensor_prefix))
+                .tag("outdoor")
                 .value(Value::Speed(speed))
                 .timestamp(response.timestamp)
                 .sensor_title("Wind Gusts")
@@ -83,6 +90,7 @@ impl OpenWeather {
         }
 
         Message::new(format!("{}::cloudiness", sensor_prefix))
+            .tag("outdoor")
             .value(Value::Cloudiness(response.clouds.all))
             .timestamp(response.timestamp)
             .sensor_title("Cloudiness")
@@ -90,6 +98,7 @@ impl OpenWeather {
             .send_and_forget(tx);
 
         Message::new(format!("{}::rain::last_hour", sensor_prefix))
+            .tag("outdoor")
             .value(Value::from_mm(response.rain.last_hour))
             .timestamp(response.timestamp)
             .sensor_title("Rain Last Hour")
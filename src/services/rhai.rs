@@ -1,8 +1,11 @@
 use std::process::Command;
+use std::time::Instant;
 
 use itertools::Itertools;
 use rhai::{Array, Dynamic, Engine, EvalAltResult, ImmutableString, RegisterFn, RegisterResultFn, Scope, AST};
 
+use crate::core::datetime;
+use crate::core::metrics::METRICS;
 use crate::prelude::*;
 use crate::settings::Service;
 
@@ -13,16 +16,23 @@ mod telegram;
 #[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct Rhai {
     script: String,
+
+    /// Only the messages of the sensors with any of the tags are passed to `on_message`.
+    /// All the messages are passed if it's empty.
+    #[serde(default)]
+    tags: Vec<String>,
 }
 
 impl Rhai {
     pub fn spawn(self, service_id: String, bus: &mut Bus, services: HashMap<String, Service>) -> Result {
         let tx = bus.add_tx();
-        let rx = bus.add_rx();
+        let rx = bus.add_rx_tagged(&service_id, self.tags.clone());
+        let lifetime = Lifetime::current(&service_id);
 
         thread::Builder::new()
             .name(service_id.clone())
             .spawn(move || -> Result<(), ()> {
+                let _guard = lifetime.guard();
                 let mut engine = Engine::new();
                 engine.set_max_expr_depths(128, 32);
                 let ast = self.compile_script(&service_id, &engine)?;
@@ -38,12 +48,25 @@ impl Rhai {
                 self.consume_ast(&service_id, &engine, &ast, &mut scope)?;
 
                 for message in &rx {
-                    if let Err(error) = engine.call_fn::<_, Dynamic>(&mut scope, &ast, "on_message", (message,)) {
-                        error!("[{}] `on_message` has failed: {}", &service_id, error.to_string());
+                    if !lifetime.is_alive() {
+                        // Dropping the receiver unsubscribes the service from the bus.
+                        break;
+                    }
+                    if lifetime.is_paused() {
+                        continue;
+                    }
+                    let start_time = Instant::now();
+                    let error = engine
+                        .call_fn::<_, Dynamic>(&mut scope, &ast, "on_message", (message,))
+                        .err()
+                        .map(|error| error.to_string());
+                    if let Some(ref error) = error {
+                        error!("[{}] `on_message` has failed: {}", &service_id, error);
                     }
+                    METRICS.record_service_iteration(&service_id, start_time.elapsed(), error);
                 }
 
-                unreachable!();
+                Ok(())
             })?;
 
         Ok(())
@@ -78,7 +101,7 @@ impl Rhai {
 
     fn register_functions(engine: &mut Engine, tx: &Sender) {
         Self::register_debug_functions::<MessageType>(engine);
-        Self::register_debug_functions::<DateTime<Local>>(engine);
+        Self::register_datetime_functions(engine);
 
         Self::register_message_functions(engine, &tx);
         Self::register_value_functions(engine);
@@ -137,6 +160,42 @@ impl Rhai {
         engine.register_fn("+", |left: T, right: &str| format!("{:?}", left) + right);
     }
 
+    /// Registers the timestamp functions. The timestamps are converted into the display time zone.
+    fn register_datetime_functions(engine: &mut Engine) {
+        engine.register_fn("to_string", |this: &mut DateTime<Local>| datetime::format(this));
+        engine.register_fn("print", |this: &mut DateTime<Local>| datetime::format(this));
+        engine.register_fn("debug", to_debug_string::<DateTime<Local>>);
+        engine.register_fn("+", |left: &str, right: DateTime<Local>| {
+            left.to_owned() + &datetime::format(&right)
+        });
+        engine.register_fn("+", |left: DateTime<Local>, right: &str| {
+            datetime::format(&left) + right
+        });
+
+        engine.register_fn("now", Local::now);
+        engine.register_get("year", |this: &mut DateTime<Local>| {
+            datetime::to_display(this).year() as i64
+        });
+        engine.register_get("month", |this: &mut DateTime<Local>| {
+            datetime::to_display(this).month() as i64
+        });
+        engine.register_get("day", |this: &mut DateTime<Local>| {
+            datetime::to_display(this).day() as i64
+        });
+        engine.register_get("hour", |this: &mut DateTime<Local>| {
+            datetime::to_display(this).hour() as i64
+        });
+        engine.register_get("minute", |this: &mut DateTime<Local>| {
+            datetime::to_display(this).minute() as i64
+        });
+        engine.register_get("second", |this: &mut DateTime<Local>| {
+            datetime::to_display(this).second() as i64
+        });
+        engine.register_get("weekday", |this: &mut DateTime<Local>| {
+            datetime::to_display(this).weekday().number_from_monday() as i64
+        });
+    }
+
     /// Registers `Message` functions.
     fn register_message_functions(engine: &mut Engine, tx: &Sender) {
         Self::register_debug_functions::<Message>(engine);
@@ -191,6 +250,12 @@ impl Rhai {
                 this.reading.timestamp = timestamp;
             },
         );
+        engine.register_fn("has_tag", |this: &mut Message, tag: String| this.sensor.has_tag(&tag));
+        engine.register_fn("add_tag", |this: &mut Message, tag: String| {
+            if !this.sensor.has_tag(&tag) {
+                this.sensor.tags.push(tag);
+            }
+        });
     }
 
     fn register_value_functions(engine: &mut Engine) {
@@ -239,6 +304,17 @@ fn spawn_process(program: &str, args: Array) -> FnResult {
 mod tests {
     use super::*;
 
+    #[test]
+    fn datetime_functions_ok() -> Result {
+        let mut engine = Engine::new();
+        Rhai::register_datetime_functions(&mut engine);
+        let mut scope = Scope::new();
+        scope.push("timestamp", Local.ymd(2020, 7, 1).and_hms(12, 30, 0));
+        assert_eq!(engine.eval_with_scope::<i64>(&mut scope, "timestamp.weekday")?, 3);
+        assert_eq!(engine.eval_with_scope::<i64>(&mut scope, "timestamp.minute")?, 30);
+        Ok(())
+    }
+
     #[test]
     fn spawn_process_ok() -> Result {
         let mut engine = Engine::new();
@@ -5,6 +5,7 @@ use reqwest::Method;
 
 use crate::prelude::*;
 use crate::services::{call_json_api, CLIENT};
+use crate::settings::Secret;
 use bytes::Bytes;
 
 #[derive(Deserialize, Debug, Clone, Serialize)]
@@ -18,7 +19,7 @@ pub struct Ring {
 #[derive(Deserialize, Debug, Clone, Serialize)]
 struct Secrets {
     /// Initial `refresh_token` used to get an active access token for the first time.
-    initial_refresh_token: String,
+    initial_refresh_token: Secret<String>,
 }
 
 const fn default_interval_millis() -> u64 {
@@ -42,6 +43,7 @@ impl Ring {
                             .location(&device.description)
                             .sensor_title("Doorbot Battery State")
                             .value(Value::BatteryLife(f64::from_str(battery_life)?))
+                            .tag("battery")
                             .send_and_forget(&tx);
                     }
                     self.process_doorbot_recordings(&service_id, &db, &device, &tx)?;
@@ -138,7 +140,7 @@ impl Ring {
                 info!("[{}] Refreshing access token…", service_id);
                 let refresh_token = db
                     .get_user_data::<String>(&refresh_token_key)?
-                    .unwrap_or_else(|| self.secrets.initial_refresh_token.clone());
+                    .unwrap_or_else(|| self.secrets.initial_refresh_token.to_string());
                 let response = CLIENT
                     .post("https://oauth.ring.com/oauth/token")
                     .form(&[
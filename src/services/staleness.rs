@@ -0,0 +1,39 @@
+//! System service which reports the sensors missing their expected updates.
+
+use crate::core::staleness::{is_stale, IS_STALE_SUFFIX};
+use crate::prelude::*;
+use std::time::Duration;
+
+const INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct Staleness;
+
+impl Staleness {
+    pub fn spawn(self, service_id: String, bus: &mut Bus, db: Connection) -> Result {
+        let tx = bus.add_tx();
+        spawn_service_loop(service_id, INTERVAL, move || self.loop_(&db, &tx))
+    }
+
+    /// Sends `…::is_stale` whenever a sensor staleness differs from the last reported one.
+    fn loop_(&self, db: &Connection, tx: &Sender) -> Result {
+        let actuals = db.select_actuals()?;
+        let reported: HashMap<&str, bool> = actuals
+            .iter()
+            .filter(|(sensor, _)| sensor.id.ends_with(IS_STALE_SUFFIX))
+            .map(|(sensor, reading)| (sensor.id.as_str(), reading.value == Value::Boolean(true)))
+            .collect();
+        for (sensor, reading) in &actuals {
+            let is_stale = is_stale(&sensor.id, reading);
+            let sensor_id = format!("{}{}", sensor.id, IS_STALE_SUFFIX);
+            if reported.get(sensor_id.as_str()).copied().unwrap_or(false) != is_stale {
+                tx.send(
+                    Message::new(sensor_id)
+                        .value(is_stale)
+                        .sensor_title(format!("{} Is Stale", sensor.title.as_deref().unwrap_or(&sensor.id)))
+                        .location(&sensor.location),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
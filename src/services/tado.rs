@@ -1,8 +1,13 @@
 //! [tado°](https://www.tado.com/) API.
 
+use crate::core::control::Control;
+use crate::core::metrics::METRICS;
+use crate::core::writes;
 use crate::prelude::*;
 use crate::services::{call_json_api, CLIENT};
+use crate::settings::Secret;
 use reqwest::{Method, Url};
+use serde_json::json;
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::{Duration, SystemTime};
 
@@ -10,6 +15,11 @@ const CLIENT_ID: &str = "public-api-preview";
 const CLIENT_SECRET: &str = "4HJGRffVR8xb3XdEUQpjgZ1VplJi6Xgw";
 const SCOPE: &str = "home.user";
 const REFRESH_PERIOD: Duration = Duration::from_secs(180);
+const SET_TEMPERATURE_SUFFIX: &str = "::temperature::set";
+
+/// Temperature range which is accepted by heating zones, in Celsius.
+const MIN_TEMPERATURE: f64 = 5.0;
+const MAX_TEMPERATURE: f64 = 25.0;
 
 #[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct Tado {
@@ -27,8 +37,8 @@ pub struct Tado {
 
 #[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct Secrets {
-    email: String,
-    password: String,
+    email: Secret<String>,
+    password: Secret<String>,
 }
 
 /// Creates an empty token by default.
@@ -37,11 +47,34 @@ fn default_token() -> Arc<Mutex<Option<Token>>> {
 }
 
 impl Tado {
+    /// Logging in takes a few requests, thus the service starts in the background.
+    /// Otherwise, restarting it would block the web request.
     pub fn spawn(self, service_id: String, bus: &mut Bus) -> Result {
         let tx = bus.add_tx();
+        let rx = bus.add_rx(&service_id);
+        let lifetime = Lifetime::current(&service_id);
+
+        thread::Builder::new().name(service_id.clone()).spawn(move || {
+            if let Err(error) = self.start(service_id.clone(), &lifetime, rx, tx) {
+                error!("[{}] Failed to start: {}", service_id, error.to_string());
+                METRICS.record_service_error(&service_id, error.to_string());
+                lifetime.mark_failed();
+            }
+        })?;
+
+        Ok(())
+    }
+
+    fn start(self, service_id: String, lifetime: &Lifetime, rx: Receiver, tx: Sender) -> Result {
         let me = self.get_me()?;
         let home = self.get_home(me.home_id)?;
+        if !lifetime.is_alive() {
+            // The service has been stopped or restarted while logging in.
+            return Ok(());
+        }
 
+        self.clone()
+            .spawn_writer(service_id.clone(), me.home_id, rx, tx.clone())?;
         spawn_service_loop(service_id.clone(), REFRESH_PERIOD, move || {
             self.loop_(&service_id, &me, &home, &tx)
         })
@@ -74,11 +107,13 @@ impl Tado {
             };
 
             Message::new(format!("{}::is_online", sensor_prefix))
+                .tag("heating")
                 .value(zone_state.link.state == LinkState::Online)
                 .location(&zone.name)
                 .sensor_title(format!("{} Online", zone_title))
                 .send_and_forget(tx);
             Message::new(format!("{}::is_on", sensor_prefix))
+                .tag("heating")
                 .value(zone_state.setting.power == PowerState::On)
                 .location(&zone.name)
                 .sensor_title(format!("{} On", zone_title,))
@@ -86,6 +121,7 @@ impl Tado {
 
             if zone.open_window_detection.supported && zone.open_window_detection.enabled == Some(true) {
                 Message::new(format!("{}::is_window_closed", sensor_prefix))
+                    .tag("heating")
                     .value(!zone_state.open_window_detected)
                     .location(&zone.name)
                     .sensor_title("Is Window Closed")
@@ -93,15 +129,22 @@ impl Tado {
             }
 
             if let ZoneSettingAttributes::Heating { temperature } = zone_state.setting.attributes {
-                Message::new(format!("{}::temperature::set", sensor_prefix))
+                Message::new(format!("{}{}", sensor_prefix, SET_TEMPERATURE_SUFFIX))
+                    .tag("heating")
                     .value(Value::Temperature(temperature.celsius))
                     .location(&zone.name)
                     .sensor_title("Set Temperature")
+                    .control(Control::Slider {
+                        min: MIN_TEMPERATURE,
+                        max: MAX_TEMPERATURE,
+                        step: 0.5,
+                    })
                     .send_and_forget(tx);
             }
 
             if let Some(humidity) = zone_state.sensor_data_points.humidity {
                 Message::new(format!("{}::humidity", sensor_prefix))
+                    .tag("heating")
                     .timestamp(humidity.timestamp)
                     .location(&zone.name)
                     .sensor_title("Humidity")
@@ -111,6 +154,7 @@ impl Tado {
 
             if let Some(temperature) = zone_state.sensor_data_points.inside_temperature {
                 Message::new(format!("{}::temperature", sensor_prefix))
+                    .tag("heating")
                     .timestamp(temperature.timestamp)
                     .location(&zone.name)
                     .sensor_title("Ambient Temperature")
@@ -120,6 +164,7 @@ impl Tado {
 
             if self.enable_open_window_detection_skill && zone_state.open_window_detected {
                 Message::new(format!("{}::open_window_activated", sensor_prefix))
+                    .tag("heating")
                     .type_(MessageType::ReadNonLogged)
                     .location(&zone.name)
                     .sensor_title("Open Window Activated")
@@ -132,6 +177,62 @@ impl Tado {
     }
 }
 
+/// Control.
+impl Tado {
+    /// Spawns the thread which handles the `Write` messages, those change the zone set temperatures.
+    fn spawn_writer(self, service_id: String, home_id: u32, rx: Receiver, tx: Sender) -> Result {
+        let lifetime = Lifetime::current(&service_id);
+        let sensor_prefix = format!("{}::{}::", service_id, home_id);
+
+        thread::Builder::new().name(service_id.clone()).spawn(move || {
+            let _guard = lifetime.guard();
+            for message in &rx {
+                if !lifetime.is_alive() {
+                    break;
+                }
+                if message.type_ != MessageType::Write || lifetime.is_paused() {
+                    continue;
+                }
+                if let Some(zone_id) = parse_set_temperature_zone_id(&sensor_prefix, &message.sensor.id) {
+                    let result = self.write_set_temperature(home_id, zone_id, &message, &tx);
+                    writes::report(&message, &result);
+                    if let Err(error) = result {
+                        error!("[{}] Failed to set the temperature: {}", service_id, error.to_string());
+                    }
+                }
+            }
+        })?;
+
+        Ok(())
+    }
+
+    /// Sets the manual temperature overlay and sends the new set temperature back.
+    fn write_set_temperature(&self, home_id: u32, zone_id: u32, message: &Message, tx: &Sender) -> Result {
+        let celsius = match message.reading.value {
+            Value::Temperature(celsius) if (MIN_TEMPERATURE..=MAX_TEMPERATURE).contains(&celsius) => celsius,
+            ref value => return Err(format!("unexpected value: {:?}", value).into()),
+        };
+        self.set_overlay(home_id, zone_id, celsius)?;
+        message
+            .clone()
+            .type_(MessageType::ReadLogged)
+            .timestamp(Local::now())
+            .send_and_forget(tx);
+        Ok(())
+    }
+}
+
+/// Parses the zone ID from a set temperature sensor ID, for example: `tado::123456::1::temperature::set`.
+fn parse_set_temperature_zone_id(sensor_prefix: &str, sensor_id: &str) -> Option<u32> {
+    if !sensor_id.starts_with(sensor_prefix) || !sensor_id.ends_with(SET_TEMPERATURE_SUFFIX) {
+        return None;
+    }
+    sensor_id
+        .get(sensor_prefix.len()..sensor_id.len() - SET_TEMPERATURE_SUFFIX.len())?
+        .parse()
+        .ok()
+}
+
 /// Authentication.
 impl Tado {
     /// Ensures that the service is logged in. Logs in or refreshes the access token when needed.
@@ -165,8 +266,8 @@ impl Tado {
                     ("client_secret", CLIENT_SECRET),
                     ("grant_type", "password"),
                     ("scope", SCOPE),
-                    ("username", &self.secrets.email),
-                    ("password", &self.secrets.password),
+                    ("username", self.secrets.email.as_str()),
+                    ("password", self.secrets.password.as_str()),
                 ],
             )?)
             .send()?
@@ -246,6 +347,22 @@ impl Tado {
         )
     }
 
+    /// Sets the manual heating temperature of the zone, until it's changed again.
+    fn set_overlay(&self, home_id: u32, zone_id: u32, celsius: f64) -> Result {
+        let url = format!("https://my.tado.com/api/v2/homes/{}/zones/{}/overlay", home_id, zone_id,);
+        debug!("Calling {}…", url);
+        CLIENT
+            .put(&url)
+            .header("Authorization", format!("Bearer {}", self.get_access_token()?))
+            .json(&json!({
+                "setting": {"type": "HEATING", "power": "ON", "temperature": {"celsius": celsius}},
+                "termination": {"type": "MANUAL"},
+            }))
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+
     /// Activates the [Open Window](https://support.tado.com/en/articles/3387308-how-does-the-open-window-detection-skill-work) mode.
     fn activate_open_window(&self, home_id: u32, zone_id: u32) -> Result {
         self.call(
@@ -426,6 +543,22 @@ fn deserialize_expires_at<'de, D: Deserializer<'de>>(deserializer: D) -> std::re
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse_set_temperature_zone_id_ok() {
+        assert_eq!(
+            parse_set_temperature_zone_id("tado::123456::", "tado::123456::1::temperature::set"),
+            Some(1),
+        );
+        assert_eq!(
+            parse_set_temperature_zone_id("tado::123456::", "tado::123456::1::temperature"),
+            None
+        );
+        assert_eq!(
+            parse_set_temperature_zone_id("tado::123456::", "tado::1::temperature::set"),
+            None
+        );
+    }
+
     #[test]
     fn parse_token() -> Result {
         // language=json
@@ -1,18 +1,21 @@
 //! [Telegram bot](https://core.telegram.org/bots/api) service which is able to receive and send messages.
 
 use std::fmt::Debug;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
 use log::debug;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+use crate::core::metrics::METRICS;
 use crate::prelude::*;
 use crate::services::CLIENT;
+use crate::settings::Secret;
 use reqwest::blocking::multipart::{Form, Part};
 
 const GET_UPDATES_TIMEOUT_SECS: u64 = 60;
+const PAUSED_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct Telegram {
@@ -22,20 +25,31 @@ pub struct Telegram {
 /// Secrets section.
 #[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct Secrets {
-    token: String,
+    token: Secret<String>,
 }
 
 impl Telegram {
     pub fn spawn(self, service_id: String, bus: &mut Bus) -> Result {
         let tx = bus.add_tx();
+        let lifetime = Lifetime::current(&service_id);
 
         thread::Builder::new().name(service_id.clone()).spawn(move || {
+            let _guard = lifetime.guard();
             let mut offset: Option<i64> = None;
-            loop {
+            while lifetime.is_alive() {
+                if lifetime.is_paused() {
+                    sleep(PAUSED_INTERVAL);
+                    continue;
+                }
+                let start_time = Instant::now();
                 match self.loop_(&service_id, offset, &tx) {
-                    Ok(new_offset) => offset = new_offset,
+                    Ok(new_offset) => {
+                        offset = new_offset;
+                        METRICS.record_service_iteration(&service_id, start_time.elapsed(), None);
+                    }
                     Err(error) => {
                         error!("Failed to refresh the sensors: {}", error.to_string());
+                        METRICS.record_service_iteration(&service_id, start_time.elapsed(), Some(error.to_string()));
                         sleep(Duration::from_secs(60));
                     }
                 }
@@ -98,7 +112,7 @@ impl Telegram {
 
         let url = format!(
             "https://api.telegram.org/bot{}/{}",
-            self.secrets.token,
+            self.secrets.token.as_str(),
             match call {
                 TelegramMethodCall::GetUpdates { .. } => "getUpdates",
                 TelegramMethodCall::SendMessage { .. } => "sendMessage",
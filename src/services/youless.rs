@@ -45,42 +45,49 @@ impl YouLess {
             .pop()
             .ok_or("YouLess response is empty")?;
         Message::new(format!("{}::nett", service_id))
+            .tag("energy")
             .value(Value::from_kwh(response.nett))
             .optional_location(self.location.clone())
             .sensor_title("Nett Counter")
             .timestamp(response.timestamp)
             .send_and_forget(tx);
         Message::new(format!("{}::power", service_id))
+            .tag("energy")
             .value(Value::Power(response.power))
             .optional_location(self.location.clone())
             .sensor_title("Actual Consumption")
             .timestamp(response.timestamp)
             .send_and_forget(tx);
         Message::new(format!("{}::consumption::low", service_id))
+            .tag("energy")
             .value(Value::from_kwh(response.consumption_low))
             .optional_location(self.location.clone())
             .sensor_title("Total Consumption Low")
             .timestamp(response.timestamp)
             .send_and_forget(tx);
         Message::new(format!("{}::consumption::high", service_id))
+            .tag("energy")
             .value(Value::from_kwh(response.consumption_high))
             .optional_location(self.location.clone())
             .sensor_title("Total Consumption High")
             .timestamp(response.timestamp)
             .send_and_forget(tx);
         Message::new(format!("{}::production::low", service_id))
+            .tag("energy")
             .value(Value::from_kwh(response.production_low))
             .optional_location(self.location.clone())
             .sensor_title("Total Production Low")
             .timestamp(response.timestamp)
             .send_and_forget(tx);
         Message::new(format!("{}::production::high", service_id))
+            .tag("energy")
             .value(Value::from_kwh(response.production_high))
             .optional_location(self.location.clone())
             .sensor_title("Total Production High")
             .timestamp(response.timestamp)
             .send_and_forget(tx);
         Message::new(format!("{}::gas", service_id))
+            .tag("energy")
             .value(Value::Volume(response.gas))
             .optional_location(self.location.clone())
             .sensor_title("Total Gas Consumption")
@@ -7,20 +7,29 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
+pub mod secret;
+
+pub use self::secret::Secret;
+
 /// Read the settings file.
 pub fn read<P: AsRef<Path> + std::fmt::Debug>(paths: Vec<P>) -> Result<Settings> {
-    Ok(toml::from_str(
+    Ok(parse(
         &paths
             .iter()
             .map(|path| -> Result<String> {
                 info!("Reading {:?}…", path);
                 Ok(fs::read_to_string(path)?)
             })
-            .collect::<Result<Vec<String>>>()?
-            .join("\n\n"),
+            .collect::<Result<Vec<String>>>()?,
     )?)
 }
 
+/// Parses the settings which are split into several files.
+/// Line numbers in an error match the first file.
+pub fn parse(contents: &[String]) -> Result<Settings, toml::de::Error> {
+    toml::from_str(&contents.join("\n\n"))
+}
+
 /// Settings root.
 #[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct Settings {
@@ -28,10 +37,164 @@ pub struct Settings {
     #[serde(default = "default_http_port")]
     pub http_port: u16,
 
+    /// Addresses which the web server listens on, each of them on `http_port`.
+    #[serde(default = "default_http_addresses")]
+    pub http_addresses: Vec<String>,
+
+    /// Certificate and private key to serve HTTPS instead of HTTP.
+    #[serde(default)]
+    pub tls: Option<Tls>,
+
+    /// URL prefix to serve the web interface under, for example: `/iot` behind a reverse proxy.
+    #[serde(default)]
+    pub base_path: String,
+
+    /// Maximum number of points in a sensor chart. Longer series get downsampled.
+    #[serde(default = "default_max_chart_points")]
+    pub max_chart_points: usize,
+
     /// Services configuration.
     /// Each entry is a pair of service ID (defined by user) and service settings.
     /// Service ID is normally used as a sensor prefix, for instance: `service_id::service_sensor`.
     pub services: HashMap<String, Service>,
+
+    /// Tokens which allow devices to push messages via the web server.
+    /// Each entry is a pair of token name (defined by user) and token settings.
+    #[serde(default)]
+    pub tokens: HashMap<String, Token>,
+
+    /// Web interface users. Each entry is a pair of user name and user settings.
+    /// The web interface is open to anyone if there are no users.
+    #[serde(default)]
+    pub users: HashMap<String, User>,
+
+    /// Web interface dashboards. Each entry is a pair of dashboard slug and dashboard settings.
+    /// More dashboards may be added in the web interface.
+    #[serde(default)]
+    pub dashboards: HashMap<String, Dashboard>,
+
+    /// Expected update intervals of the sensors. The first matching entry applies.
+    /// Sensors which miss their interval are marked stale.
+    #[serde(default)]
+    pub expected_updates: Vec<ExpectedUpdate>,
+
+    /// User-defined sensor tags. The first matching entry replaces the tags set by the service.
+    #[serde(default)]
+    pub sensor_tags: Vec<SensorTags>,
+
+    /// IDs of the services which must be running for `/ready` to report the readiness.
+    #[serde(default)]
+    pub critical_services: Vec<String>,
+
+    /// [Time zone](https://en.wikipedia.org/wiki/List_of_tz_database_time_zones) to display the timestamps in,
+    /// for example: `Europe/Amsterdam`. Defaults to the system time zone.
+    #[serde(default)]
+    pub timezone: Option<chrono_tz::Tz>,
+
+    /// How the timestamps are displayed.
+    #[serde(default)]
+    pub datetime_format: DateTimeFormat,
+
+    /// Cumulative energy meters to show on the energy page.
+    #[serde(default)]
+    pub energy: Energy,
+}
+
+/// Sensor ID [glob patterns](https://www.sqlite.org/lang_corefunc.html#glob) of the cumulative energy meters.
+/// The increments of all the matching sensors add up, for example, the low and high tariffs.
+#[derive(Deserialize, Debug, Clone, Serialize)]
+pub struct Energy {
+    #[serde(default = "default_energy_consumption")]
+    pub consumption: Vec<String>,
+
+    #[serde(default = "default_energy_production")]
+    pub production: Vec<String>,
+}
+
+impl Default for Energy {
+    fn default() -> Self {
+        Energy {
+            consumption: default_energy_consumption(),
+            production: default_energy_production(),
+        }
+    }
+}
+
+/// Clock and date order of the displayed timestamps.
+#[derive(Deserialize, Debug, Clone, Copy, Serialize, Default, PartialEq)]
+pub struct DateTimeFormat {
+    #[serde(default)]
+    pub clock: Clock,
+
+    #[serde(default)]
+    pub date_order: DateOrder,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Serialize, PartialEq)]
+pub enum Clock {
+    /// For example: `14:30`.
+    #[serde(rename = "24h")]
+    H24,
+
+    /// For example: `2:30 PM`.
+    #[serde(rename = "12h")]
+    H12,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock::H24
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Serialize, PartialEq)]
+pub enum DateOrder {
+    /// For example: `19 Oct`.
+    #[serde(rename = "dmy")]
+    DayMonthYear,
+
+    /// For example: `Oct 19`.
+    #[serde(rename = "mdy")]
+    MonthDayYear,
+
+    /// For example: `2020-10-19`.
+    #[serde(rename = "ymd")]
+    YearMonthDay,
+}
+
+impl Default for DateOrder {
+    fn default() -> Self {
+        DateOrder::MonthDayYear
+    }
+}
+
+/// Tags of the sensors.
+#[derive(Deserialize, Debug, Clone, Serialize)]
+pub struct SensorTags {
+    /// Sensor ID glob pattern, for example: `tado::*::humidity`.
+    pub pattern: String,
+
+    pub tags: Vec<String>,
+}
+
+/// Expected update interval of the sensors.
+#[derive(Deserialize, Debug, Clone, Serialize)]
+pub struct ExpectedUpdate {
+    /// Sensor ID [glob pattern](https://docs.rs/glob/0.3.0/glob/struct.Pattern.html),
+    /// for example: `buienradar::*` to cover all the sensors of the `buienradar` service.
+    pub pattern: String,
+
+    pub interval_minutes: i64,
+}
+
+/// TLS settings section.
+#[derive(Deserialize, Debug, Clone, Serialize, PartialEq)]
+pub struct Tls {
+    /// Path to the certificate chain in PEM format.
+    pub certificate_path: String,
+
+    /// Path to the private key in PEM format.
+    pub private_key_path: String,
 }
 
 /// Service settings section.
@@ -66,6 +229,171 @@ pub enum Service {
     YouLess(services::youless::YouLess),
 }
 
+/// Token which allows a client to push messages.
+#[derive(Deserialize, Debug, Clone, Serialize)]
+pub struct Token {
+    pub secrets: TokenSecrets,
+
+    /// If set, the token only allows sensor IDs starting with the prefix.
+    #[serde(default)]
+    pub sensor_prefix: Option<String>,
+
+    /// Allowed message types.
+    #[serde(default = "default_token_types")]
+    pub types: Vec<MessageType>,
+}
+
+#[derive(Deserialize, Debug, Clone, Serialize)]
+pub struct TokenSecrets {
+    /// Token hash, produced by `my-iot hash-token`.
+    pub token_hash: Secret<String>,
+}
+
+/// Web interface user.
+#[derive(Deserialize, Debug, Clone, Serialize)]
+pub struct User {
+    pub role: Role,
+    pub secrets: UserSecrets,
+}
+
+#[derive(Deserialize, Debug, Clone, Serialize)]
+pub struct UserSecrets {
+    /// Password hash, produced by `my-iot hash-password`.
+    pub password_hash: Secret<String>,
+}
+
+/// User role. Each role is also allowed to do whatever the previous ones are allowed to.
+#[derive(Deserialize, Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// Views the sensors.
+    Viewer,
+
+    /// Also sends `Write` commands to the services.
+    Operator,
+
+    /// Also views the settings and deletes the sensors.
+    Admin,
+}
+
+/// Chosen sensor tiles, available at `/dashboards/<slug>`.
+#[derive(Deserialize, Debug, Clone, Serialize)]
+pub struct Dashboard {
+    pub title: String,
+
+    /// Whether the dashboard is shown on the home page instead of all the sensors.
+    #[serde(default)]
+    pub is_home: bool,
+
+    #[serde(default)]
+    pub sections: Vec<DashboardSection>,
+}
+
+#[derive(Deserialize, Debug, Clone, Serialize)]
+pub struct DashboardSection {
+    /// Optional section heading.
+    #[serde(default)]
+    pub title: Option<String>,
+
+    #[serde(default)]
+    pub tiles: Vec<DashboardTile>,
+
+    /// Adds a tile for each sensor with the tag, after the listed tiles.
+    #[serde(default)]
+    pub tag: Option<String>,
+
+    /// Charts below the tiles.
+    #[serde(default)]
+    pub charts: Vec<Chart>,
+}
+
+#[derive(Deserialize, Debug, Clone, Serialize)]
+pub struct DashboardTile {
+    pub sensor_id: String,
+
+    /// Tile width, by default it depends on the sensor value.
+    #[serde(default)]
+    pub size: Option<TileSize>,
+
+    /// If set, the tile embeds a mini-chart of the specified period.
+    #[serde(default)]
+    pub chart_minutes: Option<i64>,
+}
+
+/// Chart which overlays several sensors, also available at `/chart`.
+#[derive(Deserialize, Debug, Clone, Serialize)]
+pub struct Chart {
+    pub sensor_ids: Vec<String>,
+
+    /// Chart period.
+    #[serde(default = "default_chart_minutes")]
+    pub minutes: i64,
+
+    /// If set, the chart also shows the same sensors as they were the specified number of minutes earlier.
+    /// For example, `1440` compares today with yesterday.
+    #[serde(default)]
+    pub compare_minutes: Option<i64>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Serialize, PartialEq)]
+pub enum TileSize {
+    Small,
+    Medium,
+    Large,
+    Wide,
+}
+
+impl Token {
+    /// Checks whether the token allows sending the message.
+    pub fn allows(&self, message: &Message) -> bool {
+        self.types.contains(&message.type_)
+            && self
+                .sensor_prefix
+                .as_ref()
+                .map_or(true, |prefix| message.sensor.id.starts_with(prefix))
+    }
+}
+
+impl Service {
+    /// Returns the service type name as it's specified in the settings.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Service::Buienradar(_) => "Buienradar",
+            Service::Clock(_) => "Clock",
+            Service::OpenWeather(_) => "OpenWeather",
+            Service::Rhai(_) => "Rhai",
+            Service::Ring(_) => "Ring",
+            Service::Solar(_) => "Solar",
+            Service::Tado(_) => "Tado",
+            Service::Telegram(_) => "Telegram",
+            Service::YouLess(_) => "YouLess",
+        }
+    }
+}
+
 pub fn default_http_port() -> u16 {
     8081
 }
+
+pub fn default_http_addresses() -> Vec<String> {
+    vec!["0.0.0.0".into()]
+}
+
+pub fn default_max_chart_points() -> usize {
+    1000
+}
+
+pub fn default_chart_minutes() -> i64 {
+    60
+}
+
+fn default_energy_consumption() -> Vec<String> {
+    vec!["*::consumption::*".into()]
+}
+
+fn default_energy_production() -> Vec<String> {
+    vec!["*::production::*".into()]
+}
+
+fn default_token_types() -> Vec<MessageType> {
+    vec![MessageType::ReadLogged, MessageType::ReadNonLogged]
+}
@@ -0,0 +1,148 @@
+//! Secret settings values. They're never displayed nor logged.
+
+use std::cell::Cell;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use serde::de::Error as _;
+use serde::{Deserializer, Serializer};
+
+use crate::prelude::*;
+
+/// Displayed instead of a secret value.
+pub const REDACTED: &str = "<redacted>";
+
+thread_local! {
+    static IS_REVEALED: Cell<bool> = Cell::new(false);
+}
+
+/// Serializes the actual secret values within the closure, for example, to compare the settings.
+pub fn reveal<R, F: FnOnce() -> R>(f: F) -> R {
+    IS_REVEALED.with(|is_revealed| {
+        let was_revealed = is_revealed.replace(true);
+        let result = f();
+        is_revealed.set(was_revealed);
+        result
+    })
+}
+
+/// Secret value. It's serialized and formatted as a redacted placeholder.
+///
+/// In TOML, it's either the value itself, or a reference to an environment variable or a file:
+///
+/// ```toml
+/// token = { env = "TELEGRAM_TOKEN" }
+/// password = { file = "/run/secrets/tado_password" }
+/// ```
+#[derive(Clone, PartialEq)]
+pub struct Secret<T>(T);
+
+/// Secret as it's specified in the settings.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SecretSource<T> {
+    Env { env: String },
+    File { file: String },
+    Value(T),
+}
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T> Deref for Secret<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T: Serialize> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if IS_REVEALED.with(Cell::get) {
+            self.0.serialize(serializer)
+        } else {
+            serializer.serialize_str(REDACTED)
+        }
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Secret<T>
+where
+    T: Deserialize<'de> + FromStr,
+    T::Err: fmt::Display,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = match SecretSource::<T>::deserialize(deserializer)? {
+            SecretSource::Value(value) => return Ok(Secret(value)),
+            SecretSource::Env { env } => std::env::var(&env)
+                .map_err(|error| D::Error::custom(format!("environment variable `{}`: {}", env, error)))?,
+            SecretSource::File { file } => std::fs::read_to_string(&file)
+                .map_err(|error| D::Error::custom(format!("secret file `{}`: {}", file, error)))?
+                .trim_end_matches(&['\r', '\n'][..])
+                .to_string(),
+        };
+        text.parse().map(Secret).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize, Serialize, Debug)]
+    struct Secrets {
+        token: Secret<String>,
+        latitude: Secret<f64>,
+    }
+
+    #[test]
+    fn value_ok() -> Result {
+        let secrets: Secrets = toml::from_str("token = \"abc\"\nlatitude = 52.0")?;
+        assert_eq!(*secrets.token, "abc");
+        assert_eq!(*secrets.latitude, 52.0);
+        Ok(())
+    }
+
+    #[test]
+    fn env_ok() -> Result {
+        std::env::set_var("MY_IOT_TEST_SECRET", "from-env");
+        let secrets: Secrets = toml::from_str("token = { env = \"MY_IOT_TEST_SECRET\" }\nlatitude = 1.0")?;
+        assert_eq!(*secrets.token, "from-env");
+        Ok(())
+    }
+
+    #[test]
+    fn file_ok() -> Result {
+        let path = std::env::temp_dir().join("my-iot-test-secret");
+        std::fs::write(&path, "4.5\n")?;
+        let secrets: Secrets = toml::from_str(&format!(
+            "token = \"abc\"\nlatitude = {{ file = {:?} }}",
+            path.display().to_string(),
+        ))?;
+        assert_eq!(*secrets.latitude, 4.5);
+        Ok(())
+    }
+
+    #[test]
+    fn redacted_ok() -> Result {
+        let secrets = Secrets {
+            token: Secret::new("abc".into()),
+            latitude: Secret::new(52.0),
+        };
+        assert!(!format!("{:?}", secrets).contains("abc"));
+        assert_eq!(serde_json::to_value(&secrets)?["token"], REDACTED);
+        assert_eq!(reveal(|| serde_json::to_value(&secrets))?["token"], "abc");
+        Ok(())
+    }
+}
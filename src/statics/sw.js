@@ -1 +1,106 @@
-'use strict';
+// `VERSION` and `ASSETS` are prepended by the server, so that a new release replaces the caches.
+const STATIC_CACHE = `my-iot-static-${VERSION}`;
+const PAGE_CACHE = `my-iot-pages-${VERSION}`;
+
+// The scope is the web server base path.
+const BASE_PATH = new URL(self.registration.scope).pathname.replace(/\/$/, '');
+
+// `ASSETS` are the content-hashed URLs of the embedded assets.
+const STATIC_ASSETS = [...ASSETS, `${BASE_PATH}/favicon.ico`, `${BASE_PATH}/my-iot.webmanifest`];
+
+// The pages which are kept for offline use: the home dashboard, the other dashboards and sensors.
+const PAGE_PATTERN = /^\/(dashboards\/(?!edit$)[^/]+|sensors\/[^/]+)?$/;
+
+// Header which stores the time when a page has been cached.
+const CACHED_AT_HEADER = 'X-My-IoT-Cached-At';
+
+self.addEventListener('install', event => {
+  event.waitUntil(
+    caches.open(STATIC_CACHE)
+      .then(cache => cache.addAll(STATIC_ASSETS))
+      .then(() => self.skipWaiting())
+  );
+});
+
+self.addEventListener('activate', event => {
+  event.waitUntil(
+    caches.keys()
+      .then(keys => Promise.all(
+        keys
+          .filter(key => key.startsWith('my-iot-') && key !== STATIC_CACHE && key !== PAGE_CACHE)
+          .map(key => caches.delete(key))
+      ))
+      .then(() => self.clients.claim())
+  );
+});
+
+self.addEventListener('fetch', event => {
+  const request = event.request;
+  if (request.method !== 'GET') {
+    return;
+  }
+  const url = new URL(request.url);
+  if (url.origin !== self.location.origin) {
+    return;
+  }
+  if (STATIC_ASSETS.includes(url.pathname) || url.pathname.startsWith(`${BASE_PATH}/static/`)) {
+    event.respondWith(fetchAsset(request));
+  } else if (
+    request.mode === 'navigate'
+    && url.pathname.startsWith(BASE_PATH)
+    && PAGE_PATTERN.test(url.pathname.slice(BASE_PATH.length))
+  ) {
+    event.respondWith(fetchPage(request));
+  }
+});
+
+// Serves the asset from the cache. The plain URLs, such as the fonts referenced by the stylesheets, get cached on the first use.
+async function fetchAsset(request) {
+  const cache = await caches.open(STATIC_CACHE);
+  const cached = await cache.match(request);
+  if (cached) {
+    return cached;
+  }
+  const response = await fetch(request);
+  if (response.ok) {
+    await cache.put(request, response.clone());
+  }
+  return response;
+}
+
+// Fetches the page and remembers it, unless it belongs to a logged-in user. Falls back to the last rendered page when the server is unreachable.
+async function fetchPage(request) {
+  const cache = await caches.open(PAGE_CACHE);
+  try {
+    const response = await fetch(request);
+    // The server forbids storing the pages of the logged-in users.
+    const cacheControl = response.headers.get('Cache-Control') || '';
+    if (response.ok && !response.redirected && !cacheControl.includes('no-store')) {
+      const headers = new Headers(response.headers);
+      headers.set(CACHED_AT_HEADER, new Date().toISOString());
+      const body = await response.clone().blob();
+      await cache.put(request, new Response(body, {status: response.status, headers: headers}));
+    }
+    return response;
+  } catch (error) {
+    const cached = await cache.match(request) || await cache.match(request, {ignoreSearch: true});
+    if (!cached) {
+      throw error;
+    }
+    return withOfflineBanner(cached);
+  }
+}
+
+// Inserts the offline notification right after the opening `<body>` tag.
+async function withOfflineBanner(response) {
+  const cachedAt = new Date(response.headers.get(CACHED_AT_HEADER));
+  const banner = `
+    <div class="notification is-warning is-marginless has-text-centered" style="border-radius: 0">
+      <span class="icon"><i class="fas fa-plug"></i></span>
+      Offline, last updated at <strong>${cachedAt.toLocaleString()}</strong>
+    </div>`;
+  const html = (await response.text()).replace(/<body[^>]*>/, match => match + banner);
+  const headers = new Headers(response.headers);
+  headers.delete(CACHED_AT_HEADER);
+  return new Response(html, {status: 200, headers: headers});
+}
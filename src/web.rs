@@ -1,148 +1,396 @@
 //! Implements the web server.
 
 use std::io::Cursor;
+use std::path::PathBuf;
 use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
 
 use chrono::Duration;
-use itertools::Itertools;
 use rocket::config::Environment;
-use rocket::http::hyper::header::ETag;
+use rocket::http::hyper::header::{ETag, EntityTag};
+use rocket::http::uri::Uri;
 use rocket::http::ContentType;
 use rocket::http::Status;
+use rocket::request::LenientForm;
 use rocket::response::content::Content;
 use rocket::response::Redirect;
-use rocket::{delete, get, routes, uri, Config, Response, Rocket, State};
+use rocket::{catch, catchers, delete, get, routes, uri, Config, Request, Response, Rocket, State};
 use rocket_contrib::json::Json;
 
 use crate::prelude::*;
+use crate::services::Supervisor;
 use crate::settings::Settings;
-use crate::web::cached_content::Cached;
+use crate::web::assets::AssetResponse;
+use crate::web::auth::{Admin, Csrf, Empty, Viewer};
+use crate::web::base_path::BasePath;
+pub use crate::web::event_hub::EventHub;
+use crate::web::event_stream::EventStream;
 use crate::web::if_none_match::IfNoneMatch;
+use crate::web::last_event_id::LastEventId;
+use crate::web::layout::Layout;
+use crate::web::live_settings::{CurrentSettings, LiveSettings};
 use crate::web::message_counter::MessageCounter;
+use crate::web::revalidated::Revalidated;
+use crate::web::sensor_filter::SensorFilter;
+use crate::web::settings_editor::SettingsPaths;
 use crate::web::to_html_string::ToHtmlString;
 
-mod cached_content;
+mod api;
+mod assets;
+mod auth;
+mod base_path;
+mod bearer_token;
+mod charts;
+mod controls;
+mod dashboards;
+mod energy;
 mod entity_tag;
+mod event_hub;
+mod event_stream;
+mod health;
 mod if_none_match;
+mod ingest;
+mod last_event_id;
+mod layout;
+mod live_settings;
+mod logs;
+mod lttb;
 mod message_counter;
+mod metrics;
+mod readings;
+mod revalidated;
+mod sensor_filter;
+mod service_status;
+mod settings_editor;
 mod templates;
+mod tls;
 mod to_html_string;
+mod tokens;
 
-const STATIC_MAX_AGE_SECS: u32 = 3600;
+/// Maximum number of state changes in a timeline, they're downsampled evenly across the period.
+const MAX_TIMELINE_CHANGES: u32 = 1000;
 
-/// Start the web application.
-pub fn start_server(settings: &Settings, db: Connection, message_counter: Arc<AtomicU64>) -> Result {
-    Err(Box::new(make_rocket(settings, db, message_counter)?.launch()))
-}
+/// Each open event stream occupies a worker thread, thus there should be plenty of them.
+/// The streams take at most a half, see `event_hub::MAX_SUBSCRIPTIONS`.
+const WORKERS: u16 = 32;
 
-/// Builds the [Rocket](https://rocket.rs/) application.
-fn make_rocket(settings: &Settings, db: Connection, message_counter: Arc<AtomicU64>) -> Result<Rocket> {
-    Ok(rocket::custom(
-        Config::build(Environment::Production)
-            .port(settings.http_port)
-            .keep_alive(600)
-            .finalize()?,
-    )
-    .manage(db)
-    .manage(settings.clone())
-    .manage(MessageCounter(message_counter))
-    .mount(
-        "/",
-        routes![
-            get_index,
-            get_settings,
-            get_sensor,
-            delete_sensor,
-            get_sensor_json,
-            get_favicon,
-            get_favicon_16,
-            get_favicon_32,
-            get_apple_touch_icon,
-            get_android_chrome_192,
-            get_android_chrome_512,
-            get_bulma_css,
-            get_bulma_prefers_dark,
-            get_chart_js,
-            get_font_awesome,
-            get_webfonts_fa_solid_900,
-            get_webfonts_fa_regular_400,
-            get_webfonts_fa_brands_400,
-            get_sw_js,
-            get_webmanifest,
-        ],
+/// Start the web application. Each additional address is served in a separate thread.
+pub fn start_server(
+    settings: &Settings,
+    settings_paths: Vec<PathBuf>,
+    db: Connection,
+    message_counter: Arc<AtomicU64>,
+    tx: Sender,
+    event_hub: EventHub,
+    supervisor: Supervisor,
+) -> Result {
+    if settings.http_addresses.is_empty() {
+        return Err("at least one of `http_addresses` must be specified".into());
+    }
+    let addresses = match &settings.tls {
+        // The TLS connections are forwarded to the web server on the loopback interface.
+        Some(tls_settings) => {
+            let config = tls::make_config(tls_settings)?;
+            let upstream = tls::unused_loopback_address()?;
+            for address in &settings.http_addresses {
+                tls::spawn(address, settings.http_port, upstream, config.clone())?;
+            }
+            vec![(upstream.ip().to_string(), upstream.port())]
+        }
+        None => settings
+            .http_addresses
+            .iter()
+            .map(|address| (address.clone(), settings.http_port))
+            .collect(),
+    };
+    let ((address, port), other_addresses) = addresses.split_first().unwrap();
+    let live_settings = LiveSettings::new(settings.clone());
+    for (address, port) in other_addresses {
+        let rocket = make_rocket(
+            address,
+            *port,
+            live_settings.clone(),
+            settings_paths.clone(),
+            db.clone(),
+            message_counter.clone(),
+            tx.clone(),
+            event_hub.clone(),
+            supervisor.clone(),
+        )?;
+        thread::Builder::new()
+            .name(format!("web::{}", address))
+            .spawn(move || error!("Web server has stopped: {}.", rocket.launch()))?;
+    }
+    Err(Box::new(
+        make_rocket(
+            address,
+            *port,
+            live_settings,
+            settings_paths,
+            db,
+            message_counter,
+            tx,
+            event_hub,
+            supervisor,
+        )?
+        .launch(),
     ))
 }
 
-#[get("/")]
-fn get_index(db: State<Connection>, message_counter: State<MessageCounter>) -> Result<ToHtmlString<impl ToString>> {
-    let actuals = db
-        .select_actuals()?
-        .into_iter()
-        .group_by(|(sensor, _)| sensor.location.clone())
+/// Builds the [Rocket](https://rocket.rs/) application listening on the address.
+#[allow(clippy::too_many_arguments)]
+fn make_rocket(
+    address: &str,
+    port: u16,
+    live_settings: LiveSettings,
+    settings_paths: Vec<PathBuf>,
+    db: Connection,
+    message_counter: Arc<AtomicU64>,
+    tx: Sender,
+    event_hub: EventHub,
+    supervisor: Supervisor,
+) -> Result<Rocket> {
+    let settings = live_settings.get();
+    let base_path = BasePath::new(&settings.base_path);
+    let config = Config::build(Environment::Production)
+        .address(address)
+        .port(port)
+        .keep_alive(600)
+        .workers(WORKERS)
+        .finalize()?;
+    Ok(rocket::custom(config)
+        .manage(db)
+        .manage(live_settings)
+        .manage(MessageCounter(message_counter))
+        .manage(tx)
+        .manage(event_hub)
+        .manage(SettingsPaths(settings_paths))
+        .manage(supervisor)
+        .manage(base_path.clone())
+        .attach(auth::no_store_for_users())
+        .mount(
+            if base_path.as_str().is_empty() {
+                "/"
+            } else {
+                base_path.as_str()
+            },
+            routes![
+                get_index,
+                get_all_sensors,
+                settings_editor::get_settings,
+                settings_editor::post_settings,
+                get_sensor,
+                delete_sensor,
+                controls::post_write,
+                get_sensor_json,
+                get_events,
+                get_favicon,
+                assets::get_static,
+                get_sw_js,
+                get_webmanifest,
+                health::get_health,
+                health::get_ready,
+                ingest::get_ingest,
+                ingest::post_ingest,
+                auth::get_login,
+                auth::post_login,
+                auth::post_logout,
+                tokens::get_tokens,
+                tokens::post_token,
+                tokens::delete_token,
+                charts::get_chart,
+                dashboards::get_dashboard,
+                dashboards::get_dashboard_editor,
+                dashboards::post_dashboard,
+                dashboards::delete_dashboard,
+                energy::get_energy,
+                metrics::get_metrics,
+                readings::get_readings_export,
+                readings::delete_reading,
+                logs::get_logs,
+                logs::get_logs_json,
+                service_status::get_services,
+                service_status::post_restart,
+                service_status::post_pause,
+                service_status::post_resume,
+            ],
+        )
+        .mount(
+            &base_path.prefixed("/api/v1"),
+            routes![
+                api::get_sensors,
+                api::get_sensor,
+                api::get_sensor_readings,
+                api::get_stats,
+                service_status::get_services_json,
+                ingest::post_messages,
+            ],
+        )
+        .register(catchers![unauthorized]))
+}
+
+/// Redirects to the login page, unless it's an API call.
+#[catch(401)]
+fn unauthorized(request: &Request) -> Response<'static> {
+    let base_path = request.guard::<BasePath>().succeeded().unwrap_or_default();
+    let path = base_path.strip(request.uri().path());
+    if path.starts_with("/api/") || path == uri!(get_events: _).path() || path == uri!(metrics::get_metrics).path() {
+        Response::build().status(Status::Unauthorized).finalize()
+    } else {
+        Response::build()
+            .status(Status::SeeOther)
+            .raw_header(
+                "Location",
+                format!(
+                    "{}?next={}",
+                    base_path.prefixed(uri!(auth::get_login: _, _)),
+                    Uri::percent_encode(&request.uri().to_string()),
+                ),
+            )
+            .finalize()
+    }
+}
+
+/// Shows the home dashboard, or all the sensors if there's none.
+#[get("/?<filter..>")]
+fn get_index(
+    _user: Viewer,
+    layout: Layout,
+    settings: CurrentSettings,
+    db: State<Connection>,
+    filter: LenientForm<SensorFilter>,
+) -> Result<ToHtmlString<String>> {
+    match dashboards::select_dashboards(&settings, &db)?
         .into_iter()
-        .map(|(location, group)| (location, group.collect_vec()))
-        .collect_vec();
-    Ok(ToHtmlString(templates::IndexTemplate {
-        actuals,
-        message_count: message_counter.inner().value(),
-    }))
+        .find(|dashboard| dashboard.dashboard.is_home)
+    {
+        Some(dashboard) => Ok(ToHtmlString(
+            dashboards::render(layout, &db, dashboard, settings.max_chart_points)?.to_string(),
+        )),
+        None => Ok(ToHtmlString(
+            render_all_sensors(layout, &db, filter.into_inner())?.to_string(),
+        )),
+    }
 }
 
-#[get("/settings")]
-fn get_settings(
-    settings: State<Settings>,
-    message_counter: State<MessageCounter>,
+#[get("/sensors?<filter..>")]
+fn get_all_sensors(
+    _user: Viewer,
+    layout: Layout,
+    db: State<Connection>,
+    filter: LenientForm<SensorFilter>,
 ) -> Result<ToHtmlString<impl ToString>> {
-    Ok(ToHtmlString(templates::SettingsTemplate {
-        settings: toml::to_string_pretty(&toml::Value::try_from(settings.inner())?)?,
-        message_count: message_counter.inner().value(),
-    }))
+    Ok(ToHtmlString(render_all_sensors(layout, &db, filter.into_inner())?))
 }
 
-#[get("/sensors/<sensor_id>?<minutes>")]
+/// Renders the matching sensors grouped by location or tag.
+fn render_all_sensors(layout: Layout, db: &Connection, filter: SensorFilter) -> Result<templates::IndexTemplate> {
+    Ok(templates::IndexTemplate {
+        actuals: filter.group(filter.select(db)?),
+        is_grouped: filter.is_grouped(),
+        value_types: db.select_value_types()?,
+        tags: db.select_tags()?,
+        filter,
+        layout,
+    })
+}
+
+#[get("/sensors/<sensor_id>?<minutes>&<page>&<range..>")]
+#[allow(clippy::too_many_arguments)]
 fn get_sensor<'r>(
+    _user: Viewer,
+    layout: Layout,
+    settings: CurrentSettings,
     db: State<Connection>,
-    message_counter: State<MessageCounter>,
+    event_hub: State<EventHub>,
     if_none_match: Option<IfNoneMatch>,
     sensor_id: String,
     minutes: Option<i64>,
+    page: Option<u32>,
+    range: LenientForm<readings::ReadingsRange>,
 ) -> Result<Response<'r>> {
     if let Some((sensor, reading)) = db.select_sensor(&sensor_id)? {
-        if let Some(IfNoneMatch(entity_tag)) = if_none_match {
-            if reading.entity_tag().weak_eq(&entity_tag) {
+        // The table changes when a reading gets deleted, thus the count is a part of the tag.
+        let reading_count = db.select_sensor_reading_count(&sensor_id)?;
+        let entity_tag = EntityTag::new(
+            true,
+            format!("{:x}-{:x}", reading_count, reading.timestamp.timestamp_millis()),
+        );
+        if let Some(IfNoneMatch(if_none_match)) = if_none_match {
+            if entity_tag.weak_eq(&if_none_match) {
                 // If there's a match, we can avoid spending CPU on generation of the chart.
                 return Response::build().status(Status::NotModified).ok();
             }
         }
 
         let minutes = minutes.unwrap_or(60);
-        let chart = if reading.value.is_f64() {
-            templates::F64ChartPartialTemplate::new(
+        let page = page.unwrap_or(0);
+        let until = Local::now();
+        let since = until - Duration::minutes(minutes);
+        let history_view = reading.value.history_view();
+        let history = match history_view {
+            templates::HistoryView::Chart => templates::F64ChartPartialTemplate::new(
                 &sensor.title(),
-                db.select_values(&sensor_id, &(Local::now() - Duration::minutes(minutes)))?,
-                if let Value::Energy(_) = reading.value {
-                    WH_IN_JOULE
-                } else {
-                    1.0
-                },
+                db.select_values(&sensor_id, &since)?,
+                reading.value.chart_multiplier(),
+                settings.max_chart_points,
             )
-            .to_string()
-        } else {
-            "".into()
+            .to_string(),
+            templates::HistoryView::Timeline => templates::TimelinePartialTemplate::new(
+                &db.select_state_changes(&sensor_id, &since, &until, MAX_TIMELINE_CHANGES)?,
+                &db.select_state_durations(&sensor_id, &since, &until)?,
+                since,
+                until,
+            )
+            .to_string(),
+            templates::HistoryView::WindRose => {
+                templates::WindRosePartialTemplate::new(&db.select_value_counts(&sensor_id, &since, &until)?)
+                    .to_string()
+            }
+            templates::HistoryView::EventLog => {
+                let (readings, has_next_page) = readings::select_page(
+                    &db,
+                    &event_hub,
+                    &sensor_id,
+                    reading_count,
+                    &readings::ReadingsRange::default(),
+                    page,
+                )?;
+                templates::EventLogPartialTemplate {
+                    readings,
+                    page,
+                    has_next_page,
+                }
+                .to_string()
+            }
         };
+        let readings_table = readings::render_table(
+            &layout,
+            &db,
+            &event_hub,
+            &sensor_id,
+            reading_count,
+            &range,
+            minutes,
+            page,
+        )?;
+
+        let control = templates::render_control(&layout.base_path, &sensor, &reading);
 
         Response::build()
             .header(ContentType::HTML)
-            .header(ETag(reading.entity_tag()))
+            .header(ETag(entity_tag))
             .sized_body(Cursor::new(
                 templates::SensorTemplate {
                     sensor,
                     reading,
-                    chart,
+                    history,
+                    history_view,
+                    readings_table,
+                    control,
                     minutes,
-                    reading_count: db.select_sensor_reading_count(&sensor_id)?,
-                    message_count: message_counter.inner().value(),
+                    reading_count,
+                    layout,
                 }
                 .to_string(),
             ))
@@ -152,152 +400,100 @@ fn get_sensor<'r>(
     }
 }
 
-#[delete("/sensors/<sensor_id>")]
-fn delete_sensor(db: State<Connection>, sensor_id: String) -> Result<Redirect> {
+#[delete("/sensors/<sensor_id>", data = "<_form>")]
+fn delete_sensor(
+    _user: Admin,
+    base_path: BasePath,
+    db: State<Connection>,
+    sensor_id: String,
+    _form: Csrf<Empty>,
+) -> Result<Redirect> {
     db.delete_sensor(&sensor_id)?;
-    Ok(Redirect::to(uri!(get_index)))
+    Ok(Redirect::to(base_path.prefixed(uri!(get_index: _))))
 }
 
 #[get("/sensors/<sensor_id>/json")]
-fn get_sensor_json(db: State<Connection>, sensor_id: String) -> Result<Option<Json<Reading>>> {
-    // TODO: ETag
-    // TODO: Cache-Control: private, no-cache
-    Ok(db.select_sensor(&sensor_id)?.map(|(_, reading)| Json(reading)))
-}
-
-#[get("/favicon.ico")]
-fn get_favicon() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(ContentType::Icon, include_bytes!("statics/favicon.ico")),
-    )
-}
-
-#[get("/static/favicon-16x16.png")]
-fn get_favicon_16() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(ContentType::PNG, include_bytes!("statics/favicon-16x16.png")),
-    )
-}
-
-#[get("/static/favicon-32x32.png")]
-fn get_favicon_32() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(ContentType::PNG, include_bytes!("statics/favicon-32x32.png")),
-    )
-}
-
-#[get("/static/apple-touch-icon.png")]
-fn get_apple_touch_icon() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(ContentType::PNG, include_bytes!("statics/apple-touch-icon.png")),
-    )
-}
-
-#[get("/static/android-chrome-192x192.png")]
-fn get_android_chrome_192() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(ContentType::PNG, include_bytes!("statics/android-chrome-192x192.png")),
-    )
-}
-
-#[get("/static/android-chrome-512x512.png")]
-fn get_android_chrome_512() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(ContentType::PNG, include_bytes!("statics/android-chrome-512x512.png")),
-    )
-}
-
-#[get("/static/bulma.min.css")]
-fn get_bulma_css() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(ContentType::CSS, include_bytes!("statics/bulma.min.css")),
-    )
-}
-
-#[get("/static/bulma-prefers-dark.css")]
-fn get_bulma_prefers_dark() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(ContentType::CSS, include_bytes!("statics/bulma-prefers-dark.css")),
-    )
-}
-
-#[get("/static/Chart.bundle.min.js")]
-fn get_chart_js() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(ContentType::JavaScript, include_bytes!("statics/Chart.bundle.min.js")),
-    )
-}
-
-#[get("/static/fontawesome.css")]
-fn get_font_awesome() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(
-            ContentType::CSS,
-            include_bytes!("statics/fontawesome-free-5.13.1-web/css/all.css"),
-        ),
-    )
+fn get_sensor_json(
+    _user: Viewer,
+    db: State<Connection>,
+    sensor_id: String,
+) -> Result<Option<Revalidated<Json<Reading>>>> {
+    Ok(db
+        .select_sensor(&sensor_id)?
+        .map(|(_, reading)| Revalidated(reading.entity_tag(), Json(reading))))
 }
 
-#[get("/webfonts/fa-solid-900.woff2")]
-fn get_webfonts_fa_solid_900() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(
-            ContentType::WOFF2,
-            include_bytes!("statics/fontawesome-free-5.13.1-web/webfonts/fa-solid-900.woff2"),
-        ),
-    )
+/// Streams the new readings, optionally only for the comma-separated sensor IDs.
+/// Responds with `503 Service Unavailable` when too many streams are open.
+#[get("/events?<sensor_ids>")]
+fn get_events(
+    _user: Viewer,
+    base_path: BasePath,
+    event_hub: State<EventHub>,
+    last_event_id: Option<LastEventId>,
+    sensor_ids: Option<String>,
+) -> Result<EventStream, Status> {
+    let subscription = event_hub
+        .subscribe(last_event_id.map(|LastEventId(last_event_id)| last_event_id))
+        .ok_or(Status::ServiceUnavailable)?;
+    Ok(EventStream::new(
+        subscription,
+        sensor_ids.map_or_else(Vec::new, |sensor_ids| {
+            sensor_ids.split(',').map(ToString::to_string).collect()
+        }),
+        base_path,
+    ))
 }
 
-#[get("/webfonts/fa-regular-400.woff2")]
-fn get_webfonts_fa_regular_400() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(
-            ContentType::WOFF2,
-            include_bytes!("statics/fontawesome-free-5.13.1-web/webfonts/fa-regular-400.woff2"),
-        ),
-    )
+#[get("/favicon.ico")]
+fn get_favicon() -> Option<AssetResponse> {
+    assets::get("favicon.ico")
 }
 
-#[get("/webfonts/fa-brands-400.woff2")]
-fn get_webfonts_fa_brands_400() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(
-            ContentType::WOFF2,
-            include_bytes!("statics/fontawesome-free-5.13.1-web/webfonts/fa-brands-400.woff2"),
-        ),
-    )
+/// Serves the service worker. It's not cached, so that the browser picks up a new version right away.
+#[get("/sw.js")]
+fn get_sw_js(base_path: BasePath) -> Result<Content<String>> {
+    Ok(Content(ContentType::JavaScript, sw_js(&base_path)?))
 }
 
-#[get("/sw.js")]
-fn get_sw_js() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(ContentType::JavaScript, include_bytes!("statics/sw.js")),
-    )
+/// Prepends the version and the asset URLs to the service worker script,
+/// so that it invalidates the caches on an upgrade and precaches the assets.
+fn sw_js(base_path: &BasePath) -> Result<String> {
+    let urls: Vec<String> = assets::urls().iter().map(|url| base_path.prefixed(url)).collect();
+    Ok(format!(
+        "const VERSION = '{}';\nconst ASSETS = {};\n{}",
+        crate_version!(),
+        serde_json::to_string(&urls)?,
+        include_str!("statics/sw.js")
+    ))
 }
 
+/// Serves the manifest from the root, so that its relative URLs resolve against the base path.
 #[get("/my-iot.webmanifest")]
-fn get_webmanifest() -> Cached {
-    Cached(
-        STATIC_MAX_AGE_SECS,
-        Content(ContentType::JSON, include_bytes!("statics/my-iot.webmanifest")),
-    )
+fn get_webmanifest() -> Option<AssetResponse> {
+    assets::get("my-iot.webmanifest")
 }
 
 impl Value {
+    /// Returns the numeric value, if any. It's used to export the sensors to Prometheus.
+    pub fn to_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Counter(value) | Value::DataSize(value) => Some(value as f64),
+            Value::Duration(value)
+            | Value::Cloudiness(value)
+            | Value::Energy(value)
+            | Value::Length(value)
+            | Value::Power(value)
+            | Value::RelativeIntensity(value)
+            | Value::Rh(value)
+            | Value::Speed(value)
+            | Value::Temperature(value)
+            | Value::Volume(value)
+            | Value::BatteryLife(value) => Some(value),
+            _ => None,
+        }
+    }
+
     pub fn is_f64(&self) -> bool {
         match self {
             Value::Duration(_)
@@ -320,9 +516,12 @@ impl Value {
 
 #[cfg(test)]
 mod tests {
-    use rocket::http::Status;
+    use rocket::http::{Header, Status};
     use rocket::local::Client;
 
+    use crate::core::control::Control;
+    use crate::core::db::api_token::Scope;
+    use crate::core::logging::LOG_BUFFER;
     use crate::settings::*;
 
     use super::*;
@@ -336,6 +535,102 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sw_js_ok() -> Result {
+        let client = client()?;
+        let mut response = client.get("/sw.js").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JavaScript));
+        assert!(response
+            .body_string()
+            .unwrap()
+            .contains(&format!("const VERSION = '{}';", crate_version!())));
+        Ok(())
+    }
+
+    #[test]
+    fn settings_check_invalid() -> Result {
+        let path = temp_settings_file("[services]\n")?;
+        let (client, _) = client_with_settings_paths(vec![path.clone()])?;
+        let csrf_token = csrf_token(&client);
+        let mut response = client
+            .post("/settings")
+            .header(ContentType::Form)
+            .body(format!("_csrf={}&action=check&content=%5Bservices%0A", csrf_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.body_string().unwrap().contains("is-danger"));
+        assert_eq!(std::fs::read_to_string(&path)?, "[services]\n");
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn settings_save_ok() -> Result {
+        let path = temp_settings_file("[services]\n")?;
+        let (client, _) = client_with_settings_paths(vec![path.clone()])?;
+        let csrf_token = csrf_token(&client);
+        let mut response = client
+            .post("/settings")
+            .header(ContentType::Form)
+            .body(format!(
+                "_csrf={}&action=save&content=http_port+%3D+8082%0A%5Bservices%5D%0A",
+                csrf_token
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.body_string().unwrap().contains("after restarting My IoT"));
+        assert_eq!(std::fs::read_to_string(&path)?, "http_port = 8082\n[services]\n");
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn settings_save_applies_users() -> Result {
+        let path = temp_settings_file("")?;
+        let (client, _) = client_with_settings_paths(vec![path.clone()])?;
+        let csrf_token = csrf_token(&client);
+        let content = format!(
+            "[users.admin]\nrole = \"Admin\"\n[users.admin.secrets]\npassword_hash = \"{}\"\n",
+            crate::core::auth::hash_password("secret")?,
+        );
+        let response = client
+            .post("/settings")
+            .header(ContentType::Form)
+            .body(format!(
+                "_csrf={}&action=save&content={}",
+                csrf_token,
+                content.bytes().map(|byte| format!("%{:02X}", byte)).collect::<String>(),
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(client.get("/sensors").dispatch().status(), Status::SeeOther);
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn settings_secrets_redacted() -> Result {
+        let path = temp_settings_file("[tokens.esp.secrets]\ntoken_hash = \"plaintext-hash\"\n")?;
+        let (client, _) = client_with_settings_paths(vec![path.clone()])?;
+        let body = client.get("/settings").dispatch().body_string().unwrap();
+        assert!(!body.contains("plaintext-hash"));
+        assert!(body.contains("&lt;redacted&gt;"));
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn settings_inline_secrets_not_shown() -> Result {
+        let path = temp_settings_file("[tokens.esp]\nsecrets = { token_hash = \"plaintext-hash\" }\n")?;
+        let (client, _) = client_with_settings_paths(vec![path.clone()])?;
+        let body = client.get("/settings").dispatch().body_string().unwrap();
+        assert!(!body.contains("plaintext-hash"));
+        assert!(!body.contains("<textarea"));
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
     #[test]
     fn settings_ok() -> Result {
         let client = client()?;
@@ -345,6 +640,343 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn all_sensors_ok() -> Result {
+        let client = client()?;
+        let response = client.get("/sensors").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        Ok(())
+    }
+
+    #[test]
+    fn filtered_sensors_ok() -> Result {
+        let client = client()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        Message::new("test::visible")
+            .value(Value::Counter(1))
+            .upsert_into(&*db.connection()?)?;
+        Message::new("other::hidden")
+            .value(Value::Counter(2))
+            .upsert_into(&*db.connection()?)?;
+        let mut response = client
+            .get("/sensors?q=VISIBLE&value_type=Counter&stale=&sort=updated")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.body_string().unwrap();
+        assert!(body.contains("test::visible"));
+        assert!(!body.contains("other::hidden"));
+        Ok(())
+    }
+
+    #[test]
+    fn boolean_sensor_timeline_ok() -> Result {
+        let client = client()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        Message::new("test::door")
+            .value(Value::Boolean(true))
+            .upsert_into(&*db.connection()?)?;
+        let mut response = client.get("/sensors/test::door").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.body_string().unwrap().contains("has-background-success"));
+        Ok(())
+    }
+
+    #[test]
+    fn text_sensor_event_log_ok() -> Result {
+        let client = client()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        Message::new("test::status")
+            .value(Value::Text("Washing".into()))
+            .upsert_into(&*db.connection()?)?;
+        let mut response = client.get("/sensors/test::status?page=0").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.body_string().unwrap().contains("readings/export?format=csv"));
+        Ok(())
+    }
+
+    #[test]
+    fn readings_export_csv_ok() -> Result {
+        let client = client()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        Message::new("test::temperature")
+            .value(Value::Temperature(21.5))
+            .upsert_into(&*db.connection()?)?;
+        let mut response = client
+            .get("/sensors/test::temperature/readings/export?format=csv&since=2000-01-01T00:00")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.body_string().unwrap().contains(",Temperature,21.5"));
+        Ok(())
+    }
+
+    #[test]
+    fn delete_out_of_range_reading_not_found() -> Result {
+        let client = client()?;
+        let csrf_token = csrf_token(&client);
+        let response = client
+            .post("/sensors/test::temperature/readings/9223372036854775807")
+            .header(ContentType::Form)
+            .body(format!("_method=delete&_csrf={}", csrf_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        Ok(())
+    }
+
+    #[test]
+    fn delete_reading_ok() -> Result {
+        let client = client()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        Message::new("test::temperature")
+            .value(Value::Temperature(21.5))
+            .timestamp(Local.timestamp_millis(1000))
+            .upsert_into(&*db.connection()?)?;
+        let csrf_token = csrf_token(&client);
+        let response = client
+            .post("/sensors/test::temperature/readings/1000")
+            .header(ContentType::Form)
+            .body(format!("_method=delete&_csrf={}", csrf_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(db.select_sensor_reading_count("test::temperature")?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn write_ok() -> Result {
+        let (client, rx) = client_with_rx()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        Message::new("test::switch")
+            .value(false)
+            .control(Control::Toggle)
+            .upsert_into(&*db.connection()?)?;
+        let service = thread::spawn(move || {
+            let message = rx.recv().unwrap();
+            crate::core::writes::report(&message, &Ok(()));
+            message
+        });
+        let csrf_token = csrf_token(&client);
+        let response = client
+            .post("/sensors/test::switch/write")
+            .header(ContentType::Form)
+            .body(format!("_csrf={}&value=true", csrf_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let message = service.join().unwrap();
+        assert_eq!(message.type_, MessageType::Write);
+        assert_eq!(message.reading.value, Value::Boolean(true));
+        Ok(())
+    }
+
+    #[test]
+    fn write_error_bad_gateway() -> Result {
+        let (client, rx) = client_with_rx()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        Message::new("test::failing_switch")
+            .value(false)
+            .control(Control::Toggle)
+            .upsert_into(&*db.connection()?)?;
+        let service = thread::spawn(move || {
+            let message = rx.recv().unwrap();
+            crate::core::writes::report(&message, &Err("offline".into()));
+        });
+        let csrf_token = csrf_token(&client);
+        let mut response = client
+            .post("/sensors/test::failing_switch/write")
+            .header(ContentType::Form)
+            .body(format!("_csrf={}&value=true", csrf_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadGateway);
+        assert!(response.body_string().unwrap().contains("offline"));
+        service.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn write_not_controllable_not_found() -> Result {
+        let client = client()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        Message::new("test::temperature")
+            .value(Value::Temperature(21.0))
+            .upsert_into(&*db.connection()?)?;
+        let csrf_token = csrf_token(&client);
+        let response = client
+            .post("/sensors/test::temperature/write")
+            .header(ContentType::Form)
+            .body(format!("_csrf={}&value=22", csrf_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        Ok(())
+    }
+
+    #[test]
+    fn services_ok() -> Result {
+        let client = client()?;
+        assert_eq!(client.get("/services").dispatch().status(), Status::Ok);
+        let mut response = client.get("/api/v1/services").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.body_string().unwrap(), "[]");
+        Ok(())
+    }
+
+    #[test]
+    fn base_path_ok() -> Result {
+        let client = client_with_base_path(HashMap::new())?;
+        let mut response = client.get("/iot/sensors").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.body_string().unwrap();
+        assert!(body.contains(r#"href="/iot/sensors/test::base_path""#));
+        assert!(body.contains(r#"href="/iot/static/bulma.min."#));
+        assert_eq!(client.get("/iot/api/v1/sensors").dispatch().status(), Status::Ok);
+        assert_eq!(client.get("/sensors").dispatch().status(), Status::NotFound);
+        let mut response = client.get("/iot/sw.js").dispatch();
+        assert!(response
+            .body_string()
+            .unwrap()
+            .contains(r#"const ASSETS = ["/iot/static/"#));
+        Ok(())
+    }
+
+    #[test]
+    fn base_path_login_redirect_ok() -> Result {
+        let mut users = HashMap::new();
+        users.insert(
+            "alice".to_string(),
+            User {
+                role: Role::Admin,
+                secrets: UserSecrets {
+                    password_hash: Secret::new(crate::core::auth::hash_password("secret")?),
+                },
+            },
+        );
+        let client = client_with_base_path(users)?;
+        let response = client.get("/iot/sensors").dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert!(response
+            .headers()
+            .get_one("Location")
+            .unwrap()
+            .starts_with("/iot/login?next="));
+        Ok(())
+    }
+
+    fn client_with_base_path(users: HashMap<String, User>) -> Result<Client> {
+        let db = Connection::open_and_initialize(":memory:")?;
+        Message::new("test::base_path").upsert_into(&*db.connection()?)?;
+        let supervisor = Supervisor::new(Bus::new(Arc::new(AtomicU64::new(0))), db.clone(), None);
+        Ok(Client::new(make_rocket(
+            "127.0.0.1",
+            default_http_port(),
+            LiveSettings::new(Settings {
+                base_path: "/iot/".into(),
+                users,
+                ..test_settings()
+            }),
+            Vec::new(),
+            db,
+            Arc::new(AtomicU64::new(0)),
+            crossbeam::channel::unbounded().0,
+            EventHub::default(),
+            supervisor,
+        )?)?)
+    }
+
+    #[test]
+    fn logs_ok() -> Result {
+        LOG_BUFFER.set_capacity(1000);
+        LOG_BUFFER.push(
+            LogLevel::Error,
+            Some("test::logs_ok".into()),
+            "test".into(),
+            "failed".into(),
+        );
+        let client = client()?;
+        assert_eq!(
+            client.get("/logs?service=test::logs_ok").dispatch().status(),
+            Status::Ok
+        );
+        let mut response = client.get("/logs/json?service=test::logs_ok&level=error").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.body_string().unwrap().contains(r#""message":"failed""#));
+        Ok(())
+    }
+
+    #[test]
+    fn restart_missing_service_not_found() -> Result {
+        let client = client()?;
+        let csrf_token = csrf_token(&client);
+        let response = client
+            .post("/services/missing/restart")
+            .header(ContentType::Form)
+            .body(format!("_csrf={}", csrf_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        Ok(())
+    }
+
+    #[test]
+    fn home_dashboard_ok() -> Result {
+        let client = client()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        Message::new("test::temperature")
+            .value(Value::Temperature(21.0))
+            .upsert_into(&*db.connection()?)?;
+        Message::new("test::battery")
+            .value(Value::BatteryLife(80.0))
+            .tag("battery")
+            .upsert_into(&*db.connection()?)?;
+        db.upsert_dashboard(
+            "climate",
+            &Dashboard {
+                title: "Climate".into(),
+                is_home: true,
+                sections: vec![DashboardSection {
+                    title: Some("Living Room".into()),
+                    tiles: vec![DashboardTile {
+                        sensor_id: "test::temperature".into(),
+                        size: Some(TileSize::Wide),
+                        chart_minutes: Some(60),
+                    }],
+                    tag: Some("battery".into()),
+                    charts: vec![],
+                }],
+            },
+        )?;
+        let mut response = client.get("/").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.body_string().unwrap();
+        assert!(body.contains("Living Room"));
+        assert!(body.contains("sparkline-0-0"));
+        assert!(body.contains("test::battery"));
+        Ok(())
+    }
+
+    #[test]
+    fn overlay_chart_ok() -> Result {
+        let client = client()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        Message::new("test::temperature")
+            .value(Value::Temperature(21.0))
+            .upsert_into(&*db.connection()?)?;
+        Message::new("test::humidity")
+            .value(Value::Rh(40.0))
+            .upsert_into(&*db.connection()?)?;
+        let mut response = client
+            .get("/chart?sensor_ids=test::temperature,test::humidity&compare_minutes=1440")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.body_string().unwrap();
+        assert!(body.contains("test::humidity, 1 day earlier"));
+        Ok(())
+    }
+
+    #[test]
+    fn missing_dashboard_not_found() -> Result {
+        let client = client()?;
+        let response = client.get("/dashboards/missing").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        Ok(())
+    }
+
     #[test]
     fn favicon_ok() -> Result {
         let client = client()?;
@@ -354,14 +986,598 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn health_ok() -> Result {
+        let client = client()?;
+        let response = client.get("/health").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        Ok(())
+    }
+
+    /// The test client doesn't spawn the bus dispatcher.
+    #[test]
+    fn not_ready() -> Result {
+        let client = client()?;
+        let mut response = client.get("/ready").dispatch();
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let readiness: serde_json::Value = serde_json::from_str(&response.body_string().unwrap())?;
+        assert_eq!(readiness["is_ready"], false);
+        assert_eq!(readiness["checks"][0]["name"], "database");
+        assert_eq!(readiness["checks"][0]["is_ok"], true);
+        Ok(())
+    }
+
+    #[test]
+    fn energy_ok() -> Result {
+        let client = client()?;
+        assert_eq!(client.get("/energy").dispatch().status(), Status::Ok);
+        assert_eq!(
+            client
+                .get("/energy?since=2020-10-18&until=2020-10-19&resolution=hour")
+                .dispatch()
+                .status(),
+            Status::Ok
+        );
+        assert_eq!(
+            client
+                .get("/energy?since=-262144-01-01&until=%2B262143-12-31")
+                .dispatch()
+                .status(),
+            Status::Ok
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hashed_asset_ok() -> Result {
+        let client = client()?;
+        let response = client
+            .get(assets::url("bulma.min.css"))
+            .header(Header::new("Accept-Encoding", "gzip, br"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::CSS));
+        assert_eq!(response.headers().get_one("Content-Encoding"), Some("br"));
+        assert!(response
+            .headers()
+            .get_one("Cache-Control")
+            .unwrap()
+            .contains("immutable"));
+        Ok(())
+    }
+
+    #[test]
+    fn asset_encodings_not_interchangeable() -> Result {
+        let client = client()?;
+        let entity_tag = client
+            .get(assets::url("bulma.min.css"))
+            .header(Header::new("Accept-Encoding", "br"))
+            .dispatch()
+            .headers()
+            .get_one("ETag")
+            .unwrap()
+            .to_string();
+        assert!(entity_tag.ends_with(r#"-br""#));
+        let response = client
+            .get(assets::url("bulma.min.css"))
+            .header(Header::new("If-None-Match", entity_tag))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Content-Encoding"), None);
+        assert_eq!(response.headers().get_one("Vary"), Some("Accept-Encoding"));
+        Ok(())
+    }
+
+    #[test]
+    fn plain_asset_not_modified() -> Result {
+        let client = client()?;
+        let entity_tag = client
+            .get("/static/favicon-16x16.png")
+            .dispatch()
+            .headers()
+            .get_one("ETag")
+            .unwrap()
+            .to_string();
+        let response = client
+            .get("/static/favicon-16x16.png")
+            .header(Header::new("If-None-Match", entity_tag))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotModified);
+        Ok(())
+    }
+
+    #[test]
+    fn api_sensors_ok() -> Result {
+        let client = client()?;
+        let response = client.get("/api/v1/sensors?pattern=test::*").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        assert!(response.headers().get_one("ETag").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn api_sensors_not_modified() -> Result {
+        let client = client()?;
+        let entity_tag = client
+            .get("/api/v1/sensors")
+            .dispatch()
+            .headers()
+            .get_one("ETag")
+            .map(ToString::to_string)
+            .unwrap();
+        let response = client
+            .get("/api/v1/sensors")
+            .header(rocket::http::Header::new("If-None-Match", entity_tag))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotModified);
+        Ok(())
+    }
+
+    #[test]
+    fn api_missing_sensor_readings_not_found() -> Result {
+        let client = client()?;
+        let response = client.get("/api/v1/sensors/missing/readings").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        Ok(())
+    }
+
+    #[test]
+    fn api_sensor_readings_out_of_range_bad_request() -> Result {
+        let client = client()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        Message::new("test::temperature")
+            .value(Value::Temperature(21.5))
+            .upsert_into(&*db.connection()?)?;
+        for query in &[
+            "since=9223372036854775807",
+            "until=-9223372036854775808",
+            "bucket_secs=9223372036854775807",
+        ] {
+            let response = client
+                .get(format!("/api/v1/sensors/test::temperature/readings?{}", query))
+                .dispatch();
+            assert_eq!(response.status(), Status::BadRequest);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn api_sensor_readings_zero_limit_paginates() -> Result {
+        let client = client()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        let now = Local::now();
+        for minutes in 1..=2 {
+            Message::new("test::paginated")
+                .value(Value::Counter(minutes))
+                .timestamp(now - chrono::Duration::minutes(minutes as i64))
+                .upsert_into(&*db.connection()?)?;
+        }
+        let mut response = client
+            .get("/api/v1/sensors/test::paginated/readings?limit=0")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = serde_json::from_str(&response.body_string().unwrap())?;
+        assert_eq!(body["readings"].as_array().unwrap().len(), 1);
+        assert_eq!(body["next_offset"], 1);
+        Ok(())
+    }
+
+    #[test]
+    fn api_stats_ok() -> Result {
+        let client = client()?;
+        let response = client.get("/api/v1/stats").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        Ok(())
+    }
+
+    #[test]
+    fn ingest_ok() -> Result {
+        let (client, rx) = client_with_rx()?;
+        let response = client
+            .post("/ingest/esp::temperature?kind=Temperature&value=21.5")
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Accepted);
+        assert_eq!(rx.try_recv()?.reading.value, Value::Temperature(21.5));
+        Ok(())
+    }
+
+    #[test]
+    fn ingest_unauthorized() -> Result {
+        let (client, rx) = client_with_rx()?;
+        let response = client
+            .get("/ingest/esp::temperature?kind=Temperature&value=21.5")
+            .header(Header::new("Authorization", "Bearer wrong"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert!(rx.try_recv().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn ingest_query_token_unauthorized() -> Result {
+        let (client, rx) = client_with_rx()?;
+        let response = client
+            .get("/ingest/esp::temperature?kind=Temperature&value=21.5&token=secret")
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert!(rx.try_recv().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn ingest_forbidden_type() -> Result {
+        let (client, _rx) = client_with_rx()?;
+        let response = client
+            .get("/ingest/esp::temperature?kind=Temperature&value=21.5&type=Write")
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        Ok(())
+    }
+
+    #[test]
+    fn ingest_control_characters_bad_request() -> Result {
+        let (client, rx) = client_with_rx()?;
+        let response = client
+            .post("/ingest/esp::temperature?kind=Temperature&value=21.5&title=Hi%0A")
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert!(rx.try_recv().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sensor_script_title_escaped() -> Result {
+        let client = client()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        Message::new("test::temperature")
+            .value(Value::Temperature(21.5))
+            .sensor_title("</script><script>alert(1)</script>")
+            .upsert_into(&*db.connection()?)?;
+        let mut response = client.get("/sensors/test::temperature").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.body_string().unwrap();
+        assert!(!body.contains("<script>alert(1)"));
+        assert!(body.contains(r"\u003c/script\u003e\u003cscript\u003ealert(1)"));
+        Ok(())
+    }
+
+    #[test]
+    fn post_messages_ok() -> Result {
+        let (client, rx) = client_with_rx()?;
+        let response = client
+            .post("/api/v1/messages")
+            .header(ContentType::JSON)
+            .header(rocket::http::Header::new("Authorization", "Bearer secret"))
+            .body(r#"[{"sensor_id": "esp::is_on", "type": "ReadNonLogged", "value": {"type": "Boolean", "value": true}}]"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Accepted);
+        let message = rx.try_recv()?;
+        assert_eq!(message.type_, MessageType::ReadNonLogged);
+        assert_eq!(message.reading.value, Value::Boolean(true));
+        Ok(())
+    }
+
+    #[test]
+    fn index_redirects_to_login() -> Result {
+        let client = client_with_users()?;
+        let response = client.get("/").dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert!(response
+            .headers()
+            .get_one("Location")
+            .unwrap()
+            .starts_with("/login?next="));
+        Ok(())
+    }
+
+    #[test]
+    fn api_unauthorized() -> Result {
+        let client = client_with_users()?;
+        let response = client.get("/api/v1/sensors").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        Ok(())
+    }
+
+    #[test]
+    fn metrics_ok() -> Result {
+        let client = client()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        Message::new("test::temperature")
+            .value(Value::Temperature(21.5))
+            .upsert_into(&*db.connection()?)?;
+        let mut response = client.get("/metrics").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.body_string().unwrap();
+        assert!(body.contains(r#"my_iot_sensor_value{sensor_id="test::temperature","#));
+        assert!(body.contains("my_iot_database_size_bytes "));
+        Ok(())
+    }
+
+    #[test]
+    fn metrics_unauthorized() -> Result {
+        let client = client_with_users()?;
+        let response = client.get("/metrics").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        Ok(())
+    }
+
+    #[test]
+    fn login_ok() -> Result {
+        let client = client_with_users()?;
+        let response = client
+            .post("/login")
+            .header(ContentType::Form)
+            .body("name=alice&password=secret&next=/")
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(response.headers().get_one("Location"), Some("/"));
+        assert_eq!(client.get("/").dispatch().status(), Status::Ok);
+        Ok(())
+    }
+
+    #[test]
+    fn user_pages_not_stored_and_cleared_on_logout() -> Result {
+        let client = client_with_users()?;
+        client
+            .post("/login")
+            .header(ContentType::Form)
+            .body("name=alice&password=secret")
+            .dispatch();
+        let mut response = client.get("/").dispatch();
+        assert_eq!(response.headers().get_one("Cache-Control"), Some("private, no-store"));
+        let body = response.body_string().unwrap();
+        let csrf_token = body
+            .split(r#"name="_csrf" value=""#)
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap()
+            .to_string();
+        let response = client
+            .post("/logout")
+            .header(ContentType::Form)
+            .body(format!("_csrf={}", csrf_token))
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(response.headers().get_one("Clear-Site-Data"), Some(r#""cache""#));
+        Ok(())
+    }
+
+    #[test]
+    fn login_wrong_password_fails() -> Result {
+        let client = client_with_users()?;
+        client
+            .post("/login")
+            .header(ContentType::Form)
+            .body("name=alice&password=wrong")
+            .dispatch();
+        assert_eq!(client.get("/").dispatch().status(), Status::SeeOther);
+        Ok(())
+    }
+
+    #[test]
+    fn delete_sensor_without_csrf_token_forbidden() -> Result {
+        let client = client_with_users()?;
+        client
+            .post("/login")
+            .header(ContentType::Form)
+            .body("name=alice&password=secret")
+            .dispatch();
+        let response = client
+            .post("/sensors/test")
+            .header(ContentType::Form)
+            .body("_method=delete")
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        Ok(())
+    }
+
+    /// Without users, a cross-site form still can't pass the CSRF check.
+    #[test]
+    fn anonymous_write_without_csrf_token_forbidden() -> Result {
+        let (client, rx) = client_with_rx()?;
+        let db = client.rocket().state::<Connection>().unwrap();
+        Message::new("test::switch")
+            .value(false)
+            .control(Control::Toggle)
+            .upsert_into(&*db.connection()?)?;
+        csrf_token(&client);
+        let response = client
+            .post("/sensors/test::switch/write")
+            .header(ContentType::Form)
+            .body("_csrf=&value=true")
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        assert!(rx.try_recv().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn login_rejects_backslash_redirect() -> Result {
+        let client = client_with_users()?;
+        let response = client
+            .post("/login")
+            .header(ContentType::Form)
+            .body("name=alice&password=secret&next=%2F%5Cevil.com")
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(response.headers().get_one("Location"), Some("/"));
+        Ok(())
+    }
+
+    #[test]
+    fn api_token_reads_allowed_sensors_only() -> Result {
+        let client = client_with_users()?;
+        let mut response = client
+            .get("/api/v1/sensors")
+            .header(rocket::http::Header::new("Authorization", "Bearer api-secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.body_string().unwrap();
+        assert!(body.contains("test::visible"));
+        assert!(!body.contains("other::hidden"));
+        Ok(())
+    }
+
+    #[test]
+    fn api_wrong_token_unauthorized() -> Result {
+        let client = client_with_users()?;
+        let response = client
+            .get("/api/v1/sensors")
+            .header(rocket::http::Header::new("Authorization", "Bearer wrong"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        Ok(())
+    }
+
+    #[test]
+    fn api_token_write_ok() -> Result {
+        let client = client_with_users()?;
+        let response = client
+            .post("/ingest/tradfri::bulb?kind=Boolean&value=true&type=Write")
+            .header(Header::new("Authorization", "Bearer api-secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Accepted);
+        Ok(())
+    }
+
+    #[test]
+    fn api_token_ingest_forbidden() -> Result {
+        let client = client_with_users()?;
+        let response = client
+            .post("/ingest/tradfri::bulb?kind=Boolean&value=true")
+            .header(Header::new("Authorization", "Bearer api-secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        Ok(())
+    }
+
+    /// Writes the settings into a temporary file of its own, so that the tests don't interfere.
+    fn temp_settings_file(content: &str) -> crate::Result<PathBuf> {
+        let path = std::env::temp_dir().join(format!("my-iot-settings-{}.toml", crate::core::auth::generate_token()?));
+        std::fs::write(&path, content)?;
+        Ok(path)
+    }
+
     fn client() -> crate::Result<Client> {
-        Ok(Client::new(make_rocket(
-            &Settings {
-                http_port: default_http_port(),
-                services: HashMap::new(),
+        Ok(client_with_rx()?.0)
+    }
+
+    /// Visits a page to get the anonymous CSRF token, the client keeps its cookie for the next requests.
+    fn csrf_token(client: &Client) -> String {
+        client
+            .get("/")
+            .dispatch()
+            .cookies()
+            .into_iter()
+            .find(|cookie| cookie.name() == "csrf")
+            .map(|cookie| cookie.value().to_string())
+            .unwrap()
+    }
+
+    fn client_with_users() -> crate::Result<Client> {
+        let mut users = HashMap::new();
+        users.insert(
+            "alice".to_string(),
+            User {
+                role: Role::Admin,
+                secrets: UserSecrets {
+                    password_hash: Secret::new(crate::core::auth::hash_password("secret")?),
+                },
             },
-            Connection::open_and_initialize(":memory:")?,
+        );
+        let db = Connection::open_and_initialize(":memory:")?;
+        Message::new("test::visible").upsert_into(&*db.connection()?)?;
+        Message::new("other::hidden").upsert_into(&*db.connection()?)?;
+        db.insert_api_token(
+            "script",
+            &crate::core::auth::hash_token("api-secret"),
+            &[
+                Scope::Read {
+                    pattern: "test::*".into(),
+                },
+                Scope::Write {
+                    pattern: "tradfri::*".into(),
+                },
+            ],
+        )?;
+        let (tx, _) = crossbeam::channel::unbounded();
+        let supervisor = Supervisor::new(Bus::new(Arc::new(AtomicU64::new(0))), db.clone(), None);
+        Ok(Client::new(make_rocket(
+            "127.0.0.1",
+            default_http_port(),
+            LiveSettings::new(Settings {
+                users,
+                ..test_settings()
+            }),
+            Vec::new(),
+            db,
             Arc::new(AtomicU64::new(0)),
+            tx,
+            EventHub::default(),
+            supervisor,
         )?)?)
     }
+
+    /// Default settings of the test clients, override the fields with the struct update syntax.
+    fn test_settings() -> Settings {
+        Settings {
+            http_port: default_http_port(),
+            http_addresses: default_http_addresses(),
+            tls: None,
+            base_path: String::new(),
+            max_chart_points: default_max_chart_points(),
+            services: HashMap::new(),
+            tokens: HashMap::new(),
+            users: HashMap::new(),
+            dashboards: HashMap::new(),
+            expected_updates: Vec::new(),
+            sensor_tags: Vec::new(),
+            critical_services: Vec::new(),
+            timezone: None,
+            datetime_format: DateTimeFormat::default(),
+            energy: Energy::default(),
+        }
+    }
+
+    fn client_with_rx() -> crate::Result<(Client, Receiver)> {
+        client_with_settings_paths(Vec::new())
+    }
+
+    fn client_with_settings_paths(settings_paths: Vec<PathBuf>) -> crate::Result<(Client, Receiver)> {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let db = Connection::open_and_initialize(":memory:")?;
+        let supervisor = Supervisor::new(Bus::new(Arc::new(AtomicU64::new(0))), db.clone(), None);
+        let mut tokens = HashMap::new();
+        tokens.insert(
+            "esp".to_string(),
+            Token {
+                secrets: TokenSecrets {
+                    token_hash: Secret::new(crate::core::auth::hash_token("secret")),
+                },
+                sensor_prefix: Some("esp::".into()),
+                types: vec![MessageType::ReadLogged, MessageType::ReadNonLogged],
+            },
+        );
+        let client = Client::new(make_rocket(
+            "127.0.0.1",
+            default_http_port(),
+            LiveSettings::new(Settings {
+                tokens,
+                ..test_settings()
+            }),
+            settings_paths,
+            db,
+            Arc::new(AtomicU64::new(0)),
+            tx,
+            EventHub::default(),
+            supervisor,
+        )?)?;
+        Ok((client, rx))
+    }
 }
@@ -0,0 +1,246 @@
+//! Versioned JSON API.
+
+use chrono::Duration;
+use rocket::http::hyper::header::EntityTag;
+use rocket::http::Status;
+use rocket::{get, State};
+use rocket_contrib::json::Json;
+
+use crate::core::db::Bucket;
+use crate::core::staleness;
+use crate::prelude::*;
+use crate::web::auth::Access;
+use crate::web::entity_tag::{entity_tag_of, with_stale_count};
+use crate::web::live_settings::CurrentSettings;
+use crate::web::message_counter::MessageCounter;
+use crate::web::revalidated::Revalidated;
+
+/// Default history page size.
+const DEFAULT_LIMIT: u32 = 1000;
+
+/// Maximum history page size.
+const MAX_LIMIT: u32 = 10000;
+
+/// Default history period.
+const DEFAULT_PERIOD_MINUTES: i64 = 60;
+
+#[derive(Serialize)]
+pub struct SensorResponse {
+    sensor: Sensor,
+    reading: Reading,
+
+    /// Whether the sensor has missed its expected update interval.
+    is_stale: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reading_count: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum HistoryResponse {
+    Readings {
+        readings: Vec<Reading>,
+
+        /// Offset of the next page, if any.
+        next_offset: Option<u32>,
+    },
+    Buckets {
+        buckets: Vec<Bucket>,
+    },
+}
+
+#[derive(Serialize)]
+pub struct StatsResponse {
+    db_size: u64,
+    sensor_count: u64,
+    reading_count: u64,
+    message_count: u64,
+
+    /// Service IDs and types.
+    services: HashMap<String, &'static str>,
+}
+
+/// Lists the sensors and their latest readings.
+/// The sensors may be filtered by location, by tag and by sensor ID `GLOB` pattern, for example: `buienradar::*`.
+/// API tokens only see the sensors which their scopes allow to read.
+#[get("/sensors?<location>&<pattern>&<tag>")]
+pub fn get_sensors(
+    access: Access,
+    db: State<Connection>,
+    location: Option<String>,
+    pattern: Option<String>,
+    tag: Option<String>,
+) -> Result<Revalidated<Json<Vec<SensorResponse>>>> {
+    let actuals: Vec<(Sensor, Reading)> = db
+        .select_actuals_by(location.as_deref(), pattern.as_deref(), tag.as_deref())?
+        .into_iter()
+        .filter(|(sensor, _)| access.can_read(&sensor.id))
+        .collect();
+    let sensors: Vec<SensorResponse> = actuals
+        .into_iter()
+        .map(|(sensor, reading)| SensorResponse {
+            is_stale: staleness::is_stale(&sensor.id, &reading),
+            sensor,
+            reading,
+            reading_count: None,
+        })
+        .collect();
+    Ok(Revalidated(
+        with_stale_count(
+            entity_tag_of(sensors.iter().map(|response| &response.reading)),
+            sensors.iter().filter(|response| response.is_stale).count(),
+        ),
+        Json(sensors),
+    ))
+}
+
+#[get("/sensors/<sensor_id>")]
+pub fn get_sensor(
+    access: Access,
+    db: State<Connection>,
+    sensor_id: String,
+) -> Result<Option<Revalidated<Json<SensorResponse>>>> {
+    if !access.can_read(&sensor_id) {
+        return Ok(None);
+    }
+    Ok(match db.select_sensor(&sensor_id)? {
+        Some((sensor, reading)) => {
+            let is_stale = staleness::is_stale(&sensor.id, &reading);
+            Some(Revalidated(
+                with_stale_count(reading.entity_tag(), is_stale as usize),
+                Json(SensorResponse {
+                    sensor,
+                    reading,
+                    is_stale,
+                    reading_count: Some(db.select_sensor_reading_count(&sensor_id)?),
+                }),
+            ))
+        }
+        None => None,
+    })
+}
+
+/// Selects the sensor history within the specified period.
+///
+/// - `since` and `until` are Unix timestamps in milliseconds, the last hour by default
+/// - `limit` and `offset` paginate the readings
+/// - `bucket_secs` makes it return the readings aggregated into buckets of the specified duration instead
+///
+/// Responds with `400 Bad Request` if the timestamps or the bucket duration are out of range.
+#[get("/sensors/<sensor_id>/readings?<since>&<until>&<limit>&<offset>&<bucket_secs>")]
+pub fn get_sensor_readings(
+    access: Access,
+    db: State<Connection>,
+    sensor_id: String,
+    since: Option<i64>,
+    until: Option<i64>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    bucket_secs: Option<i64>,
+) -> Result<Result<Option<Revalidated<Json<HistoryResponse>>>, Status>> {
+    if !access.can_read(&sensor_id) || db.select_sensor(&sensor_id)?.is_none() {
+        return Ok(Ok(None));
+    }
+
+    let (since, until) = match to_period(since, until) {
+        Some(period) => period,
+        None => return Ok(Err(Status::BadRequest)),
+    };
+
+    if let Some(bucket_secs) = bucket_secs {
+        let bucket = match bucket_secs.checked_mul(1000) {
+            Some(bucket_millis) => Duration::milliseconds(bucket_millis),
+            None => return Ok(Err(Status::BadRequest)),
+        };
+        let buckets = db.select_buckets(&sensor_id, &since, &until, &bucket)?;
+        let entity_tag = EntityTag::new(
+            true,
+            format!(
+                "{:x}-{:x}",
+                buckets.iter().map(|bucket| bucket.count).sum::<u64>(),
+                buckets.last().map_or(0, |bucket| bucket.timestamp.timestamp_millis()),
+            ),
+        );
+        return Ok(Ok(Some(Revalidated(
+            entity_tag,
+            Json(HistoryResponse::Buckets { buckets }),
+        ))));
+    }
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).max(1).min(MAX_LIMIT);
+    let offset = offset.unwrap_or(0);
+    let readings = db.select_readings(&sensor_id, &since, &until, limit, offset)?;
+    let next_offset = next_offset(offset, limit, readings.len());
+    Ok(Ok(Some(Revalidated(
+        entity_tag_of(&readings),
+        Json(HistoryResponse::Readings { readings, next_offset }),
+    ))))
+}
+
+/// Converts the query timestamps in milliseconds, the last hour by default.
+/// Returns `None` if they're out of range.
+fn to_period(since: Option<i64>, until: Option<i64>) -> Option<(DateTime<Local>, DateTime<Local>)> {
+    let until = match until {
+        Some(until) => Local.timestamp_millis_opt(until).single()?,
+        None => Local::now(),
+    };
+    let since = match since {
+        Some(since) => Local.timestamp_millis_opt(since).single()?,
+        None => until.checked_sub_signed(Duration::minutes(DEFAULT_PERIOD_MINUTES))?,
+    };
+    Some((since, until))
+}
+
+/// Returns the offset of the next page, unless the page is the last one.
+/// A full page at the very end of the offset range is the last one as well.
+fn next_offset(offset: u32, limit: u32, count: usize) -> Option<u32> {
+    if count as u32 == limit {
+        offset.checked_add(limit)
+    } else {
+        None
+    }
+}
+
+#[get("/stats")]
+pub fn get_stats(
+    _access: Access,
+    db: State<Connection>,
+    settings: CurrentSettings,
+    message_counter: State<MessageCounter>,
+) -> Result<Json<StatsResponse>> {
+    Ok(Json(StatsResponse {
+        db_size: db.select_size()?,
+        sensor_count: db.select_sensor_count()?,
+        reading_count: db.select_reading_count()?,
+        message_count: message_counter.inner().value(),
+        services: settings
+            .services
+            .iter()
+            .map(|(service_id, service)| (service_id.clone(), service.type_name()))
+            .collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_offset_ok() {
+        assert_eq!(next_offset(0, 10, 10), Some(10));
+        assert_eq!(next_offset(10, 10, 5), None);
+    }
+
+    #[test]
+    fn to_period_out_of_range_none() {
+        assert!(to_period(Some(i64::MAX), None).is_none());
+        assert!(to_period(None, Some(i64::MIN)).is_none());
+        assert!(to_period(Some(0), Some(1000)).is_some());
+    }
+
+    #[test]
+    fn next_offset_overflow_none() {
+        assert_eq!(next_offset(u32::MAX - 5, 10, 10), None);
+    }
+}
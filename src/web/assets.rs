@@ -0,0 +1,207 @@
+//! Static assets, embedded by the build script.
+//!
+//! Each asset is served under `/static/` both at its plain path, for example, `bulma.min.css`,
+//! and at its hashed path, for example, `bulma.min.0123456789abcdef.css`. The latter never changes,
+//! so it's cached forever. The former is for the references which can't know the hash,
+//! such as the fonts in the Font Awesome stylesheet.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use lazy_static::lazy_static;
+use rocket::get;
+use rocket::http::hyper::header::{ETag, EntityTag};
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::Responder;
+use rocket::{Request, Response};
+
+use crate::web::if_none_match::IfNoneMatch;
+
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+/// The plain paths are revalidated after this.
+const PLAIN_MAX_AGE_SECS: u32 = 3600;
+
+/// The hashed paths never change.
+const HASHED_MAX_AGE_SECS: u32 = 365 * 24 * 3600;
+
+pub struct Asset {
+    /// Path relative to `src/statics`.
+    pub path: &'static str,
+
+    /// Content hash.
+    pub hash: &'static str,
+
+    pub content: &'static [u8],
+    pub gzip: Option<&'static [u8]>,
+    pub brotli: Option<&'static [u8]>,
+}
+
+lazy_static! {
+    /// Assets by their plain and hashed paths. The flag tells whether the path is hashed.
+    static ref ASSETS_BY_PATH: HashMap<String, (&'static Asset, bool)> = ASSETS
+        .iter()
+        .flat_map(|asset| vec![(asset.path.to_string(), (asset, false)), (asset.hashed_path(), (asset, true))])
+        .collect();
+}
+
+impl Asset {
+    /// Inserts the hash before the extension.
+    pub fn hashed_path(&self) -> String {
+        let name_start = self.path.rfind('/').map_or(0, |i| i + 1);
+        match self.path[name_start..].rfind('.') {
+            Some(i) => format!(
+                "{}.{}{}",
+                &self.path[..name_start + i],
+                self.hash,
+                &self.path[name_start + i..]
+            ),
+            None => format!("{}.{}", self.path, self.hash),
+        }
+    }
+
+    pub fn content_type(&self) -> ContentType {
+        match self.path.rsplit('.').next() {
+            Some("webmanifest") => ContentType::new("application", "manifest+json"),
+            Some(extension) => ContentType::from_extension(extension).unwrap_or(ContentType::Binary),
+            None => ContentType::Binary,
+        }
+    }
+
+    /// Each encoding is a different representation, thus it has an entity tag of its own.
+    pub fn entity_tag(&self, encoding: Option<&str>) -> EntityTag {
+        match encoding {
+            Some(encoding) => EntityTag::new(false, format!("{}-{}", self.hash, encoding)),
+            None => EntityTag::new(false, self.hash.to_string()),
+        }
+    }
+}
+
+/// Returns the hashed URL of the asset relative to the base path, for example: `"bulma.min.css"`.
+/// Falls back to the plain URL for an unknown asset, so that it shows up as a missing resource.
+pub fn url(path: &str) -> String {
+    let path = ASSETS_BY_PATH
+        .get(path)
+        .map_or_else(|| path.to_string(), |(asset, _)| asset.hashed_path());
+    format!("/static/{}", path)
+}
+
+/// Returns the hashed URLs of all the assets, for the service worker to precache them.
+pub fn urls() -> Vec<String> {
+    ASSETS.iter().map(|asset| url(asset.path)).collect()
+}
+
+/// Looks up an asset by its plain or hashed path.
+pub fn get(path: &str) -> Option<AssetResponse> {
+    ASSETS_BY_PATH
+        .get(path)
+        .map(|(asset, is_hashed)| AssetResponse(asset, *is_hashed))
+}
+
+#[get("/static/<path..>")]
+pub fn get_static(path: PathBuf) -> Option<AssetResponse> {
+    get(&path.to_string_lossy().replace('\\', "/"))
+}
+
+/// Serves an asset with a strong entity tag, in the best encoding the client accepts.
+pub struct AssetResponse(&'static Asset, bool);
+
+impl<'r> Responder<'r> for AssetResponse {
+    fn respond_to(self, request: &Request) -> Result<Response<'r>, Status> {
+        let AssetResponse(asset, is_hashed) = self;
+        let accept_encoding = request.headers().get_one("Accept-Encoding").unwrap_or_default();
+        let (encoding, content) = match (asset.brotli, asset.gzip) {
+            (Some(brotli), _) if accepts_encoding(accept_encoding, "br") => (Some("br"), brotli),
+            (_, Some(gzip)) if accepts_encoding(accept_encoding, "gzip") => (Some("gzip"), gzip),
+            _ => (None, asset.content),
+        };
+        let entity_tag = asset.entity_tag(encoding);
+        let mut response = Response::build();
+        match IfNoneMatch::from_request(request) {
+            Outcome::Success(IfNoneMatch(if_none_match)) if if_none_match.weak_eq(&entity_tag) => {
+                response.status(Status::NotModified);
+            }
+            _ => {
+                response.header(asset.content_type()).sized_body(Cursor::new(content));
+            }
+        }
+        if let Some(encoding) = encoding {
+            response.raw_header("Content-Encoding", encoding);
+        }
+        if asset.brotli.is_some() || asset.gzip.is_some() {
+            response.raw_header("Vary", "Accept-Encoding");
+        }
+        response.header(ETag(entity_tag)).raw_header(
+            "Cache-Control",
+            if is_hashed {
+                format!("public, max-age={}, immutable", HASHED_MAX_AGE_SECS)
+            } else {
+                format!("public, max-age={}", PLAIN_MAX_AGE_SECS)
+            },
+        );
+        response.ok()
+    }
+}
+
+/// Tells whether the `Accept-Encoding` header value allows the encoding.
+fn accepts_encoding(accept_encoding: &str, encoding: &str) -> bool {
+    accept_encoding.split(',').any(|item| {
+        let mut parts = item.split(';').map(str::trim);
+        parts.next().map_or(false, |name| name.eq_ignore_ascii_case(encoding))
+            && parts.all(|parameter| {
+                !parameter.starts_with("q=") || parameter[2..].parse::<f32>().map_or(false, |q| q > 0.0)
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashed_path_ok() {
+        let asset = Asset {
+            path: "fontawesome-free-5.13.1-web/css/all.css",
+            hash: "0123456789abcdef",
+            content: b"",
+            gzip: None,
+            brotli: None,
+        };
+        assert_eq!(
+            asset.hashed_path(),
+            "fontawesome-free-5.13.1-web/css/all.0123456789abcdef.css"
+        );
+    }
+
+    #[test]
+    fn entity_tag_per_encoding() {
+        let asset = Asset {
+            path: "bulma.min.css",
+            hash: "0123456789abcdef",
+            content: b"",
+            gzip: None,
+            brotli: None,
+        };
+        assert_eq!(asset.entity_tag(None).tag(), "0123456789abcdef");
+        assert_eq!(asset.entity_tag(Some("br")).tag(), "0123456789abcdef-br");
+    }
+
+    #[test]
+    fn accepts_encoding_ok() {
+        assert!(accepts_encoding("gzip, deflate, br", "br"));
+        assert!(accepts_encoding("gzip;q=1.0, br;q=0.5", "br"));
+        assert!(!accepts_encoding("gzip, br;q=0", "br"));
+        assert!(!accepts_encoding("gzip, br;q=0.000", "br"));
+        assert!(!accepts_encoding("gzip", "br"));
+        assert!(!accepts_encoding("", "gzip"));
+    }
+
+    #[test]
+    fn url_ok() {
+        assert!(url("bulma.min.css").starts_with("/static/bulma.min."));
+        assert!(url("bulma.min.css").ends_with(".css"));
+        assert_ne!(url("bulma.min.css"), "/static/bulma.min.css");
+    }
+}
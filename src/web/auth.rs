@@ -0,0 +1,379 @@
+//! Web interface authentication and authorisation.
+
+use std::io::Read;
+
+use chrono::Duration;
+use ring::constant_time::verify_slices_are_equal;
+use rocket::data::{self, FromDataSimple};
+use rocket::fairing::AdHoc;
+use rocket::http::{ContentType, Cookie, Cookies, SameSite, Status};
+use rocket::request::{Form, FormItems, FromForm, FromRequest, Outcome};
+use rocket::response::{Redirect, Responder};
+use rocket::{get, post, uri, Data, FromForm, Request, Response, State};
+
+use crate::core::auth::{generate_token, hash_token, verify_password};
+use crate::core::db::api_token::ApiToken;
+use crate::prelude::*;
+use crate::settings::Role;
+use crate::web::base_path::BasePath;
+use crate::web::bearer_token::BearerToken;
+use crate::web::layout::Layout;
+use crate::web::live_settings::CurrentSettings;
+use crate::web::templates;
+use crate::web::to_html_string::ToHtmlString;
+
+const SESSION_COOKIE_NAME: &str = "session";
+
+/// Cookie which carries the CSRF token when there are no users and thus no sessions.
+const CSRF_COOKIE_NAME: &str = "csrf";
+const SESSION_DAYS: i64 = 30;
+
+/// Form field which carries the CSRF token.
+const CSRF_FIELD_NAME: &str = "_csrf";
+
+/// Limits the form size in order to not read an arbitrary large body.
+const FORM_LIMIT: u64 = 64 * 1024;
+
+/// Authenticated web interface user.
+#[derive(Clone)]
+pub struct CurrentUser {
+    /// User name, `None` when there are no users configured and thus authentication is disabled.
+    pub name: Option<String>,
+
+    pub role: Role,
+
+    /// Session's or client's token which must be submitted with every form.
+    pub csrf_token: String,
+}
+
+impl CurrentUser {
+    /// Anyone is an administrator when authentication is disabled.
+    fn anonymous(csrf_token: String) -> Self {
+        CurrentUser {
+            name: None,
+            role: Role::Admin,
+            csrf_token,
+        }
+    }
+
+    fn authenticate(request: &Request) -> Option<Self> {
+        let settings = request.guard::<CurrentSettings>().succeeded()?;
+        if settings.users.is_empty() {
+            return Some(Self::anonymous(anonymous_csrf_token(request)?));
+        }
+        let db = request.guard::<State<Connection>>().succeeded()?;
+        let token = request.cookies().get(SESSION_COOKIE_NAME)?.value().to_string();
+        let (name, csrf_token) = match db.select_session(&hash_token(&token)) {
+            Ok(session) => session?,
+            Err(error) => {
+                error!("Failed to select the session: {}", error.to_string());
+                return None;
+            }
+        };
+        // The user may have been removed from the settings since the session was started.
+        let user = settings.users.get(&name)?;
+        Some(CurrentUser {
+            name: Some(name),
+            role: user.role,
+            csrf_token,
+        })
+    }
+
+    pub fn is_csrf_token_valid(&self, csrf_token: &str) -> bool {
+        !self.csrf_token.is_empty()
+            && verify_slices_are_equal(self.csrf_token.as_bytes(), csrf_token.as_bytes()).is_ok()
+    }
+}
+
+/// Returns the client's CSRF token from its cookie, issuing a new one on the first visit.
+/// A cross-site page can neither read the cookie nor the token in the page, so it can't submit the matching token.
+fn anonymous_csrf_token(request: &Request) -> Option<String> {
+    let mut cookies = request.cookies();
+    if let Some(cookie) = cookies.get(CSRF_COOKIE_NAME) {
+        return Some(cookie.value().to_string());
+    }
+    let csrf_token = match generate_token() {
+        Ok(csrf_token) => csrf_token,
+        Err(error) => {
+            error!("Failed to generate a CSRF token: {}", error.to_string());
+            return None;
+        }
+    };
+    cookies.add(
+        Cookie::build(CSRF_COOKIE_NAME, csrf_token.clone())
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Strict)
+            .permanent()
+            .finish(),
+    );
+    Some(csrf_token)
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for CurrentUser {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        match request.local_cache(|| Self::authenticate(request)) {
+            Some(user) => Outcome::Success(user.clone()),
+            None => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// Requires the current user to have at least the specified role.
+fn require_role(request: &Request, role: Role) -> Outcome<CurrentUser, ()> {
+    match request.guard::<CurrentUser>() {
+        Outcome::Success(user) if user.role >= role => Outcome::Success(user),
+        Outcome::Success(_) => Outcome::Failure((Status::Forbidden, ())),
+        Outcome::Failure(failure) => Outcome::Failure(failure),
+        Outcome::Forward(forward) => Outcome::Forward(forward),
+    }
+}
+
+/// Allows to view the sensors.
+pub struct Viewer(pub CurrentUser);
+
+impl<'a, 'r> FromRequest<'a, 'r> for Viewer {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        require_role(request, Role::Viewer).map(Viewer)
+    }
+}
+
+/// Allows to control the services.
+pub struct Operator(pub CurrentUser);
+
+impl<'a, 'r> FromRequest<'a, 'r> for Operator {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        require_role(request, Role::Operator).map(Operator)
+    }
+}
+
+/// Allows to change anything.
+pub struct Admin(pub CurrentUser);
+
+impl<'a, 'r> FromRequest<'a, 'r> for Admin {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        require_role(request, Role::Admin).map(Admin)
+    }
+}
+
+/// Either a logged-in user or a script or a device with an API token.
+pub enum Access {
+    User(CurrentUser),
+    Token(ApiToken),
+}
+
+impl Access {
+    /// Checks whether the sensor may be read. Any user is allowed to read any sensor.
+    pub fn can_read(&self, sensor_id: &str) -> bool {
+        match self {
+            Access::User(_) => true,
+            Access::Token(token) => token.can_read(sensor_id),
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Access {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        if let Outcome::Success(BearerToken(token)) = request.guard::<BearerToken>() {
+            let db = request.guard::<State<Connection>>().succeeded();
+            return match db.and_then(|db| authenticate_api_token(&db, &token)) {
+                Some(token) => Outcome::Success(Access::Token(token)),
+                None => Outcome::Failure((Status::Unauthorized, ())),
+            };
+        }
+        require_role(request, Role::Viewer).map(Access::User)
+    }
+}
+
+/// Forbids storing the responses to logged-in users, so that neither the browser nor the service worker
+/// keeps the pages, along with their CSRF tokens, after a logout.
+pub fn no_store_for_users() -> AdHoc {
+    AdHoc::on_response("No store for users", |request, response| {
+        if let Some(CurrentUser { name: Some(_), .. }) = request.local_cache(|| None::<CurrentUser>) {
+            response.set_raw_header("Cache-Control", "private, no-store");
+        }
+    })
+}
+
+/// Tells the browser to drop its caches, including the pages kept for offline use.
+pub struct ClearCache<R>(pub R);
+
+impl<'r, R: Responder<'r>> Responder<'r> for ClearCache<R> {
+    fn respond_to(self, request: &Request) -> Result<Response<'r>, Status> {
+        let mut response = self.0.respond_to(request)?;
+        response.set_raw_header("Clear-Site-Data", r#""cache""#);
+        Ok(response)
+    }
+}
+
+/// Looks the API token up in the database and records that it's just been used.
+pub fn authenticate_api_token(db: &Connection, token: &str) -> Option<ApiToken> {
+    match db.use_api_token(&hash_token(token)) {
+        Ok(token) => token,
+        Err(error) => {
+            error!("Failed to select the API token: {}", error.to_string());
+            None
+        }
+    }
+}
+
+/// Form which is only accepted along with the valid CSRF token of the current user.
+pub struct Csrf<T>(pub T);
+
+impl<T: for<'f> FromForm<'f>> FromDataSimple for Csrf<T> {
+    type Error = String;
+
+    fn from_data(request: &Request, data: Data) -> data::Outcome<Self, Self::Error> {
+        if !request.content_type().map_or(false, ContentType::is_form) {
+            return data::Outcome::Forward(data);
+        }
+        let user = match request.guard::<CurrentUser>() {
+            Outcome::Success(user) => user,
+            _ => return data::Outcome::Failure((Status::Unauthorized, "not authenticated".into())),
+        };
+        let mut body = String::new();
+        if let Err(error) = data.open().take(FORM_LIMIT).read_to_string(&mut body) {
+            return data::Outcome::Failure((Status::BadRequest, error.to_string()));
+        }
+        let csrf_token = FormItems::from(body.as_str())
+            .find(|item| item.key.as_str() == CSRF_FIELD_NAME)
+            .and_then(|item| item.value.url_decode().ok())
+            .unwrap_or_default();
+        if !user.is_csrf_token_valid(&csrf_token) {
+            warn!("Invalid CSRF token.");
+            return data::Outcome::Failure((Status::Forbidden, "invalid CSRF token".into()));
+        }
+        match T::from_form(&mut FormItems::from(body.as_str()), false) {
+            Ok(form) => data::Outcome::Success(Csrf(form)),
+            Err(_) => data::Outcome::Failure((Status::UnprocessableEntity, "invalid form".into())),
+        }
+    }
+}
+
+/// Form without any fields besides the CSRF token.
+pub struct Empty;
+
+impl<'f> FromForm<'f> for Empty {
+    type Error = ();
+
+    fn from_form(_items: &mut FormItems<'f>, _strict: bool) -> Result<Self, Self::Error> {
+        Ok(Empty)
+    }
+}
+
+#[derive(FromForm)]
+pub struct LoginForm {
+    name: String,
+    password: String,
+    next: Option<String>,
+}
+
+#[get("/login?<next>&<is_failed>")]
+pub fn get_login(layout: Layout, next: Option<String>, is_failed: Option<bool>) -> ToHtmlString<impl ToString> {
+    ToHtmlString(templates::LoginTemplate {
+        layout,
+        next: next.unwrap_or_else(|| layout.base_path.prefixed("/")),
+        is_failed: is_failed.unwrap_or(false),
+    })
+}
+
+#[post("/login", data = "<form>")]
+pub fn post_login(
+    settings: CurrentSettings,
+    base_path: BasePath,
+    db: State<Connection>,
+    mut cookies: Cookies,
+    form: Form<LoginForm>,
+) -> Result<Redirect> {
+    let next = form
+        .next
+        .clone()
+        .filter(|next| is_local_path(next))
+        .unwrap_or_else(|| base_path.prefixed("/"));
+    let is_authenticated = settings.users.get(&form.name).map_or(false, |user| {
+        verify_password(&form.password, &user.secrets.password_hash)
+    });
+    if !is_authenticated {
+        warn!("Failed login attempt for `{}`.", form.name);
+        return Ok(Redirect::to(format!(
+            "{}?is_failed=true&next={}",
+            base_path.prefixed(uri!(get_login: _, _)),
+            rocket::http::uri::Uri::percent_encode(&next),
+        )));
+    }
+
+    let token = generate_token()?;
+    db.insert_session(
+        &hash_token(&token),
+        &form.name,
+        &generate_token()?,
+        &(Local::now() + Duration::days(SESSION_DAYS)),
+    )?;
+    cookies.add(
+        Cookie::build(SESSION_COOKIE_NAME, token)
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .permanent()
+            .finish(),
+    );
+    info!("`{}` has logged in.", form.name);
+    Ok(Redirect::to(next))
+}
+
+#[post("/logout", data = "<_form>")]
+pub fn post_logout(
+    // Authenticates before `cookies` borrows the cookie jar, the CSRF check then reuses the cached user.
+    _user: CurrentUser,
+    base_path: BasePath,
+    db: State<Connection>,
+    mut cookies: Cookies,
+    _form: Csrf<Empty>,
+) -> Result<ClearCache<Redirect>> {
+    if let Some(cookie) = cookies.get(SESSION_COOKIE_NAME) {
+        db.delete_session(&hash_token(cookie.value()))?;
+    }
+    cookies.remove(Cookie::named(SESSION_COOKIE_NAME));
+    Ok(ClearCache(Redirect::to(base_path.prefixed(uri!(get_login: _, _)))))
+}
+
+/// Checks that the redirect target is a path on the same site.
+/// Browsers treat `\` as `/` and drop tabs and newlines, so `/\evil.com` would lead to `//evil.com`.
+fn is_local_path(path: &str) -> bool {
+    path.starts_with('/') && !path.starts_with("//") && !path.contains('\\') && !path.chars().any(char::is_control)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_local_path_ok() {
+        assert!(is_local_path("/"));
+        assert!(is_local_path("/iot/sensors/test::temperature?page=1"));
+        assert!(!is_local_path("https://evil.com"));
+        assert!(!is_local_path("//evil.com"));
+        assert!(!is_local_path("/\\evil.com"));
+        assert!(!is_local_path("/\t/evil.com"));
+        assert!(!is_local_path("evil.com"));
+    }
+
+    #[test]
+    fn csrf_token_ok() {
+        let user = CurrentUser::anonymous("token".into());
+        assert!(user.is_csrf_token_valid("token"));
+        assert!(!user.is_csrf_token_valid("other"));
+        assert!(!user.is_csrf_token_valid(""));
+        assert!(!CurrentUser::anonymous(String::new()).is_csrf_token_valid(""));
+    }
+}
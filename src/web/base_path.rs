@@ -0,0 +1,71 @@
+use std::fmt::Display;
+
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, State};
+
+use crate::web::assets;
+
+/// URL prefix of the web interface, for example: `/iot`. It's empty when served at the root.
+/// It's managed by the Rocket instance, so that each instance is able to have its own.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct BasePath(String);
+
+impl BasePath {
+    /// Makes sure that the base path starts with a slash and doesn't end with one.
+    pub fn new(base_path: &str) -> Self {
+        let base_path = base_path.trim_matches('/');
+        if base_path.is_empty() {
+            BasePath(String::new())
+        } else {
+            BasePath(format!("/{}", base_path))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the absolute path of the page, taking the base path into account.
+    pub fn prefixed(&self, path: impl Display) -> String {
+        format!("{}{}", self.0, path)
+    }
+
+    /// Returns the hashed URL of the asset, for example: `"bulma.min.css"`.
+    pub fn asset(&self, path: &str) -> String {
+        self.prefixed(assets::url(path))
+    }
+
+    /// Strips the base path off the request path.
+    pub fn strip<'p>(&self, path: &'p str) -> &'p str {
+        path.strip_prefix(self.0.as_str()).unwrap_or(path)
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for BasePath {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        request
+            .guard::<State<BasePath>>()
+            .map(|base_path| base_path.inner().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_ok() {
+        assert_eq!(BasePath::new("").as_str(), "");
+        assert_eq!(BasePath::new("/").as_str(), "");
+        assert_eq!(BasePath::new("iot/").as_str(), "/iot");
+        assert_eq!(BasePath::new("/my/iot").as_str(), "/my/iot");
+    }
+
+    #[test]
+    fn prefixed_ok() {
+        assert_eq!(BasePath::new("/iot").prefixed("/sensors"), "/iot/sensors");
+        assert_eq!(BasePath::default().prefixed("/sensors"), "/sensors");
+    }
+}
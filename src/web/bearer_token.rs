@@ -0,0 +1,21 @@
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+
+/// Extracts a bearer token from the [`Authorization`](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Authorization)
+/// header.
+pub struct BearerToken(pub String);
+
+impl<'a, 'r> FromRequest<'a, 'r> for BearerToken {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        match request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+        {
+            Some(token) => Outcome::Success(BearerToken(token.trim().into())),
+            None => Outcome::Forward(()),
+        }
+    }
+}
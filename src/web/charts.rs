@@ -0,0 +1,128 @@
+//! Charts which overlay several sensors.
+
+use chrono::Duration;
+use rocket::http::uri::Uri;
+use rocket::{get, State};
+
+use crate::prelude::*;
+use crate::settings::{default_chart_minutes, Chart};
+use crate::web::auth::Viewer;
+use crate::web::base_path::BasePath;
+use crate::web::layout::Layout;
+use crate::web::live_settings::CurrentSettings;
+use crate::web::templates;
+use crate::web::to_html_string::ToHtmlString;
+
+/// Shows the chart of the comma-separated sensor IDs.
+#[get("/chart?<sensor_ids>&<minutes>&<compare_minutes>")]
+pub fn get_chart(
+    _user: Viewer,
+    layout: Layout,
+    settings: CurrentSettings,
+    db: State<Connection>,
+    sensor_ids: Option<String>,
+    minutes: Option<i64>,
+    compare_minutes: Option<i64>,
+) -> Result<ToHtmlString<impl ToString>> {
+    let chart = Chart {
+        sensor_ids: sensor_ids
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|sensor_id| !sensor_id.is_empty())
+            .map(String::from)
+            .collect(),
+        minutes: minutes.unwrap_or_else(default_chart_minutes),
+        compare_minutes: compare_minutes.filter(|compare_minutes| *compare_minutes != 0),
+    };
+    Ok(ToHtmlString(templates::ChartTemplate {
+        chart_html: render(&db, "0", &chart, settings.max_chart_points)?,
+        sensor_ids: chart.sensor_ids.join(","),
+        minutes: chart.minutes,
+        compare_minutes: chart.compare_minutes.unwrap_or(0),
+        layout,
+    }))
+}
+
+/// Renders the chart, skipping the missing and non-numeric sensors.
+/// Each series is downsampled to `max_points`.
+pub fn render(db: &Connection, id: &str, chart: &Chart, max_points: usize) -> Result<String> {
+    let now = Local::now();
+    let since = now - Duration::minutes(chart.minutes);
+    let mut series = Vec::new();
+    for (color_index, sensor_id) in chart.sensor_ids.iter().enumerate() {
+        let (sensor, reading) = match db.select_sensor(sensor_id)? {
+            Some((sensor, reading)) if reading.value.is_f64() => (sensor, reading),
+            _ => continue,
+        };
+        let multiplier = reading.value.chart_multiplier();
+        let unit = reading.value.chart_unit();
+        series.push(templates::Series {
+            label: sensor.title(),
+            unit,
+            values: scale(db.select_values(sensor_id, &since)?, multiplier, Duration::zero()),
+            is_dashed: false,
+            color_index,
+        });
+        if let Some(compare_minutes) = chart.compare_minutes {
+            // The earlier values are shifted forward in order to overlay the current ones.
+            let shift = Duration::minutes(compare_minutes);
+            series.push(templates::Series {
+                label: format!("{}, {} earlier", sensor.title(), format_minutes(compare_minutes)),
+                unit,
+                values: scale(
+                    db.select_values_between(sensor_id, &(since - shift), &(now - shift))?,
+                    multiplier,
+                    shift,
+                ),
+                is_dashed: true,
+                color_index,
+            });
+        }
+    }
+    Ok(templates::OverlayChartPartialTemplate::new(id.into(), series, max_points).to_string())
+}
+
+/// Builds the bookmarkable chart URL.
+pub fn chart_url(base_path: &BasePath, chart: &Chart) -> String {
+    let mut url = base_path.prefixed(format!(
+        "/chart?sensor_ids={}&minutes={}",
+        Uri::percent_encode(&chart.sensor_ids.join(",")),
+        chart.minutes,
+    ));
+    if let Some(compare_minutes) = chart.compare_minutes {
+        url.push_str(&format!("&compare_minutes={}", compare_minutes));
+    }
+    url
+}
+
+fn scale(values: Vec<(DateTime<Local>, f64)>, multiplier: f64, shift: Duration) -> Vec<(DateTime<Local>, f64)> {
+    values
+        .into_iter()
+        .map(|(timestamp, value)| (timestamp + shift, value * multiplier))
+        .collect()
+}
+
+/// Formats the period in the largest whole units, for example: `1 day`.
+fn format_minutes(minutes: i64) -> String {
+    let (value, unit) = match minutes {
+        _ if minutes % 10080 == 0 => (minutes / 10080, "week"),
+        _ if minutes % 1440 == 0 => (minutes / 1440, "day"),
+        _ if minutes % 60 == 0 => (minutes / 60, "hour"),
+        _ => (minutes, "minute"),
+    };
+    format!("{} {}{}", value, unit, if value == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_minutes_ok() {
+        assert_eq!(format_minutes(1440), "1 day");
+        assert_eq!(format_minutes(120), "2 hours");
+        assert_eq!(format_minutes(90), "90 minutes");
+    }
+}
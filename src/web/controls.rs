@@ -0,0 +1,140 @@
+//! Lets the operators control the sensors from the web interface.
+
+use std::time::Duration;
+
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::{post, FromForm, State};
+use rocket_contrib::json::Json;
+use serde_json::json;
+
+use crate::core::control::Control;
+use crate::core::writes;
+use crate::prelude::*;
+use crate::web::auth::{Csrf, Operator};
+use crate::web::ingest::parse_value;
+
+/// How long to wait for the service to report the outcome of a write.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Raw value as it's submitted by a control input.
+#[derive(FromForm)]
+pub struct WriteForm {
+    value: String,
+}
+
+/// Sends the submitted value to the sensor service as a `Write` message and waits for the outcome.
+/// Responds with `202 Accepted` if the service hasn't reported it in time, the new state then arrives later.
+#[post("/sensors/<sensor_id>/write", data = "<form>")]
+pub fn post_write(
+    _user: Operator,
+    db: State<Connection>,
+    tx: State<Sender>,
+    sensor_id: String,
+    form: Csrf<WriteForm>,
+) -> Result<Option<Custom<Json<serde_json::Value>>>> {
+    let Csrf(form) = form;
+    let (sensor, reading) = match db.select_sensor(&sensor_id)? {
+        Some(actual) => actual,
+        None => return Ok(None),
+    };
+    let control = match sensor.control {
+        Some(ref control) => control,
+        None => return Ok(None),
+    };
+    let value = match control.parse(&reading.value, &form.value) {
+        Ok(value) => value,
+        Err(error) => return Ok(Some(Custom(Status::BadRequest, Json(json!({ "error": error }))))),
+    };
+    let value_html = value.to_string();
+    let message = Message {
+        type_: MessageType::Write,
+        sensor,
+        reading: Reading {
+            timestamp: Local::now(),
+            value,
+        },
+    };
+    Ok(Some(match writes::send_and_wait(message, &tx, WRITE_TIMEOUT) {
+        Some(Ok(())) => Custom(Status::Ok, Json(json!({ "value_html": value_html }))),
+        Some(Err(error)) => Custom(Status::BadGateway, Json(json!({ "error": error }))),
+        None => Custom(Status::Accepted, Json(json!({ "value_html": value_html }))),
+    }))
+}
+
+impl Control {
+    /// Builds the value of the same type as the current one, and checks that it's allowed.
+    pub fn parse(&self, current: &Value, raw: &str) -> Result<Value, String> {
+        let kind = match self {
+            Control::Toggle => "Boolean".to_string(),
+            _ => serde_json::to_value(current)
+                .ok()
+                .and_then(|current| current["type"].as_str().map(ToString::to_string))
+                .ok_or_else(|| "the current value has no type".to_string())?,
+        };
+        let value = parse_value(&kind, Some(raw)).map_err(|_| format!("«{}» is not a valid {}", raw, kind))?;
+        self.validate(&value)?;
+        Ok(value)
+    }
+
+    fn validate(&self, value: &Value) -> Result<(), String> {
+        match self {
+            Control::Toggle => Ok(()),
+            Control::Slider { min, max, .. } => validate_range(value, Some(*min), Some(*max)),
+            Control::Number { min, max, .. } => validate_range(value, *min, *max),
+            Control::Select { options } => match value {
+                Value::Text(text) if options.contains(text) => Ok(()),
+                _ => Err(format!("the value must be one of: {}", options.join(", "))),
+            },
+        }
+    }
+}
+
+fn validate_range(value: &Value, min: Option<f64>, max: Option<f64>) -> Result<(), String> {
+    let number = value.to_f64().ok_or_else(|| "the value is not a number".to_string())?;
+    if min.map_or(false, |min| number < min) || max.map_or(false, |max| number > max) {
+        return Err(format!(
+            "the value must be between {} and {}",
+            min.map_or_else(|| "−∞".to_string(), |min| min.to_string()),
+            max.map_or_else(|| "∞".to_string(), |max| max.to_string()),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_toggle_ok() {
+        assert_eq!(Control::Toggle.parse(&Value::None, "true"), Ok(Value::Boolean(true)));
+    }
+
+    #[test]
+    fn parse_slider_keeps_type() {
+        let control = Control::Slider {
+            min: 5.0,
+            max: 25.0,
+            step: 0.5,
+        };
+        assert_eq!(
+            control.parse(&Value::Temperature(18.0), "21.5"),
+            Ok(Value::Temperature(21.5)),
+        );
+        assert!(control.parse(&Value::Temperature(18.0), "30").is_err());
+        assert!(control.parse(&Value::Temperature(18.0), "warm").is_err());
+    }
+
+    #[test]
+    fn parse_select_ok() {
+        let control = Control::Select {
+            options: vec!["eco".into(), "comfort".into()],
+        };
+        assert_eq!(
+            control.parse(&Value::Text("eco".into()), "comfort"),
+            Ok(Value::Text("comfort".into())),
+        );
+        assert!(control.parse(&Value::Text("eco".into()), "boost").is_err());
+    }
+}
@@ -0,0 +1,244 @@
+//! User-defined dashboards.
+
+use chrono::Duration;
+use rocket::response::Redirect;
+use rocket::{delete, get, post, uri, FromForm, Responder, State};
+
+use crate::prelude::*;
+use crate::settings::{Dashboard, Settings};
+use crate::web::auth::{Admin, Csrf, Empty, Viewer};
+use crate::web::base_path::BasePath;
+use crate::web::charts;
+use crate::web::layout::Layout;
+use crate::web::live_settings::CurrentSettings;
+use crate::web::templates;
+use crate::web::to_html_string::ToHtmlString;
+
+/// Shown in the editor when a new dashboard is being created.
+const EXAMPLE_CONTENT: &str = r#"title = "Climate"
+is_home = false
+
+[[sections]]
+title = "Living Room"
+
+[[sections.tiles]]
+sensor_id = "buienradar::6240::temperature"
+size = "Large"
+chart_minutes = 60
+"#;
+
+/// Dashboard along with its slug.
+pub struct NamedDashboard {
+    pub slug: String,
+    pub dashboard: Dashboard,
+
+    /// Dashboards from the settings can't be edited in the web interface.
+    pub is_editable: bool,
+}
+
+#[derive(FromForm)]
+pub struct DashboardForm {
+    slug: String,
+
+    /// Dashboard in TOML.
+    content: String,
+}
+
+#[derive(Responder)]
+pub enum EditorResponse {
+    Saved(Redirect),
+    Invalid(ToHtmlString<String>),
+}
+
+/// Selects the dashboards from both the settings and the database, ordered by title.
+/// The settings take precedence in case of the same slug.
+pub fn select_dashboards(settings: &Settings, db: &Connection) -> Result<Vec<NamedDashboard>> {
+    let mut dashboards: Vec<NamedDashboard> = settings
+        .dashboards
+        .iter()
+        .map(|(slug, dashboard)| NamedDashboard {
+            slug: slug.clone(),
+            dashboard: dashboard.clone(),
+            is_editable: false,
+        })
+        .collect();
+    for (slug, dashboard) in db.select_dashboards::<Dashboard>()? {
+        if !settings.dashboards.contains_key(&slug) {
+            dashboards.push(NamedDashboard {
+                slug,
+                dashboard,
+                is_editable: true,
+            });
+        }
+    }
+    dashboards.sort_by(|lhs, rhs| lhs.dashboard.title.cmp(&rhs.dashboard.title));
+    Ok(dashboards)
+}
+
+#[get("/dashboards/<slug>")]
+pub fn get_dashboard(
+    _user: Viewer,
+    layout: Layout,
+    settings: CurrentSettings,
+    db: State<Connection>,
+    slug: String,
+) -> Result<Option<ToHtmlString<impl ToString>>> {
+    Ok(
+        match select_dashboards(&settings, &db)?
+            .into_iter()
+            .find(|dashboard| dashboard.slug == slug)
+        {
+            Some(dashboard) => Some(ToHtmlString(render(layout, &db, dashboard, settings.max_chart_points)?)),
+            None => None,
+        },
+    )
+}
+
+/// Shows the dashboard editor, for a new dashboard if the slug is not specified.
+#[get("/dashboards/edit?<slug>")]
+pub fn get_dashboard_editor(
+    _user: Admin,
+    layout: Layout,
+    db: State<Connection>,
+    slug: Option<String>,
+) -> Result<Option<ToHtmlString<impl ToString>>> {
+    let content = match slug {
+        Some(ref slug) => match db
+            .select_dashboards::<Dashboard>()?
+            .into_iter()
+            .find(|(other_slug, _)| other_slug == slug)
+        {
+            Some((_, dashboard)) => toml::to_string_pretty(&dashboard)?,
+            None => return Ok(None),
+        },
+        None => EXAMPLE_CONTENT.into(),
+    };
+    Ok(Some(ToHtmlString(templates::DashboardEditorTemplate {
+        layout,
+        slug: slug.unwrap_or_default(),
+        content,
+        error: None,
+    })))
+}
+
+#[post("/dashboards", data = "<form>")]
+pub fn post_dashboard(
+    _user: Admin,
+    layout: Layout,
+    settings: CurrentSettings,
+    db: State<Connection>,
+    form: Csrf<DashboardForm>,
+) -> Result<EditorResponse> {
+    let Csrf(form) = form;
+    let slug = slug::slugify(&form.slug);
+    let error = if slug.is_empty() {
+        Some("The slug must not be empty".to_string())
+    } else if settings.dashboards.contains_key(&slug) {
+        Some("The dashboard is defined in the settings and can't be changed here".to_string())
+    } else {
+        match toml::from_str::<Dashboard>(&form.content) {
+            Ok(dashboard) => {
+                db.upsert_dashboard(&slug, &dashboard)?;
+                info!("Dashboard `{}` has been saved.", slug);
+                return Ok(EditorResponse::Saved(Redirect::to(
+                    layout.base_path.prefixed(uri!(get_dashboard: slug)),
+                )));
+            }
+            Err(error) => Some(error.to_string()),
+        }
+    };
+    Ok(EditorResponse::Invalid(ToHtmlString(
+        templates::DashboardEditorTemplate {
+            layout,
+            slug,
+            content: form.content,
+            error,
+        }
+        .to_string(),
+    )))
+}
+
+#[delete("/dashboards/<slug>", data = "<_form>")]
+pub fn delete_dashboard(
+    _user: Admin,
+    base_path: BasePath,
+    db: State<Connection>,
+    slug: String,
+    _form: Csrf<Empty>,
+) -> Result<Redirect> {
+    db.delete_dashboard(&slug)?;
+    info!("Dashboard `{}` has been deleted.", slug);
+    Ok(Redirect::to(base_path.prefixed("/")))
+}
+
+/// Renders the dashboard tiles, skipping the missing sensors.
+/// The charts are downsampled to `max_chart_points` each.
+pub fn render(
+    layout: Layout,
+    db: &Connection,
+    dashboard: NamedDashboard,
+    max_chart_points: usize,
+) -> Result<templates::DashboardTemplate> {
+    let mut sections = Vec::new();
+    for (section_index, section) in dashboard.dashboard.sections.iter().enumerate() {
+        let mut tiles = Vec::new();
+        for (tile_index, tile) in section.tiles.iter().enumerate() {
+            let (sensor, reading) = match db.select_sensor(&tile.sensor_id)? {
+                Some(actual) => actual,
+                None => continue,
+            };
+            let chart = match tile.chart_minutes {
+                Some(minutes) if reading.value.is_f64() => templates::SparklinePartialTemplate::new(
+                    format!("{}-{}", section_index, tile_index),
+                    db.select_values(&sensor.id, &(Local::now() - Duration::minutes(minutes)))?,
+                    reading.value.chart_multiplier(),
+                    max_chart_points,
+                )
+                .to_string(),
+                _ => String::new(),
+            };
+            tiles.push(templates::render_dashboard_tile(
+                &layout.base_path,
+                &sensor,
+                &reading,
+                tile.size,
+                chart,
+            ));
+        }
+        if let Some(tag) = &section.tag {
+            for (sensor, reading) in db.select_actuals_by(None, None, Some(tag))? {
+                tiles.push(templates::render_dashboard_tile(
+                    &layout.base_path,
+                    &sensor,
+                    &reading,
+                    None,
+                    String::new(),
+                ));
+            }
+        }
+        let mut charts = Vec::new();
+        for (chart_index, chart) in section.charts.iter().enumerate() {
+            charts.push((
+                charts::chart_url(&layout.base_path, chart),
+                charts::render(
+                    db,
+                    &format!("{}-{}", section_index, chart_index),
+                    chart,
+                    max_chart_points,
+                )?,
+            ));
+        }
+        sections.push(templates::DashboardSectionPartial {
+            title: section.title.clone(),
+            tiles,
+            charts,
+        });
+    }
+    Ok(templates::DashboardTemplate {
+        layout,
+        slug: dashboard.slug,
+        title: dashboard.dashboard.title,
+        sections,
+        is_editable: dashboard.is_editable,
+    })
+}
@@ -0,0 +1,317 @@
+//! Energy page: increments of the cumulative energy meters, such as the YouLess counters.
+//!
+//! The meters report their running totals, so the consumption within a period is the difference
+//! between the latest readings as of its boundaries. This takes an index lookup per boundary,
+//! instead of scanning all the readings within the period.
+
+use chrono::Duration;
+use itertools::Itertools;
+use rocket::{get, State};
+
+use crate::core::datetime;
+use crate::prelude::*;
+use crate::web::auth::Viewer;
+use crate::web::layout::Layout;
+use crate::web::live_settings::CurrentSettings;
+use crate::web::templates;
+use crate::web::to_html_string::ToHtmlString;
+
+/// Format of the `since` and `until` dates.
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Default period, including the current day.
+const DEFAULT_DAYS: i64 = 30;
+
+/// The calendar heatmap covers a year of full weeks, ending with the last day of the period.
+const HEATMAP_WEEKS: i64 = 53;
+
+/// The dates are clamped to the years since 1970 and up to a year ahead,
+/// so that the period arithmetic can't overflow.
+const MIN_YEAR: i32 = 1970;
+const MAX_DAYS_AHEAD: i64 = 366;
+
+/// Number of the heatmap colors, besides the one of the days without consumption.
+const HEATMAP_LEVELS: usize = 4;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Resolution {
+    Hour,
+    Day,
+}
+
+impl Resolution {
+    fn parse(resolution: Option<&str>) -> Self {
+        match resolution {
+            Some("hour") => Resolution::Hour,
+            _ => Resolution::Day,
+        }
+    }
+
+    fn step(self) -> Duration {
+        match self {
+            Resolution::Hour => Duration::hours(1),
+            Resolution::Day => Duration::days(1),
+        }
+    }
+
+    /// Longer periods get shortened, so that the page stays reasonably fast and the bars stay readable.
+    fn max_days(self) -> i64 {
+        match self {
+            Resolution::Hour => 7,
+            Resolution::Day => 366,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Resolution::Hour => "hour",
+            Resolution::Day => "day",
+        }
+    }
+}
+
+/// Consumption and production within an hour or a day, in kWh.
+pub struct Bar {
+    pub label: String,
+    pub consumption: f64,
+    pub production: f64,
+}
+
+/// Day of the calendar heatmap.
+pub struct HeatmapDay {
+    pub date: NaiveDate,
+
+    /// Consumption in kWh.
+    pub consumption: f64,
+
+    /// Color level, `0` stands for no consumption.
+    pub level: usize,
+}
+
+/// Shows the consumption and production by hour or by day, and the daily consumption over the last year.
+/// The dates are inclusive and interpreted in the display time zone.
+#[get("/energy?<since>&<until>&<resolution>")]
+pub fn get_energy(
+    _user: Viewer,
+    layout: Layout,
+    db: State<Connection>,
+    settings: CurrentSettings,
+    since: Option<String>,
+    until: Option<String>,
+    resolution: Option<String>,
+) -> Result<ToHtmlString<impl ToString>> {
+    let resolution = Resolution::parse(resolution.as_deref());
+    let until = parse_date(until.as_deref()).unwrap_or_else(today);
+    let since = parse_date(since.as_deref())
+        .unwrap_or_else(|| until - Duration::days(DEFAULT_DAYS - 1))
+        .min(until)
+        .max(until - Duration::days(resolution.max_days() - 1));
+
+    let consumption_ids = select_sensor_ids(&db, &settings.energy.consumption)?;
+    let production_ids = select_sensor_ids(&db, &settings.energy.production)?;
+
+    let boundaries = period_boundaries(since, until, resolution);
+    let consumption = select_increments(&db, &consumption_ids, &boundaries)?;
+    let production = select_increments(&db, &production_ids, &boundaries)?;
+    let format = datetime::datetime_format();
+    let bars = boundaries
+        .iter()
+        .zip(consumption)
+        .zip(production)
+        .map(|(((boundary, _), consumption), production)| Bar {
+            label: match resolution {
+                Resolution::Hour => boundary
+                    .format(&format!("{} {}", format.date_strftime(), format.hour_strftime()))
+                    .to_string(),
+                Resolution::Day => boundary.format(format.date_strftime()).to_string(),
+            },
+            consumption: consumption * WH_IN_JOULE / 1000.0,
+            production: production * WH_IN_JOULE / 1000.0,
+        })
+        .collect_vec();
+
+    let heatmap_since = heatmap_since(until);
+    let heatmap_boundaries = period_boundaries(heatmap_since, until, Resolution::Day);
+    let heatmap = to_heatmap(
+        heatmap_boundaries
+            .iter()
+            .map(|(boundary, _)| boundary.date())
+            .zip(select_increments(&db, &consumption_ids, &heatmap_boundaries)?)
+            .map(|(date, consumption)| (date, consumption * WH_IN_JOULE / 1000.0))
+            .collect(),
+    );
+
+    Ok(ToHtmlString(templates::EnergyTemplate {
+        layout,
+        since: since.format(DATE_FORMAT).to_string(),
+        until: until.format(DATE_FORMAT).to_string(),
+        resolution,
+        total_consumption: bars.iter().map(|bar| bar.consumption).sum(),
+        total_production: bars.iter().map(|bar| bar.production).sum(),
+        has_meters: !consumption_ids.is_empty() || !production_ids.is_empty(),
+        bars,
+        heatmap,
+    }))
+}
+
+fn parse_date(date: Option<&str>) -> Option<NaiveDate> {
+    date.and_then(|date| NaiveDate::parse_from_str(date, DATE_FORMAT).ok())
+        .map(|date| {
+            date.max(NaiveDate::from_ymd(MIN_YEAR, 1, 1))
+                .min(today() + Duration::days(MAX_DAYS_AHEAD))
+        })
+}
+
+/// Returns the current date in the display time zone.
+fn today() -> NaiveDate {
+    datetime::to_display(&Local::now()).date().naive_local()
+}
+
+/// Returns the Monday which starts the heatmap.
+fn heatmap_since(until: NaiveDate) -> NaiveDate {
+    let since = until - Duration::weeks(HEATMAP_WEEKS - 1);
+    since - Duration::days(since.weekday().num_days_from_monday() as i64)
+}
+
+/// Returns the energy sensors which match any of the patterns.
+fn select_sensor_ids(db: &Connection, patterns: &[String]) -> Result<Vec<String>> {
+    let mut sensor_ids = Vec::new();
+    for pattern in patterns {
+        for (sensor, reading) in db.select_actuals_by(None, Some(pattern), None)? {
+            if let Value::Energy(_) = reading.value {
+                sensor_ids.push(sensor.id);
+            }
+        }
+    }
+    Ok(sensor_ids.into_iter().unique().collect())
+}
+
+/// Returns the wall-clock starts of the hours or days within the dates, including the end of the last one.
+/// The starts which don't exist in the display time zone, because of a daylight saving time transition,
+/// are skipped, so that the previous period gets longer.
+fn period_boundaries(
+    since: NaiveDate,
+    until: NaiveDate,
+    resolution: Resolution,
+) -> Vec<(NaiveDateTime, DateTime<Local>)> {
+    let end = (until + Duration::days(1)).and_hms(0, 0, 0);
+    let mut boundary = since.and_hms(0, 0, 0);
+    let mut boundaries = Vec::new();
+    while boundary <= end {
+        if let Some(timestamp) = datetime::from_display(&boundary) {
+            boundaries.push((boundary, timestamp));
+        }
+        boundary += resolution.step();
+    }
+    boundaries
+}
+
+/// Returns the sum of the sensor increments between the consecutive boundaries, in joules.
+/// The result has one element less than the boundaries.
+fn select_increments(
+    db: &Connection,
+    sensor_ids: &[String],
+    boundaries: &[(NaiveDateTime, DateTime<Local>)],
+) -> Result<Vec<f64>> {
+    let timestamps = boundaries.iter().map(|(_, timestamp)| *timestamp).collect_vec();
+    let mut totals = vec![0.0; timestamps.len().saturating_sub(1)];
+    for sensor_id in sensor_ids {
+        for (total, increment) in totals
+            .iter_mut()
+            .zip(increments(&db.select_values_at(sensor_id, &timestamps)?))
+        {
+            *total += increment;
+        }
+    }
+    Ok(totals)
+}
+
+/// Turns the cumulative values into the increments between them.
+/// A missing value counts as no increment. A decrease means that the meter has restarted from zero,
+/// for example, when it got replaced, so the next value is the increment.
+fn increments(values: &[Option<f64>]) -> Vec<f64> {
+    values
+        .iter()
+        .tuple_windows()
+        .map(|(previous, next)| match (previous, next) {
+            (Some(previous), Some(next)) if next >= previous => next - previous,
+            (Some(_), Some(next)) => *next,
+            _ => 0.0,
+        })
+        .collect()
+}
+
+/// Assigns the color levels relative to the maximum daily consumption.
+fn to_heatmap(days: Vec<(NaiveDate, f64)>) -> Vec<HeatmapDay> {
+    let max_consumption = days.iter().map(|(_, consumption)| *consumption).fold(0.0, f64::max);
+    days.into_iter()
+        .map(|(date, consumption)| HeatmapDay {
+            date,
+            consumption,
+            level: if consumption > 0.0 {
+                ((consumption / max_consumption * HEATMAP_LEVELS as f64).ceil() as usize)
+                    .max(1)
+                    .min(HEATMAP_LEVELS)
+            } else {
+                0
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn increments_ok() {
+        assert_eq!(
+            increments(&[None, Some(1.0), Some(3.0), Some(3.5), Some(0.5), Some(1.0)]),
+            vec![0.0, 2.0, 0.5, 0.5, 0.5]
+        );
+        assert!(increments(&[]).is_empty());
+    }
+
+    #[test]
+    fn increments_reset_ok() {
+        assert_eq!(
+            increments(&[Some(100.0), Some(102.0), Some(1.5), Some(4.0)]),
+            vec![2.0, 1.5, 2.5]
+        );
+    }
+
+    #[test]
+    fn heatmap_since_is_monday() {
+        let since = heatmap_since(NaiveDate::from_ymd(2020, 10, 21));
+        assert_eq!(since.weekday(), Weekday::Mon);
+        assert_eq!(since, NaiveDate::from_ymd(2019, 10, 21));
+    }
+
+    #[test]
+    fn parse_date_clamped() {
+        assert_eq!(parse_date(Some("2020-10-19")), Some(NaiveDate::from_ymd(2020, 10, 19)));
+        assert_eq!(parse_date(Some("0001-01-01")), Some(NaiveDate::from_ymd(1970, 1, 1)));
+        assert_eq!(
+            parse_date(Some("+262143-12-31")),
+            Some(today() + Duration::days(MAX_DAYS_AHEAD))
+        );
+        assert_eq!(parse_date(Some("tomorrow")), None);
+    }
+
+    #[test]
+    fn to_heatmap_ok() {
+        let date = NaiveDate::from_ymd(2020, 10, 19);
+        let levels = to_heatmap(vec![(date, 0.0), (date, 1.0), (date, 5.0), (date, 8.0)])
+            .iter()
+            .map(|day| day.level)
+            .collect_vec();
+        assert_eq!(levels, vec![0, 1, 3, 4]);
+    }
+
+    #[test]
+    fn resolution_ok() {
+        assert_eq!(Resolution::parse(Some("hour")), Resolution::Hour);
+        assert_eq!(Resolution::parse(Some("week")), Resolution::Day);
+        assert_eq!(Resolution::parse(None), Resolution::Day);
+    }
+}
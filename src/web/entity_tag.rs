@@ -6,3 +6,17 @@ impl Reading {
         EntityTag::new(true, format!("{:x}", self.timestamp.timestamp_millis()))
     }
 }
+
+/// Builds an entity tag for a list of readings.
+/// It changes whenever any of the readings is updated, or a reading is added or removed.
+pub fn entity_tag_of<'a, I: IntoIterator<Item = &'a Reading>>(readings: I) -> EntityTag {
+    let (count, max_timestamp) = readings.into_iter().fold((0_usize, 0_i64), |(count, max), reading| {
+        (count + 1, max.max(reading.timestamp.timestamp_millis()))
+    });
+    EntityTag::new(true, format!("{:x}-{:x}", count, max_timestamp))
+}
+
+/// Makes the entity tag change whenever sensors become stale, which happens without any new readings.
+pub fn with_stale_count(entity_tag: EntityTag, stale_count: usize) -> EntityTag {
+    EntityTag::new(entity_tag.weak, format!("{}-{:x}", entity_tag.tag(), stale_count))
+}
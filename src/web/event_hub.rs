@@ -0,0 +1,238 @@
+//! Forwards the bus messages to the web clients.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crossbeam::channel;
+
+use crate::prelude::*;
+
+/// That many recent events are kept to let clients resume after reconnecting.
+const RECENT_EVENT_COUNT: usize = 1000;
+
+/// That many events may wait for a subscriber, a slower one gets dropped and has to reconnect.
+const SUBSCRIBER_CAPACITY: usize = 100;
+
+/// Each event stream occupies a web server worker, the rest are left for the other requests.
+pub const MAX_SUBSCRIPTIONS: usize = 16;
+
+/// Message with its sequential event ID.
+pub type Event = (u64, Message);
+
+/// Event ID as seen by the clients: the hub epoch and the sequential ID.
+/// The epoch tells apart the IDs issued before a restart.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct EventId {
+    pub epoch: i64,
+    pub id: u64,
+}
+
+/// Keeps the recent messages and forwards the new ones to the event stream subscribers.
+#[derive(Clone)]
+pub struct EventHub {
+    /// Start time in milliseconds.
+    epoch: i64,
+
+    inner: Arc<Mutex<Inner>>,
+
+    /// Number of the active subscriptions.
+    subscription_count: Arc<AtomicUsize>,
+}
+
+#[derive(Default)]
+struct Inner {
+    last_id: u64,
+    recent: VecDeque<Event>,
+    subscribers: Vec<channel::Sender<Event>>,
+}
+
+/// New events along with the missed ones. Dropping it releases the subscription slot.
+pub struct Subscription {
+    pub epoch: i64,
+    pub missed: Vec<Event>,
+    pub rx: channel::Receiver<Event>,
+    subscription_count: Arc<AtomicUsize>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.subscription_count.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Default for EventHub {
+    fn default() -> Self {
+        EventHub {
+            epoch: Local::now().timestamp_millis(),
+            inner: Default::default(),
+            subscription_count: Default::default(),
+        }
+    }
+}
+
+impl EventHub {
+    /// Spawns the thread which listens to the bus and publishes the messages.
+    pub fn spawn(&self, bus: &mut Bus) -> Result {
+        info!("Spawning web event hub…");
+        let rx = bus.add_rx("system::web::events");
+        let hub = self.clone();
+        thread::Builder::new()
+            .name("system::web::events".into())
+            .spawn(move || {
+                for message in &rx {
+                    hub.publish(message);
+                }
+                unreachable!();
+            })?;
+        Ok(())
+    }
+
+    /// Assigns the next event ID to the message and sends it out to the subscribers.
+    pub fn publish(&self, message: Message) {
+        match (message.type_, &message.reading.value) {
+            (MessageType::Write, _) | (_, Value::Blob(..)) => return,
+            _ => {}
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.last_id += 1;
+        let event = (inner.last_id, message);
+
+        // Dropped receivers mean that the clients have disconnected.
+        // Full ones mean that the clients don't keep up, they'll resume after reconnecting.
+        inner.subscribers.retain(|tx| tx.try_send(event.clone()).is_ok());
+
+        if inner.recent.len() == RECENT_EVENT_COUNT {
+            inner.recent.pop_front();
+        }
+        inner.recent.push_back(event);
+    }
+
+    /// Subscribes to the new events, unless there are too many subscriptions already.
+    /// Also returns the recent events that followed the last seen event, if any.
+    /// All the recent events followed an event seen before a restart.
+    pub fn subscribe(&self, last_event_id: Option<EventId>) -> Option<Subscription> {
+        if self.subscription_count.fetch_add(1, Ordering::SeqCst) >= MAX_SUBSCRIPTIONS {
+            self.subscription_count.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        let subscription_count = self.subscription_count.clone();
+
+        let (tx, rx) = channel::bounded(SUBSCRIBER_CAPACITY);
+        let mut inner = self.inner.lock().unwrap();
+        let missed = match last_event_id {
+            Some(EventId { epoch, id: last_id }) => inner
+                .recent
+                .iter()
+                .filter(|(id, _)| epoch != self.epoch || *id > last_id)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        inner.subscribers.push(tx);
+        Some(Subscription {
+            epoch: self.epoch,
+            missed,
+            rx,
+            subscription_count,
+        })
+    }
+
+    /// Returns the recent readings of the sensor, the newest first.
+    /// It's the only history of the sensors which are not logged into the database.
+    pub fn recent_readings(&self, sensor_id: &str) -> Vec<Reading> {
+        self.inner
+            .lock()
+            .unwrap()
+            .recent
+            .iter()
+            .rev()
+            .filter(|(_, message)| message.sensor.id == sensor_id)
+            .map(|(_, message)| message.reading.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscriber_receives_new_event() {
+        let hub = EventHub::default();
+        let subscription = hub.subscribe(None).unwrap();
+        hub.publish(Message::new("test"));
+        assert!(subscription.missed.is_empty());
+        assert_eq!(subscription.rx.try_recv().unwrap().0, 1);
+    }
+
+    #[test]
+    fn subscriber_resumes_after_last_event() {
+        let hub = EventHub::default();
+        hub.publish(Message::new("test::1"));
+        hub.publish(Message::new("test::2"));
+        let subscription = hub
+            .subscribe(Some(EventId {
+                epoch: hub.epoch,
+                id: 1,
+            }))
+            .unwrap();
+        assert_eq!(subscription.missed.len(), 1);
+        assert_eq!(subscription.missed[0].1.sensor.id, "test::2");
+    }
+
+    #[test]
+    fn subscriber_resumes_after_restart() {
+        let hub = EventHub::default();
+        hub.publish(Message::new("test::1"));
+        hub.publish(Message::new("test::2"));
+        let subscription = hub
+            .subscribe(Some(EventId {
+                epoch: hub.epoch - 1,
+                id: 5,
+            }))
+            .unwrap();
+        assert_eq!(subscription.missed.len(), 2);
+    }
+
+    #[test]
+    fn slow_subscriber_dropped() {
+        let hub = EventHub::default();
+        let subscription = hub.subscribe(None).unwrap();
+        for _ in 0..=SUBSCRIBER_CAPACITY {
+            hub.publish(Message::new("test"));
+        }
+        assert_eq!(subscription.rx.iter().count(), SUBSCRIBER_CAPACITY);
+    }
+
+    #[test]
+    fn subscriptions_limited() {
+        let hub = EventHub::default();
+        let subscriptions = (0..MAX_SUBSCRIPTIONS)
+            .map(|_| hub.subscribe(None).unwrap())
+            .collect::<Vec<_>>();
+        assert!(hub.subscribe(None).is_none());
+        drop(subscriptions);
+        assert!(hub.subscribe(None).is_some());
+    }
+
+    #[test]
+    fn recent_readings_ok() {
+        let hub = EventHub::default();
+        hub.publish(Message::new("test::1").value(Value::Counter(1)));
+        hub.publish(Message::new("test::2"));
+        hub.publish(Message::new("test::1").value(Value::Counter(2)));
+        let readings = hub.recent_readings("test::1");
+        assert_eq!(readings.len(), 2);
+        assert_eq!(readings[0].value, Value::Counter(2));
+    }
+
+    #[test]
+    fn write_message_is_not_published() {
+        let hub = EventHub::default();
+        let subscription = hub.subscribe(None).unwrap();
+        hub.publish(Message::new("test").type_(MessageType::Write));
+        assert!(subscription.rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,115 @@
+//! [Server-sent events](https://developer.mozilla.org/en-US/docs/Web/API/Server-sent_events).
+
+use std::io::{self, Cursor, Read};
+use std::time::Duration;
+
+use crossbeam::channel::RecvTimeoutError;
+use rocket::http::ContentType;
+use rocket::response::{Responder, Stream};
+use rocket::{Request, Response};
+use serde_json::json;
+
+use crate::prelude::*;
+use crate::web::base_path::BasePath;
+use crate::web::event_hub::{Event, Subscription};
+use crate::web::templates;
+
+/// Send a comment when idle, so that a disconnected client is eventually noticed.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Tells the browser to reconnect in that many milliseconds.
+const RETRY_MILLIS: u64 = 5000;
+
+/// Streams the events as they're published by the `EventHub`.
+pub struct EventStream {
+    /// Holds the subscription slot as long as the client stays connected.
+    subscription: Subscription,
+
+    /// Sensor IDs the client is interested in, all sensors if empty.
+    sensor_ids: Vec<String>,
+
+    /// Prefixes the links in the rendered tiles.
+    base_path: BasePath,
+
+    /// Formatted but not yet written events.
+    buffer: Cursor<Vec<u8>>,
+
+    /// Whether the current chunk should be finished by the next read.
+    is_end_of_chunk: bool,
+}
+
+impl EventStream {
+    pub fn new(mut subscription: Subscription, sensor_ids: Vec<String>, base_path: BasePath) -> Self {
+        let missed = std::mem::take(&mut subscription.missed);
+        let mut stream = EventStream {
+            subscription,
+            sensor_ids,
+            base_path,
+            buffer: Cursor::new(format!("retry: {}\n\n", RETRY_MILLIS).into_bytes()),
+            is_end_of_chunk: false,
+        };
+        for event in missed.iter() {
+            if let Some(formatted) = stream.format(event) {
+                stream.buffer.get_mut().extend(formatted.into_bytes());
+            }
+        }
+        stream
+    }
+
+    fn format(&self, (id, message): &Event) -> Option<String> {
+        if !self.sensor_ids.is_empty() && !self.sensor_ids.contains(&message.sensor.id) {
+            return None;
+        }
+        let data = json!({
+            "sensor": message.sensor,
+            "reading": message.reading,
+            "timestamp_millis": message.reading.timestamp.timestamp_millis(),
+            "value_html": message.reading.value.to_string(),
+            "tile_html": templates::render_tile(&self.base_path, &message.sensor, &message.reading),
+        });
+        Some(format!(
+            "id: {}-{}\nevent: reading\ndata: {}\n\n",
+            self.subscription.epoch, id, data
+        ))
+    }
+}
+
+impl Read for EventStream {
+    /// Rocket reads a chunk until it's full, which would hold the events back.
+    /// Thus, the stream pretends to end after each portion, and then blocks until the next event on the next chunk.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if (self.buffer.position() as usize) < self.buffer.get_ref().len() {
+                let size = self.buffer.read(buf)?;
+                // If the chunk is full, Rocket writes it out anyway.
+                self.is_end_of_chunk = size < buf.len();
+                return Ok(size);
+            }
+            if self.is_end_of_chunk {
+                self.is_end_of_chunk = false;
+                return Ok(0);
+            }
+            let formatted = match self.subscription.rx.recv_timeout(KEEP_ALIVE_INTERVAL) {
+                Ok(event) => match self.format(&event) {
+                    Some(formatted) => formatted,
+                    None => continue,
+                },
+                Err(RecvTimeoutError::Timeout) => ": keep-alive\n\n".into(),
+                // The hub has dropped the subscriber, the browser reconnects and resumes.
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            };
+            self.buffer = Cursor::new(formatted.into_bytes());
+        }
+    }
+}
+
+impl<'r> Responder<'r> for EventStream {
+    fn respond_to(self, request: &Request) -> Result<Response<'r>, rocket::http::Status> {
+        Response::build()
+            .merge(Stream::from(self).respond_to(request)?)
+            .header(ContentType::new("text", "event-stream"))
+            .raw_header("Cache-Control", "no-cache")
+            .raw_header("X-Accel-Buffering", "no")
+            .ok()
+    }
+}
@@ -0,0 +1,218 @@
+//! Liveness and readiness endpoints, for example, for a Docker health check.
+
+use std::time::Duration;
+
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::{get, State};
+use rocket_contrib::json::Json;
+
+use crate::core::metrics::METRICS;
+use crate::prelude::*;
+use crate::services::{ServiceState, ServiceStatus, Supervisor};
+use crate::web::live_settings::CurrentSettings;
+
+/// The persistence executor commits every second and the `system::db` service sends its readings every minute.
+const MAX_SINCE_LAST_COMMIT: Duration = Duration::from_secs(300);
+
+/// The `system::db` service sends its readings every minute, thus there's always something to dispatch.
+const MAX_SINCE_LAST_DISPATCH: Duration = Duration::from_secs(300);
+
+#[derive(Serialize)]
+pub struct Health {
+    pub status: &'static str,
+    pub version: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    pub is_ready: bool,
+    pub checks: Vec<Check>,
+}
+
+#[derive(Serialize)]
+pub struct Check {
+    pub name: String,
+    pub is_ok: bool,
+    pub details: String,
+}
+
+impl Check {
+    fn new<N: Into<String>, D: Into<String>>(name: N, is_ok: bool, details: D) -> Self {
+        Check {
+            name: name.into(),
+            is_ok,
+            details: details.into(),
+        }
+    }
+}
+
+/// Tells that the process is alive and the web server responds.
+#[get("/health")]
+pub fn get_health() -> Json<Health> {
+    Json(Health {
+        status: "ok",
+        version: crate_version!(),
+    })
+}
+
+/// Checks the database, the persistence, the bus and the critical services.
+/// Responds with `503 Service Unavailable` if any of the checks fails.
+#[get("/ready")]
+pub fn get_ready(
+    db: State<Connection>,
+    settings: CurrentSettings,
+    supervisor: State<Supervisor>,
+) -> Custom<Json<Readiness>> {
+    let mut checks = vec![
+        match db.check_writable() {
+            Ok(_) => Check::new("database", true, "writable"),
+            Err(error) => Check::new("database", false, error.to_string()),
+        },
+        check_recent(
+            "persistence",
+            METRICS.since_last_commit(),
+            MAX_SINCE_LAST_COMMIT,
+            "committed",
+        ),
+        if METRICS.is_dispatcher_alive() {
+            check_recent(
+                "bus",
+                METRICS.since_last_dispatch(),
+                MAX_SINCE_LAST_DISPATCH,
+                "dispatched",
+            )
+        } else {
+            Check::new("bus", false, "the dispatcher is not running")
+        },
+    ];
+    checks.extend(check_services(&settings.critical_services, &supervisor.statuses()));
+
+    let is_ready = checks.iter().all(|check| check.is_ok);
+    Custom(
+        if is_ready {
+            Status::Ok
+        } else {
+            Status::ServiceUnavailable
+        },
+        Json(Readiness { is_ready, checks }),
+    )
+}
+
+/// Checks that the last event has happened recently enough.
+fn check_recent(name: &str, elapsed: Option<Duration>, max_elapsed: Duration, verb: &str) -> Check {
+    match elapsed {
+        Some(elapsed) => Check::new(
+            name,
+            elapsed <= max_elapsed,
+            format!("last {} {}s ago", verb, elapsed.as_secs()),
+        ),
+        None => Check::new(name, false, format!("nothing has been {} yet", verb)),
+    }
+}
+
+/// Checks that each of the critical services is running and its last iteration hasn't failed.
+fn check_services(critical_services: &[String], statuses: &[ServiceStatus]) -> Vec<Check> {
+    critical_services
+        .iter()
+        .map(|service_id| {
+            let name = format!("service::{}", service_id);
+            match statuses.iter().find(|status| &status.service_id == service_id) {
+                None => Check::new(name, false, "not configured"),
+                Some(status) if status.state != ServiceState::Running => {
+                    Check::new(name, false, format!("{:?}", status.state).to_lowercase())
+                }
+                Some(status) if is_failing(status) => Check::new(name, false, "the last iteration has failed"),
+                Some(_) => Check::new(name, true, "running"),
+            }
+        })
+        .collect()
+}
+
+/// Tells whether the last error has happened after the last successful iteration.
+fn is_failing(status: &ServiceStatus) -> bool {
+    match (&status.stats.last_error, status.stats.last_success_at) {
+        (Some(error), Some(last_success_at)) => error.timestamp > last_success_at,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::metrics::{ServiceError, ServiceStats};
+
+    fn status(service_id: &str, state: ServiceState, stats: ServiceStats) -> ServiceStatus {
+        ServiceStatus {
+            service_id: service_id.into(),
+            type_: "Clock",
+            state,
+            stats,
+        }
+    }
+
+    #[test]
+    fn check_services_ok() {
+        let statuses = vec![
+            status("clock", ServiceState::Running, ServiceStats::default()),
+            status("paused", ServiceState::Paused, ServiceStats::default()),
+            status(
+                "failing",
+                ServiceState::Running,
+                ServiceStats {
+                    last_error: Some(ServiceError {
+                        message: "timeout".into(),
+                        timestamp: Local::now(),
+                    }),
+                    ..Default::default()
+                },
+            ),
+        ];
+        let checks = check_services(
+            &[
+                "clock".to_string(),
+                "paused".to_string(),
+                "failing".to_string(),
+                "missing".to_string(),
+            ],
+            &statuses,
+        );
+        let results: Vec<(&str, bool, &str)> = checks
+            .iter()
+            .map(|check| (check.name.as_str(), check.is_ok, check.details.as_str()))
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                ("service::clock", true, "running"),
+                ("service::paused", false, "paused"),
+                ("service::failing", false, "the last iteration has failed"),
+                ("service::missing", false, "not configured"),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_recent_ok() {
+        assert!(
+            check_recent(
+                "bus",
+                Some(Duration::from_secs(1)),
+                MAX_SINCE_LAST_DISPATCH,
+                "dispatched"
+            )
+            .is_ok
+        );
+        assert!(
+            !check_recent(
+                "bus",
+                Some(Duration::from_secs(301)),
+                MAX_SINCE_LAST_DISPATCH,
+                "dispatched"
+            )
+            .is_ok
+        );
+        assert!(!check_recent("bus", None, MAX_SINCE_LAST_DISPATCH, "dispatched").is_ok);
+    }
+}
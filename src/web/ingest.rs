@@ -0,0 +1,292 @@
+//! Lets devices push readings into the bus.
+
+use ring::constant_time::verify_slices_are_equal;
+use rocket::http::Status;
+use rocket::request::LenientForm;
+use rocket::{get, post, FromForm, State};
+use rocket_contrib::json::Json;
+use serde_json::json;
+
+use crate::core::auth::hash_token;
+use crate::core::control::Control;
+use crate::core::db::api_token::ApiToken;
+use crate::prelude::*;
+use crate::settings::{Settings, Token};
+use crate::web::auth::authenticate_api_token;
+use crate::web::bearer_token::BearerToken;
+use crate::web::live_settings::CurrentSettings;
+
+/// Maximum length of an ingested sensor ID, title, location or tag, in characters.
+const MAX_FIELD_LENGTH: usize = 200;
+
+/// Message as it's pushed by a client.
+#[derive(Deserialize, Debug)]
+pub struct MessageRequest {
+    sensor_id: String,
+
+    #[serde(rename = "type", default = "default_message_type")]
+    type_: MessageType,
+
+    #[serde(default = "default_value")]
+    value: Value,
+
+    #[serde(default)]
+    title: Option<String>,
+
+    #[serde(default)]
+    location: Option<String>,
+
+    /// Makes the sensor controllable from the web interface.
+    #[serde(default)]
+    control: Option<Control>,
+
+    /// Sensor tags, for example: `["battery", "outdoor"]`.
+    #[serde(default)]
+    tags: Vec<String>,
+
+    /// Defaults to the current time.
+    #[serde(default)]
+    timestamp: Option<DateTime<Local>>,
+}
+
+/// Query parameters of the simplified ingestion endpoint.
+#[derive(FromForm)]
+pub struct IngestParams {
+    /// Value type, for example: `Temperature`.
+    kind: Option<String>,
+
+    /// Raw value, for example: `21.5` or `true`.
+    value: Option<String>,
+
+    /// Message type, `ReadLogged` by default.
+    #[form(field = "type")]
+    type_: Option<String>,
+
+    title: Option<String>,
+    location: Option<String>,
+}
+
+/// Token which has been presented by a client.
+enum Grant<'a> {
+    /// Token from the settings.
+    Settings(&'a Token),
+
+    /// Token from the database.
+    Api(ApiToken),
+}
+
+impl Grant<'_> {
+    fn allows(&self, message: &Message) -> bool {
+        match self {
+            Grant::Settings(token) => token.allows(message),
+            Grant::Api(token) => token.allows(message),
+        }
+    }
+}
+
+const fn default_message_type() -> MessageType {
+    MessageType::ReadLogged
+}
+
+fn default_value() -> Value {
+    Value::None
+}
+
+/// Accepts a JSON array of messages and puts them onto the bus.
+#[post("/messages", format = "json", data = "<messages>")]
+pub fn post_messages(
+    settings: CurrentSettings,
+    db: State<Connection>,
+    tx: State<Sender>,
+    bearer_token: Option<BearerToken>,
+    messages: Json<Vec<MessageRequest>>,
+) -> Status {
+    let grant = match authorize(&settings, &db, bearer_token.map(|BearerToken(token)| token)) {
+        Some(grant) => grant,
+        None => return Status::Unauthorized,
+    };
+    let messages: Vec<Message> = messages.into_inner().into_iter().map(Into::into).collect();
+    send_messages(&grant, messages, &tx)
+}
+
+/// Simplified ingestion endpoint for devices which can barely make an HTTP request.
+#[get("/ingest/<sensor_id>?<params..>")]
+pub fn get_ingest(
+    settings: CurrentSettings,
+    db: State<Connection>,
+    tx: State<Sender>,
+    bearer_token: Option<BearerToken>,
+    sensor_id: String,
+    params: LenientForm<IngestParams>,
+) -> Status {
+    ingest(&settings, &db, &tx, bearer_token, sensor_id, params.into_inner())
+}
+
+#[post("/ingest/<sensor_id>?<params..>")]
+pub fn post_ingest(
+    settings: CurrentSettings,
+    db: State<Connection>,
+    tx: State<Sender>,
+    bearer_token: Option<BearerToken>,
+    sensor_id: String,
+    params: LenientForm<IngestParams>,
+) -> Status {
+    ingest(&settings, &db, &tx, bearer_token, sensor_id, params.into_inner())
+}
+
+fn ingest(
+    settings: &Settings,
+    db: &Connection,
+    tx: &Sender,
+    bearer_token: Option<BearerToken>,
+    sensor_id: String,
+    params: IngestParams,
+) -> Status {
+    let grant = match authorize(settings, db, bearer_token.map(|BearerToken(token)| token)) {
+        Some(grant) => grant,
+        None => return Status::Unauthorized,
+    };
+    let message = match params.to_message(sensor_id) {
+        Ok(message) => message,
+        Err(error) => {
+            warn!("Invalid message: {}", error.to_string());
+            return Status::BadRequest;
+        }
+    };
+    send_messages(&grant, vec![message], tx)
+}
+
+/// Finds the settings or API token which matches the provided one.
+/// The settings keep the token hashes, which are compared in constant time.
+fn authorize<'a>(settings: &'a Settings, db: &Connection, token: Option<String>) -> Option<Grant<'a>> {
+    let token = token?;
+    let token_hash = hash_token(&token);
+    match settings
+        .tokens
+        .values()
+        .find(|known| verify_slices_are_equal(known.secrets.token_hash.as_bytes(), token_hash.as_bytes()).is_ok())
+    {
+        Some(known) => Some(Grant::Settings(known)),
+        None => authenticate_api_token(db, &token).map(Grant::Api),
+    }
+}
+
+/// Validates the messages and sends them all, or none of them.
+fn send_messages(grant: &Grant, messages: Vec<Message>, tx: &Sender) -> Status {
+    if !messages.iter().all(is_valid) {
+        return Status::BadRequest;
+    }
+    if !messages.iter().all(|message| grant.allows(message)) {
+        return Status::Forbidden;
+    }
+    for message in messages.into_iter() {
+        message.send_and_forget(tx);
+    }
+    Status::Accepted
+}
+
+/// Checks the sensor fields which end up on the pages: they must be short and have no control characters.
+fn is_valid(message: &Message) -> bool {
+    let is_valid_field =
+        |field: &str| field.chars().count() <= MAX_FIELD_LENGTH && !field.chars().any(char::is_control);
+    let sensor = &message.sensor;
+    !sensor.id.is_empty()
+        && is_valid_field(&sensor.id)
+        && sensor.title.as_deref().map_or(true, is_valid_field)
+        && is_valid_field(&sensor.location)
+        && sensor.tags.iter().all(|tag| is_valid_field(tag))
+}
+
+impl IngestParams {
+    fn to_message(&self, sensor_id: String) -> Result<Message> {
+        let mut message = Message::new(sensor_id).value(match self.kind {
+            Some(ref kind) => parse_value(kind, self.value.as_deref())?,
+            None => Value::None,
+        });
+        if let Some(ref type_) = self.type_ {
+            message = message.type_(serde_json::from_value(json!(type_))?);
+        }
+        if let Some(ref title) = self.title {
+            message = message.sensor_title(title);
+        }
+        if let Some(ref location) = self.location {
+            message = message.location(location);
+        }
+        Ok(message)
+    }
+}
+
+/// Builds a value from its type name and a raw value, for example: `Temperature` and `21.5`.
+pub fn parse_value(kind: &str, value: Option<&str>) -> Result<Value> {
+    // The raw value may be either a JSON literal or a bare string.
+    if let Some(literal) = value.and_then(|value| serde_json::from_str::<serde_json::Value>(value).ok()) {
+        if let Ok(value) = serde_json::from_value(json!({ "type": kind, "value": literal })) {
+            return Ok(value);
+        }
+    }
+    Ok(serde_json::from_value(json!({ "type": kind, "value": value }))?)
+}
+
+impl From<MessageRequest> for Message {
+    fn from(request: MessageRequest) -> Self {
+        let mut message = Message::new(request.sensor_id)
+            .type_(request.type_)
+            .value(request.value)
+            .optional_location(request.location);
+        message.sensor.title = request.title;
+        message.sensor.control = request.control;
+        message.sensor.tags = request.tags;
+        if let Some(timestamp) = request.timestamp {
+            message = message.timestamp(timestamp);
+        }
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_temperature_ok() -> Result {
+        assert_eq!(parse_value("Temperature", Some("21.5"))?, Value::Temperature(21.5));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_bare_text_ok() -> Result {
+        assert_eq!(parse_value("Text", Some("hello"))?, Value::Text("hello".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_numeric_text_ok() -> Result {
+        assert_eq!(parse_value("Text", Some("42"))?, Value::Text("42".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_invalid_value_fails() {
+        assert!(parse_value("Temperature", Some("hello")).is_err());
+    }
+
+    #[test]
+    fn is_valid_ok() {
+        assert!(is_valid(&Message::new("esp::temperature").sensor_title("Temperature")));
+    }
+
+    #[test]
+    fn control_characters_invalid() {
+        assert!(!is_valid(&Message::new("esp::temperature\n")));
+        assert!(!is_valid(
+            &Message::new("esp::temperature").sensor_title("Temperature\u{0}")
+        ));
+    }
+
+    #[test]
+    fn long_title_invalid() {
+        assert!(!is_valid(
+            &Message::new("esp::temperature").sensor_title("x".repeat(MAX_FIELD_LENGTH + 1))
+        ));
+    }
+}
@@ -0,0 +1,49 @@
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+
+use crate::web::event_hub::EventId;
+
+/// Extracts a [`Last-Event-ID`](https://html.spec.whatwg.org/multipage/server-sent-events.html#the-last-event-id-header)
+/// header which a browser sends when it reconnects to an event stream.
+pub struct LastEventId(pub EventId);
+
+impl<'a, 'r> FromRequest<'a, 'r> for LastEventId {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        match request.headers().get_one("Last-Event-ID").and_then(parse) {
+            Some(last_event_id) => Outcome::Success(LastEventId(last_event_id)),
+            None => Outcome::Forward(()),
+        }
+    }
+}
+
+/// Parses the `<epoch>-<id>` event ID.
+fn parse(value: &str) -> Option<EventId> {
+    let mut parts = value.splitn(2, '-');
+    Some(EventId {
+        epoch: parts.next()?.parse().ok()?,
+        id: parts.next()?.parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ok() {
+        assert_eq!(
+            parse("1603100000000-42"),
+            Some(EventId {
+                epoch: 1603100000000,
+                id: 42
+            })
+        );
+    }
+
+    #[test]
+    fn parse_without_epoch_none() {
+        assert_eq!(parse("42"), None);
+    }
+}
@@ -0,0 +1,79 @@
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, State};
+
+use crate::prelude::*;
+use crate::settings::Role;
+use crate::web::auth::CurrentUser;
+use crate::web::base_path::BasePath;
+use crate::web::dashboards::select_dashboards;
+use crate::web::live_settings::CurrentSettings;
+use crate::web::message_counter::MessageCounter;
+
+/// Context which is shared by all the pages.
+pub struct Layout {
+    /// Prefixes the links and the assets.
+    pub base_path: BasePath,
+
+    /// Statistics in the footer.
+    pub message_count: u64,
+
+    /// Authenticated user, if any.
+    pub user: Option<CurrentUser>,
+
+    /// Dashboard slugs and titles for the navigation bar.
+    pub dashboards: Vec<(String, String)>,
+}
+
+impl Layout {
+    /// Returns the name of the logged in user, if any.
+    pub fn user_name(&self) -> Option<&str> {
+        self.user.as_ref().and_then(|user| user.name.as_deref())
+    }
+
+    pub fn csrf_token(&self) -> &str {
+        self.user.as_ref().map_or("", |user| user.csrf_token.as_str())
+    }
+
+    /// Checks whether the current user is allowed to do what the role is allowed to.
+    pub fn has_role(&self, role: Role) -> bool {
+        self.user.as_ref().map_or(false, |user| user.role >= role)
+    }
+
+    /// Checks whether the current user is allowed to control the sensors.
+    pub fn is_operator(&self) -> bool {
+        self.has_role(Role::Operator)
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.has_role(Role::Admin)
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Layout {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Layout {
+            base_path: request.guard::<BasePath>().succeeded().unwrap_or_default(),
+            message_count: request
+                .guard::<State<MessageCounter>>()
+                .succeeded()
+                .map_or(0, |message_counter| message_counter.value()),
+            user: request.guard::<CurrentUser>().succeeded(),
+            dashboards: match (
+                request.guard::<CurrentSettings>().succeeded(),
+                request.guard::<State<Connection>>().succeeded(),
+            ) {
+                (Some(settings), Some(db)) => select_dashboards(&settings, &db)
+                    .unwrap_or_else(|error| {
+                        error!("Failed to select the dashboards: {}", error.to_string());
+                        Vec::new()
+                    })
+                    .into_iter()
+                    .map(|dashboard| (dashboard.slug, dashboard.dashboard.title))
+                    .collect(),
+                _ => Vec::new(),
+            },
+        })
+    }
+}
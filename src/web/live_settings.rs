@@ -0,0 +1,65 @@
+//! Settings which get replaced when the settings editor saves them.
+
+use std::ops::Deref;
+use std::sync::RwLock;
+
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, State};
+
+use crate::prelude::*;
+use crate::settings::Settings;
+
+/// Running settings, shared by the web servers of all the addresses.
+#[derive(Clone)]
+pub struct LiveSettings(Arc<RwLock<Arc<Settings>>>);
+
+impl LiveSettings {
+    pub fn new(settings: Settings) -> Self {
+        LiveSettings(Arc::new(RwLock::new(Arc::new(settings))))
+    }
+
+    pub fn get(&self) -> Arc<Settings> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Replaces the settings, the requests which are already running keep the previous ones.
+    pub fn replace(&self, settings: Settings) {
+        *self.0.write().unwrap() = Arc::new(settings);
+    }
+}
+
+/// Snapshot of the running settings as of the start of the request.
+pub struct CurrentSettings(Arc<Settings>);
+
+impl Deref for CurrentSettings {
+    type Target = Settings;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for CurrentSettings {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        request
+            .guard::<State<LiveSettings>>()
+            .map(|settings| CurrentSettings(settings.get()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_ok() -> Result {
+        let settings = LiveSettings::new(crate::settings::parse(&["http_port = 8080".to_string()])?);
+        let previous = settings.get();
+        settings.replace(crate::settings::parse(&["http_port = 8081".to_string()])?);
+        assert_eq!(previous.http_port, 8080);
+        assert_eq!(settings.get().http_port, 8081);
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+//! Recent log records, which are kept in memory.
+
+use rocket::request::LenientForm;
+use rocket::{get, FromForm};
+use rocket_contrib::json::Json;
+
+use crate::core::logging::{LogRecord, LOG_BUFFER};
+use crate::prelude::*;
+use crate::web::auth::Admin;
+use crate::web::layout::Layout;
+use crate::web::templates;
+use crate::web::to_html_string::ToHtmlString;
+
+#[derive(FromForm, Default)]
+pub struct LogFilter {
+    /// Thread name, which is the service ID for the service threads. Empty means all the threads.
+    pub service: Option<String>,
+
+    /// The least severe level to show, for example: `Warn`. All the levels are shown by default.
+    pub level: Option<String>,
+
+    /// Only the records with greater IDs are returned. It's used for the live tailing.
+    pub after: Option<u64>,
+}
+
+impl LogFilter {
+    pub fn service(&self) -> Option<&str> {
+        self.service.as_deref().filter(|service| !service.is_empty())
+    }
+
+    pub fn level(&self) -> LogLevel {
+        self.level
+            .as_deref()
+            .and_then(|level| level.parse().ok())
+            .unwrap_or(LogLevel::Trace)
+    }
+
+    fn select(&self) -> Vec<LogRecord> {
+        LOG_BUFFER.select(self.service(), self.level(), self.after.unwrap_or(0))
+    }
+}
+
+#[get("/logs?<filter..>")]
+pub fn get_logs(_user: Admin, layout: Layout, filter: LenientForm<LogFilter>) -> ToHtmlString<impl ToString> {
+    let mut records = filter.select();
+    let last_id = records.last().map_or(0, |record| record.id);
+    records.reverse();
+    ToHtmlString(templates::LogsTemplate {
+        layout,
+        records,
+        threads: LOG_BUFFER.threads(),
+        service: filter.service().unwrap_or_default().to_string(),
+        level: filter.level().to_string().to_lowercase(),
+        last_id,
+    })
+}
+
+/// Returns the matching records, oldest first.
+#[get("/logs/json?<filter..>")]
+pub fn get_logs_json(_user: Admin, filter: LenientForm<LogFilter>) -> Json<Vec<LogRecord>> {
+    Json(filter.select())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_ok() {
+        let filter = LogFilter {
+            level: Some("warn".into()),
+            ..Default::default()
+        };
+        assert_eq!(filter.level(), LogLevel::Warn);
+        assert_eq!(LogFilter::default().level(), LogLevel::Trace);
+    }
+
+    #[test]
+    fn empty_service_means_all() {
+        let filter = LogFilter {
+            service: Some("".into()),
+            ..Default::default()
+        };
+        assert_eq!(filter.service(), None);
+    }
+}
@@ -0,0 +1,81 @@
+//! [Largest-Triangle-Three-Buckets](https://skemman.is/bitstream/1946/15343/3/SS_MSthesis.pdf) downsampling.
+//!
+//! Unlike averaging, it keeps the visual shape of a series including its spikes.
+
+use crate::prelude::*;
+
+/// Reduces the points ordered by timestamp down to `threshold` points.
+/// The first and the last points are always kept.
+pub fn downsample(points: Vec<(DateTime<Local>, f64)>, threshold: usize) -> Vec<(DateTime<Local>, f64)> {
+    if threshold < 3 || points.len() <= threshold {
+        return points;
+    }
+
+    let x = |i: usize| points[i].0.timestamp_millis() as f64;
+    let y = |i: usize| points[i].1;
+
+    // The first and the last points occupy their own buckets.
+    let bucket_size = (points.len() - 2) as f64 / (threshold - 2) as f64;
+    let bucket_start = |bucket: usize| (bucket as f64 * bucket_size) as usize + 1;
+
+    let mut sampled = Vec::with_capacity(threshold);
+    sampled.push(points[0]);
+    let mut a = 0;
+
+    for bucket in 0..threshold - 2 {
+        // Average point of the next bucket, the last point is the next bucket for the last one.
+        let (next_start, next_end) = if bucket + 1 < threshold - 2 {
+            (bucket_start(bucket + 1), bucket_start(bucket + 2).min(points.len() - 1))
+        } else {
+            (points.len() - 1, points.len())
+        };
+        let next_count = (next_end - next_start) as f64;
+        let avg_x = (next_start..next_end).map(x).sum::<f64>() / next_count;
+        let avg_y = (next_start..next_end).map(y).sum::<f64>() / next_count;
+
+        // Pick the point which forms the largest triangle with the previously selected one and the average.
+        let (x_a, y_a) = (x(a), y(a));
+        a = (bucket_start(bucket)..bucket_start(bucket + 1))
+            .max_by(|&i, &j| {
+                let area = |k: usize| ((x_a - avg_x) * (y(k) - y_a) - (x_a - x(k)) * (avg_y - y_a)).abs();
+                area(i).partial_cmp(&area(j)).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap();
+        sampled.push(points[a]);
+    }
+
+    sampled.push(points[points.len() - 1]);
+    sampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(values: &[f64]) -> Vec<(DateTime<Local>, f64)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (Local.timestamp_millis(i as i64 * 1000), *value))
+            .collect()
+    }
+
+    #[test]
+    fn short_series_unchanged() {
+        assert_eq!(downsample(points(&[1.0, 2.0, 3.0]), 10), points(&[1.0, 2.0, 3.0]));
+    }
+
+    #[test]
+    fn downsample_ok() {
+        let values: Vec<f64> = (0..10000).map(|i| if i == 5000 { 100.0 } else { 0.0 }).collect();
+        let sampled = downsample(points(&values), 100);
+        assert_eq!(sampled.len(), 100);
+        assert_eq!(sampled.first(), points(&values).first());
+        assert_eq!(sampled.last(), points(&values).last());
+        assert!(
+            sampled.iter().any(|(_, value)| *value == 100.0),
+            "the spike must be kept"
+        );
+        assert!(sampled.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+}
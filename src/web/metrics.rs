@@ -0,0 +1,174 @@
+//! [Prometheus](https://prometheus.io/) metrics.
+
+use std::fmt::Write;
+
+use rocket::http::ContentType;
+use rocket::response::content::Content;
+use rocket::{get, State};
+
+use crate::core::metrics::METRICS;
+use crate::prelude::*;
+use crate::web::auth::Access;
+use crate::web::message_counter::MessageCounter;
+
+/// Exposes the latest values of the numeric sensors and the internal metrics
+/// in the [text format](https://prometheus.io/docs/instrumenting/exposition_formats/).
+#[get("/metrics")]
+pub fn get_metrics(
+    access: Access,
+    db: State<Connection>,
+    message_counter: State<MessageCounter>,
+) -> Result<Content<String>> {
+    let mut exposition = Exposition::default();
+
+    exposition.family("my_iot_sensor_value", "gauge", "Latest value of a numeric sensor.");
+    for (sensor, reading) in db.select_actuals()? {
+        if let (true, Some(value)) = (access.can_read(&sensor.id), reading.value.to_f64()) {
+            exposition.sample(
+                "my_iot_sensor_value",
+                &[
+                    ("sensor_id", sensor.id.as_str()),
+                    ("location", sensor.location.as_str()),
+                    ("title", sensor.title().as_str()),
+                ],
+                value,
+            );
+        }
+    }
+
+    exposition.family("my_iot_messages_total", "counter", "Number of dispatched messages.");
+    exposition.sample("my_iot_messages_total", &[], message_counter.value() as f64);
+
+    exposition.family("my_iot_queue_depth", "gauge", "Number of pending messages in a queue.");
+    for (name, depth) in METRICS.queue_depths() {
+        exposition.sample("my_iot_queue_depth", &[("queue", name.as_str())], depth as f64);
+    }
+
+    exposition.family(
+        "my_iot_persistence_batches_total",
+        "counter",
+        "Number of persisted message batches.",
+    );
+    exposition.sample(
+        "my_iot_persistence_batches_total",
+        &[],
+        METRICS.persistence_batch_count() as f64,
+    );
+    exposition.family(
+        "my_iot_persistence_messages_total",
+        "counter",
+        "Number of persisted messages.",
+    );
+    exposition.sample(
+        "my_iot_persistence_messages_total",
+        &[],
+        METRICS.persisted_message_count() as f64,
+    );
+    exposition.family(
+        "my_iot_persistence_duration_seconds_total",
+        "counter",
+        "Total time spent on persisting the message batches.",
+    );
+    exposition.sample(
+        "my_iot_persistence_duration_seconds_total",
+        &[],
+        METRICS.persistence_duration().as_secs_f64(),
+    );
+    exposition.family(
+        "my_iot_persistence_last_batch_size",
+        "gauge",
+        "Number of messages in the latest persisted batch.",
+    );
+    exposition.sample(
+        "my_iot_persistence_last_batch_size",
+        &[],
+        METRICS.last_persistence_batch_size() as f64,
+    );
+
+    exposition.family("my_iot_database_size_bytes", "gauge", "Database size.");
+    exposition.sample("my_iot_database_size_bytes", &[], db.select_size()? as f64);
+
+    exposition.family(
+        "my_iot_service_failures_total",
+        "counter",
+        "Number of failed service iterations.",
+    );
+    for (service_id, count) in METRICS.service_failures() {
+        exposition.sample(
+            "my_iot_service_failures_total",
+            &[("service_id", service_id.as_str())],
+            count as f64,
+        );
+    }
+
+    Ok(Content(ContentType::Plain, exposition.0))
+}
+
+/// Builds the exposition text.
+#[derive(Default)]
+struct Exposition(String);
+
+impl Exposition {
+    /// Writes the metric family header.
+    fn family(&mut self, name: &str, type_: &str, help: &str) {
+        writeln!(self.0, "# HELP {} {}", name, help).unwrap();
+        writeln!(self.0, "# TYPE {} {}", name, type_).unwrap();
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.0.push_str(name);
+        if !labels.is_empty() {
+            let labels = labels
+                .iter()
+                .map(|(key, value)| format!(r#"{}="{}""#, key, escape_label_value(value)))
+                .collect::<Vec<String>>()
+                .join(",");
+            write!(self.0, "{{{}}}", labels).unwrap();
+        }
+        writeln!(self.0, " {}", format_value(value)).unwrap();
+    }
+}
+
+/// Formats the sample value the way Prometheus spells the special values.
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".into()
+    } else if value.is_infinite() && value.is_sign_positive() {
+        "+Inf".into()
+    } else if value.is_infinite() {
+        "-Inf".into()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', r"\\").replace('"', r#"\""#).replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_ok() {
+        let mut exposition = Exposition::default();
+        exposition.sample(
+            "my_iot_sensor_value",
+            &[("title", "Say \"hi\"\n"), ("location", "C:\\")],
+            1.5,
+        );
+        assert_eq!(
+            exposition.0,
+            "my_iot_sensor_value{title=\"Say \\\"hi\\\"\\n\",location=\"C:\\\\\"} 1.5\n",
+        );
+    }
+
+    #[test]
+    fn format_value_special_ok() {
+        assert_eq!(format_value(std::f64::NAN), "NaN");
+        assert_eq!(format_value(std::f64::INFINITY), "+Inf");
+        assert_eq!(format_value(std::f64::NEG_INFINITY), "-Inf");
+        assert_eq!(format_value(-2.5), "-2.5");
+    }
+}
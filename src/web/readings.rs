@@ -0,0 +1,379 @@
+//! Raw sensor readings: the history table, the export and the deletion of individual readings.
+
+use std::io::{self, Cursor, Read};
+
+use rocket::http::uri::Uri;
+use rocket::http::ContentType;
+use rocket::request::LenientForm;
+use rocket::response::Redirect;
+use rocket::{delete, get, FromForm, FromFormValue, Response, State};
+
+use crate::core::datetime;
+use crate::prelude::*;
+use crate::web::auth::{Admin, Csrf, Empty, Viewer};
+use crate::web::base_path::BasePath;
+use crate::web::layout::Layout;
+use crate::web::templates;
+use crate::web::EventHub;
+
+const PAGE_SIZE: u32 = 50;
+
+/// The export selects that many readings at a time, instead of loading all of them at once.
+const EXPORT_CHUNK_SIZE: u32 = 1000;
+
+/// Optional period of the readings, as it's submitted by the `datetime-local` inputs.
+#[derive(FromForm, Default)]
+pub struct ReadingsRange {
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
+impl ReadingsRange {
+    pub fn since(&self) -> Option<DateTime<Local>> {
+        self.since.as_deref().and_then(parse_datetime_local)
+    }
+
+    pub fn until(&self) -> Option<DateTime<Local>> {
+        self.until.as_deref().and_then(parse_datetime_local)
+    }
+
+    /// Returns the query string to preserve the period in the links.
+    pub fn to_query(&self) -> String {
+        vec![("since", &self.since), ("until", &self.until)]
+            .into_iter()
+            .filter_map(|(name, value)| {
+                value
+                    .as_ref()
+                    .filter(|value| !value.is_empty())
+                    .map(|value| (name, value))
+            })
+            .map(|(name, value)| format!("{}={}", name, Uri::percent_encode(value)))
+            .collect::<Vec<String>>()
+            .join("&")
+    }
+}
+
+/// Parses the value of a `datetime-local` input, for example: `2020-07-01T12:00`.
+/// It's the wall-clock time in the display time zone.
+fn parse_datetime_local(value: &str) -> Option<DateTime<Local>> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M"))
+        .ok()
+        .and_then(|datetime| datetime::from_display(&datetime))
+}
+
+#[derive(FromFormValue, Copy, Clone)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Selects the page of the sensor readings, the newest first.
+/// Also returns whether there's a next page.
+pub fn select_page(
+    db: &Connection,
+    event_hub: &EventHub,
+    sensor_id: &str,
+    reading_count: u64,
+    range: &ReadingsRange,
+    page: u32,
+) -> Result<(Vec<Reading>, bool)> {
+    let offset = page.saturating_mul(PAGE_SIZE);
+    // Fetch one extra reading to find out whether there's a next page.
+    let mut readings = if reading_count != 0 {
+        db.select_latest_readings(
+            sensor_id,
+            range.since().as_ref(),
+            range.until().as_ref(),
+            PAGE_SIZE + 1,
+            offset,
+        )?
+    } else {
+        // The sensor isn't logged, so only the recent readings are available.
+        event_hub
+            .recent_readings(sensor_id)
+            .into_iter()
+            .skip(offset as usize)
+            .take(PAGE_SIZE as usize + 1)
+            .collect()
+    };
+    let has_next_page = readings.len() > PAGE_SIZE as usize;
+    readings.truncate(PAGE_SIZE as usize);
+    Ok((readings, has_next_page))
+}
+
+/// Renders the paginated table of the sensor readings, the newest first.
+/// The links keep the period of the sensor page history in `minutes`.
+#[allow(clippy::too_many_arguments)]
+pub fn render_table(
+    layout: &Layout,
+    db: &Connection,
+    event_hub: &EventHub,
+    sensor_id: &str,
+    reading_count: u64,
+    range: &ReadingsRange,
+    minutes: i64,
+    page: u32,
+) -> Result<String> {
+    let (readings, has_next_page) = select_page(db, event_hub, sensor_id, reading_count, range, page)?;
+    Ok(templates::ReadingsTablePartialTemplate {
+        base_path: layout.base_path.clone(),
+        sensor_id: sensor_id.into(),
+        rows: readings
+            .into_iter()
+            .map(|reading| {
+                Ok(templates::ReadingRow {
+                    raw_value: serde_json::to_string(&reading.value)?,
+                    reading,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?,
+        range_since: range.since.clone().unwrap_or_default(),
+        range_until: range.until.clone().unwrap_or_default(),
+        range_query: range.to_query(),
+        minutes,
+        page,
+        has_next_page,
+        // Readings of non-logged sensors are kept in memory only.
+        can_delete: layout.is_admin() && reading_count != 0,
+        csrf_token: layout.csrf_token().into(),
+    }
+    .to_string())
+}
+
+/// Downloads the readings within the period as CSV or JSON.
+#[get("/sensors/<sensor_id>/readings/export?<format>&<range..>")]
+pub fn get_readings_export<'r>(
+    _user: Viewer,
+    db: State<Connection>,
+    sensor_id: String,
+    format: ExportFormat,
+    range: LenientForm<ReadingsRange>,
+) -> Result<Response<'r>> {
+    let (content_type, extension) = match format {
+        ExportFormat::Csv => (ContentType::CSV, "csv"),
+        ExportFormat::Json => (ContentType::JSON, "json"),
+    };
+    let export = ReadingsExport {
+        db: (*db).clone(),
+        buffer: Cursor::new(format.header().into_bytes()),
+        after: range
+            .since()
+            .and_then(|since| since.checked_sub_signed(chrono::Duration::milliseconds(1))),
+        until: range.until(),
+        sensor_id: sensor_id.clone(),
+        format,
+        is_finished: false,
+        is_first: true,
+    };
+    Response::build()
+        .header(content_type)
+        .raw_header(
+            "Content-Disposition",
+            format!(
+                r#"attachment; filename="{}.{}""#,
+                sensor_id.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_', "_"),
+                extension,
+            ),
+        )
+        .streamed_body(export)
+        .ok()
+}
+
+impl ExportFormat {
+    fn header(self) -> String {
+        match self {
+            ExportFormat::Csv => "timestamp,type,value\n".into(),
+            ExportFormat::Json => "[".into(),
+        }
+    }
+
+    fn footer(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "",
+            ExportFormat::Json => "]",
+        }
+    }
+
+    fn format(self, reading: &Reading, is_first: bool) -> Result<String> {
+        match self {
+            ExportFormat::Csv => to_csv_row(reading),
+            ExportFormat::Json => Ok(format!(
+                "{}{}",
+                if is_first { "" } else { "," },
+                serde_json::to_string(reading)?
+            )),
+        }
+    }
+}
+
+/// Streams the readings in the chronological order, selecting them chunk by chunk.
+struct ReadingsExport {
+    db: Connection,
+    sensor_id: String,
+    format: ExportFormat,
+
+    /// Timestamp of the last exported reading.
+    after: Option<DateTime<Local>>,
+
+    until: Option<DateTime<Local>>,
+
+    /// Formatted but not yet written readings.
+    buffer: Cursor<Vec<u8>>,
+
+    is_first: bool,
+    is_finished: bool,
+}
+
+impl ReadingsExport {
+    /// Formats the next chunk into the buffer, or the footer if there're no more readings.
+    fn fill_buffer(&mut self) -> Result {
+        let readings = self.db.select_readings_after(
+            &self.sensor_id,
+            self.after.as_ref(),
+            self.until.as_ref(),
+            EXPORT_CHUNK_SIZE,
+        )?;
+        let mut chunk = String::new();
+        for reading in &readings {
+            chunk.push_str(&self.format.format(reading, self.is_first)?);
+            self.is_first = false;
+        }
+        if readings.len() < EXPORT_CHUNK_SIZE as usize {
+            chunk.push_str(self.format.footer());
+            self.is_finished = true;
+        }
+        self.after = readings.last().map(|reading| reading.timestamp).or(self.after);
+        self.buffer = Cursor::new(chunk.into_bytes());
+        Ok(())
+    }
+}
+
+impl Read for ReadingsExport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while (self.buffer.position() as usize) == self.buffer.get_ref().len() {
+            if self.is_finished {
+                return Ok(0);
+            }
+            self.fill_buffer()
+                .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+        }
+        self.buffer.read(buf)
+    }
+}
+
+/// Formats the reading as a CSV row with the timestamp, value type and value columns.
+fn to_csv_row(reading: &Reading) -> Result<String> {
+    let value = serde_json::to_value(&reading.value)?;
+    let type_ = value["type"].as_str().unwrap_or_default();
+    let value = match &value["value"] {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    };
+    Ok(format!(
+        "{},{},{}\n",
+        reading.timestamp.to_rfc3339(),
+        escape_csv(type_),
+        escape_csv(&value),
+    ))
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!(r#""{}""#, value.replace('"', r#""""#))
+    } else {
+        value.into()
+    }
+}
+
+/// Deletes the single reading, for example, a bogus spike. An out-of-range timestamp matches no reading.
+#[delete("/sensors/<sensor_id>/readings/<timestamp_millis>", data = "<_form>")]
+pub fn delete_reading(
+    _user: Admin,
+    base_path: BasePath,
+    db: State<Connection>,
+    sensor_id: String,
+    timestamp_millis: i64,
+    _form: Csrf<Empty>,
+) -> Result<Option<Redirect>> {
+    let timestamp = match Local.timestamp_millis_opt(timestamp_millis).single() {
+        Some(timestamp) => timestamp,
+        None => return Ok(None),
+    };
+    if db.delete_reading(&sensor_id, &timestamp)? {
+        Ok(Some(Redirect::to(
+            base_path.prefixed(format!("/sensors/{}", Uri::percent_encode(&sensor_id))),
+        )))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_datetime_local_ok() {
+        assert_eq!(
+            parse_datetime_local("2020-07-01T12:30"),
+            Some(Local.ymd(2020, 7, 1).and_hms(12, 30, 0)),
+        );
+        assert_eq!(parse_datetime_local("yesterday"), None);
+    }
+
+    #[test]
+    fn to_query_ok() {
+        let range = ReadingsRange {
+            since: Some("2020-07-01T12:30".into()),
+            until: Some("".into()),
+        };
+        let query = range.to_query();
+        assert!(query.starts_with("since=2020-07-01T12"));
+        assert!(!query.contains("until"));
+    }
+
+    #[test]
+    fn to_csv_row_ok() -> Result {
+        let text = to_csv_row(&Reading {
+            timestamp: Local.timestamp_millis(0),
+            value: Value::Text("Hello, \"world\"".into()),
+        })?;
+        assert!(text.ends_with(",Text,\"Hello, \"\"world\"\"\"\n"));
+        let temperature = to_csv_row(&Reading {
+            timestamp: Local.timestamp_millis(1000),
+            value: Value::Temperature(21.5),
+        })?;
+        assert!(temperature.ends_with(",Temperature,21.5\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn export_in_chunks_ok() -> Result {
+        let db = Connection::open_and_initialize(":memory:")?;
+        let count = EXPORT_CHUNK_SIZE as i64 + 1;
+        for i in 0..count {
+            Message::new("test")
+                .value(Value::Counter(i as u64))
+                .timestamp(Local.timestamp_millis(i))
+                .upsert_into(&*db.connection()?)?;
+        }
+        let mut export = ReadingsExport {
+            db,
+            sensor_id: "test".into(),
+            format: ExportFormat::Json,
+            after: None,
+            until: None,
+            buffer: Cursor::new(ExportFormat::Json.header().into_bytes()),
+            is_first: true,
+            is_finished: false,
+        };
+        let mut json = String::new();
+        export.read_to_string(&mut json)?;
+        let readings: Vec<serde_json::Value> = serde_json::from_str(&json)?;
+        assert_eq!(readings.len(), count as usize);
+        assert_eq!(readings.last().unwrap()["value"]["value"], count - 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+use rocket::http::hyper::header::{ETag, EntityTag};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::Responder;
+use rocket::{Request, Response};
+
+use crate::web::if_none_match::IfNoneMatch;
+
+/// Responds with the entity tag and forces the client to revalidate the response every time.
+/// Responds with `304 Not Modified` if the client already has the matching entity.
+pub struct Revalidated<R>(pub EntityTag, pub R);
+
+impl<'r, R: Responder<'r>> Responder<'r> for Revalidated<R> {
+    fn respond_to(self, request: &Request) -> Result<Response<'r>, Status> {
+        let mut response = match IfNoneMatch::from_request(request) {
+            Outcome::Success(IfNoneMatch(entity_tag)) if entity_tag.weak_eq(&self.0) => {
+                Response::build().status(Status::NotModified).finalize()
+            }
+            _ => self.1.respond_to(request)?,
+        };
+        response.set_header(ETag(self.0));
+        response.set_raw_header("Cache-Control", "private, no-cache");
+        Ok(response)
+    }
+}
@@ -0,0 +1,193 @@
+//! Sensor search, filtering and sorting for the sensor list. The filter lives in the URL query.
+
+use std::collections::BTreeMap;
+
+use itertools::Itertools;
+use rocket::FromForm;
+
+use crate::core::db::{SensorOrder, SensorQuery};
+use crate::core::staleness;
+use crate::prelude::*;
+
+/// Title of the group of the sensors without tags.
+const UNTAGGED_GROUP: &str = "Untagged";
+
+#[derive(FromForm, Default)]
+pub struct SensorFilter {
+    /// Case-insensitive substring of the sensor ID, title or location.
+    pub q: Option<String>,
+
+    /// Value type, for example: `Temperature`.
+    pub value_type: Option<String>,
+
+    /// Shows only the sensors with the tag.
+    pub tag: Option<String>,
+
+    /// `tag` groups the sensors by tag instead of location.
+    pub group: Option<String>,
+
+    /// `true` shows only the stale sensors, `false` – only the fresh ones.
+    pub stale: Option<bool>,
+
+    /// `location`, `title` or `updated`.
+    pub sort: Option<String>,
+}
+
+impl SensorFilter {
+    pub fn q(&self) -> &str {
+        self.q.as_deref().map_or("", str::trim)
+    }
+
+    pub fn value_type(&self) -> &str {
+        self.value_type.as_deref().unwrap_or_default()
+    }
+
+    pub fn tag(&self) -> &str {
+        self.tag.as_deref().unwrap_or_default()
+    }
+
+    pub fn is_grouped_by_tag(&self) -> bool {
+        self.group.as_deref() == Some("tag")
+    }
+
+    pub fn order(&self) -> SensorOrder {
+        self.sort
+            .as_deref()
+            .and_then(|sort| sort.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Returns the `sort` parameter value of the effective order.
+    pub fn sort(&self) -> &'static str {
+        match self.order() {
+            SensorOrder::Location => "location",
+            SensorOrder::Title => "title",
+            SensorOrder::LastUpdated => "updated",
+        }
+    }
+
+    /// Returns the `stale` parameter value, empty if the staleness doesn't matter.
+    pub fn stale(&self) -> &'static str {
+        match self.stale {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "",
+        }
+    }
+
+    /// Tells whether any criteria has been specified.
+    pub fn is_active(&self) -> bool {
+        !self.q().is_empty() || !self.value_type().is_empty() || !self.tag().is_empty() || self.stale.is_some()
+    }
+
+    /// Tells whether the sensors are grouped. They're not when sorted by anything but location.
+    pub fn is_grouped(&self) -> bool {
+        self.is_grouped_by_tag() || self.order() == SensorOrder::Location
+    }
+
+    /// Groups the selected sensors, keeping their order within each group.
+    /// When grouped by tag, a sensor appears in each of its tags, the untagged sensors go last.
+    #[allow(clippy::type_complexity)]
+    pub fn group(&self, actuals: Vec<(Sensor, Reading)>) -> Vec<(String, Vec<(Sensor, Reading)>)> {
+        if self.is_grouped_by_tag() {
+            let mut groups: BTreeMap<String, Vec<(Sensor, Reading)>> = BTreeMap::new();
+            let mut untagged = Vec::new();
+            for actual in actuals {
+                if actual.0.tags.is_empty() {
+                    untagged.push(actual);
+                } else {
+                    for tag in &actual.0.tags {
+                        groups.entry(tag.clone()).or_default().push(actual.clone());
+                    }
+                }
+            }
+            let mut groups = groups.into_iter().collect_vec();
+            if !untagged.is_empty() {
+                groups.push((UNTAGGED_GROUP.into(), untagged));
+            }
+            groups
+        } else if self.order() == SensorOrder::Location {
+            actuals
+                .into_iter()
+                .group_by(|(sensor, _)| sensor.location.clone())
+                .into_iter()
+                .map(|(location, group)| (location, group.collect_vec()))
+                .collect_vec()
+        } else if actuals.is_empty() {
+            Vec::new()
+        } else {
+            vec![(String::new(), actuals)]
+        }
+    }
+
+    pub fn select(&self, db: &Connection) -> Result<Vec<(Sensor, Reading)>> {
+        let query = SensorQuery {
+            search: Some(self.q()).filter(|q| !q.is_empty()),
+            value_type: Some(self.value_type()).filter(|value_type| !value_type.is_empty()),
+            tag: Some(self.tag()).filter(|tag| !tag.is_empty()),
+            order: self.order(),
+        };
+        Ok(db
+            .search_actuals(&query)?
+            .into_iter()
+            .filter(|(sensor, reading)| {
+                self.stale
+                    .map_or(true, |stale| staleness::is_stale(&sensor.id, reading) == stale)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_ok() {
+        let filter = SensorFilter {
+            sort: Some("updated".into()),
+            ..Default::default()
+        };
+        assert_eq!(filter.order(), SensorOrder::LastUpdated);
+        assert_eq!(SensorFilter::default().order(), SensorOrder::Location);
+    }
+
+    #[test]
+    fn group_by_tag_ok() {
+        let filter = SensorFilter {
+            group: Some("tag".into()),
+            ..Default::default()
+        };
+        let actuals = vec![
+            Message::new("a").tag("outdoor").tag("battery"),
+            Message::new("b"),
+            Message::new("c").tag("battery"),
+        ]
+        .into_iter()
+        .map(|message| (message.sensor, message.reading))
+        .collect();
+        let groups: Vec<(String, Vec<String>)> = filter
+            .group(actuals)
+            .into_iter()
+            .map(|(tag, group)| (tag, group.into_iter().map(|(sensor, _)| sensor.id).collect()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                ("battery".to_string(), vec!["a".to_string(), "c".to_string()]),
+                ("outdoor".to_string(), vec!["a".to_string()]),
+                ("Untagged".to_string(), vec!["b".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn blank_filter_is_inactive() {
+        let filter = SensorFilter {
+            q: Some("  ".into()),
+            value_type: Some("".into()),
+            ..Default::default()
+        };
+        assert!(!filter.is_active());
+    }
+}
@@ -0,0 +1,142 @@
+//! Service statuses and controls: restarting and pausing the services.
+
+use rocket::response::Redirect;
+use rocket::{get, post, uri, State};
+use rocket_contrib::json::Json;
+
+use crate::core::logging::LOG_BUFFER;
+use crate::prelude::*;
+use crate::services::{ServiceStatus, Supervisor};
+use crate::web::auth::{Access, Csrf, Empty, Operator, Viewer};
+use crate::web::base_path::BasePath;
+use crate::web::layout::Layout;
+use crate::web::templates;
+use crate::web::to_html_string::ToHtmlString;
+
+/// Service status along with the sensors produced by the service.
+#[derive(Serialize)]
+pub struct ServiceSummary {
+    #[serde(flatten)]
+    pub status: ServiceStatus,
+
+    pub average_duration_millis: Option<f64>,
+
+    /// Number of the error records of the service in the log buffer.
+    pub recent_error_count: usize,
+
+    /// Sensors whose IDs start with the service ID.
+    pub sensors: Vec<Sensor>,
+}
+
+#[get("/services")]
+pub fn get_services(
+    _user: Viewer,
+    layout: Layout,
+    db: State<Connection>,
+    supervisor: State<Supervisor>,
+) -> Result<ToHtmlString<impl ToString>> {
+    Ok(ToHtmlString(templates::ServicesTemplate {
+        services: summarize(&supervisor, &db, |_| true)?,
+        layout,
+    }))
+}
+
+/// Returns the service statuses, API tokens only see the sensors which their scopes allow to read.
+#[get("/services")]
+pub fn get_services_json(
+    access: Access,
+    db: State<Connection>,
+    supervisor: State<Supervisor>,
+) -> Result<Json<Vec<ServiceSummary>>> {
+    Ok(Json(summarize(&supervisor, &db, |sensor_id| {
+        access.can_read(sensor_id)
+    })?))
+}
+
+#[post("/services/<service_id>/restart", data = "<_form>")]
+pub fn post_restart(
+    _user: Operator,
+    base_path: BasePath,
+    supervisor: State<Supervisor>,
+    service_id: String,
+    _form: Csrf<Empty>,
+) -> Option<Redirect> {
+    if supervisor.restart(&service_id) {
+        Some(Redirect::to(base_path.prefixed(uri!(get_services))))
+    } else {
+        None
+    }
+}
+
+#[post("/services/<service_id>/pause", data = "<_form>")]
+pub fn post_pause(
+    _user: Operator,
+    base_path: BasePath,
+    supervisor: State<Supervisor>,
+    service_id: String,
+    _form: Csrf<Empty>,
+) -> Option<Redirect> {
+    if supervisor.set_paused(&service_id, true) {
+        Some(Redirect::to(base_path.prefixed(uri!(get_services))))
+    } else {
+        None
+    }
+}
+
+#[post("/services/<service_id>/resume", data = "<_form>")]
+pub fn post_resume(
+    _user: Operator,
+    base_path: BasePath,
+    supervisor: State<Supervisor>,
+    service_id: String,
+    _form: Csrf<Empty>,
+) -> Option<Redirect> {
+    if supervisor.set_paused(&service_id, false) {
+        Some(Redirect::to(base_path.prefixed(uri!(get_services))))
+    } else {
+        None
+    }
+}
+
+fn summarize<F: Fn(&str) -> bool>(
+    supervisor: &Supervisor,
+    db: &Connection,
+    can_read: F,
+) -> Result<Vec<ServiceSummary>> {
+    let actuals = db.select_actuals()?;
+    Ok(supervisor
+        .statuses()
+        .into_iter()
+        .map(|status| ServiceSummary {
+            average_duration_millis: status
+                .stats
+                .average_duration()
+                .map(|duration| duration.as_secs_f64() * 1000.0),
+            recent_error_count: LOG_BUFFER.error_count(&status.service_id),
+            sensors: actuals
+                .iter()
+                .filter(|(sensor, _)| is_produced_by(&sensor.id, &status.service_id) && can_read(&sensor.id))
+                .map(|(sensor, _)| sensor.clone())
+                .collect(),
+            status,
+        })
+        .collect())
+}
+
+/// Checks whether the sensor belongs to the service, for example: `buienradar::6240::feel_temperature`.
+fn is_produced_by(sensor_id: &str, service_id: &str) -> bool {
+    sensor_id.starts_with(service_id)
+        && (sensor_id.len() == service_id.len() || sensor_id[service_id.len()..].starts_with("::"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_produced_by_ok() {
+        assert!(is_produced_by("clock", "clock"));
+        assert!(is_produced_by("buienradar::6240::temperature", "buienradar"));
+        assert!(!is_produced_by("buienradar_2::6240::temperature", "buienradar"));
+    }
+}
@@ -0,0 +1,508 @@
+//! Settings editor: validates the edited settings, saves them and restarts the changed services.
+//!
+//! The secret values are redacted in the editor. The redacted ones are taken from the saved file.
+//! The editor isn't shown if some secrets are specified in a way which the redaction doesn't cover.
+
+use std::fs;
+use std::path::PathBuf;
+
+use rocket::{get, post, FromForm, State};
+
+use crate::core::{datetime, staleness, tags};
+use crate::prelude::*;
+use crate::services::{ServiceChanges, Supervisor};
+use crate::settings::secret::REDACTED;
+use crate::settings::{self, Settings, Tls};
+use crate::web::auth::{Admin, Csrf};
+use crate::web::layout::Layout;
+use crate::web::live_settings::{CurrentSettings, LiveSettings};
+use crate::web::templates;
+use crate::web::to_html_string::ToHtmlString;
+
+/// Unchanged lines which are displayed around a change.
+const DIFF_CONTEXT: usize = 2;
+
+/// Settings files as they're specified in the command line.
+/// Only the first one is edited, the others are merged in when the settings get validated.
+pub struct SettingsPaths(pub Vec<PathBuf>);
+
+#[derive(FromForm)]
+pub struct SettingsForm {
+    content: String,
+
+    /// Either `check` or `save`.
+    action: String,
+}
+
+/// Settings validation error.
+pub struct SettingsError {
+    pub message: String,
+
+    /// One-based line number in the edited file, if known.
+    pub line: Option<usize>,
+
+    /// Text of the line with the error.
+    pub line_text: String,
+}
+
+/// Line of a diff.
+pub struct DiffLine {
+    /// `+` for an added line, `-` for a removed one, a space for an unchanged one and `…` for skipped lines.
+    pub marker: &'static str,
+
+    pub text: String,
+}
+
+#[get("/settings")]
+pub fn get_settings(
+    _user: Admin,
+    layout: Layout,
+    settings: CurrentSettings,
+    paths: State<SettingsPaths>,
+) -> Result<ToHtmlString<impl ToString>> {
+    let (content, error) = match redact(&read_edited_file(&paths)?) {
+        Ok(content) => (Some(content), None),
+        Err(error) => (None, Some(error)),
+    };
+    Ok(ToHtmlString(templates::SettingsTemplate {
+        content,
+        running: toml::to_string_pretty(&toml::Value::try_from(&*settings)?)?,
+        path: paths.0.first().map(|path| path.display().to_string()),
+        error,
+        diff: Vec::new(),
+        changes: None,
+        has_other_changes: false,
+        is_saved: false,
+        layout,
+    }))
+}
+
+/// Validates the settings and shows the changes. Saves and applies them if requested.
+#[post("/settings", data = "<form>")]
+pub fn post_settings(
+    _user: Admin,
+    layout: Layout,
+    settings: CurrentSettings,
+    live_settings: State<LiveSettings>,
+    paths: State<SettingsPaths>,
+    supervisor: State<Supervisor>,
+    form: Csrf<SettingsForm>,
+) -> Result<ToHtmlString<impl ToString>> {
+    let Csrf(form) = form;
+    let path = paths.0.first().map(|path| path.display().to_string());
+    let running = toml::to_string_pretty(&toml::Value::try_from(&*settings)?)?;
+    let saved_content = read_edited_file(&paths)?;
+    let redacted_saved_content = match redact(&saved_content) {
+        Ok(content) => content,
+        Err(error) => {
+            return Ok(ToHtmlString(templates::SettingsTemplate {
+                error: Some(error),
+                content: None,
+                running,
+                path,
+                diff: Vec::new(),
+                changes: None,
+                has_other_changes: false,
+                is_saved: false,
+                layout,
+            }));
+        }
+    };
+    let other_contents = paths
+        .0
+        .iter()
+        .skip(1)
+        .map(fs::read_to_string)
+        .collect::<std::io::Result<Vec<String>>>()?;
+
+    let (content, parsed) = match unredact(&form.content, &saved_content) {
+        Ok(content) => match parse_with_others(&content, &other_contents) {
+            Ok(new_settings) => (content, Ok(new_settings)),
+            Err(error) => (content.clone(), Err(to_settings_error(&error, &content))),
+        },
+        Err(error) => (form.content.clone(), Err(error)),
+    };
+    // The saved secrets must stay redactable, so that the editor may be shown again.
+    let parsed = parsed.and_then(|new_settings| redact(&content).map(|redacted| (new_settings, redacted)));
+    let (new_settings, redacted_content) = match parsed {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            return Ok(ToHtmlString(templates::SettingsTemplate {
+                error: Some(error),
+                content: Some(form.content),
+                running,
+                path,
+                diff: Vec::new(),
+                changes: None,
+                has_other_changes: false,
+                is_saved: false,
+                layout,
+            }));
+        }
+    };
+
+    let diff = diff_lines(&redacted_saved_content, &redacted_content);
+    let has_other_changes = startup_settings(&new_settings) != startup_settings(&settings);
+    let is_saved = form.action == "save";
+    let changes = if is_saved {
+        let path = paths.0.first().ok_or("there's no settings file to save")?;
+        write_atomically(path, &content)?;
+        info!("Saved the settings into {:?}.", path);
+        staleness::set_expected_updates(&new_settings.expected_updates)?;
+        tags::set_tag_overrides(&new_settings.sensor_tags)?;
+        datetime::set_display(new_settings.timezone, new_settings.datetime_format);
+        let changes = supervisor.apply(&new_settings)?;
+        live_settings.replace(new_settings);
+        changes
+    } else {
+        supervisor.diff(&new_settings)?
+    };
+
+    Ok(ToHtmlString(templates::SettingsTemplate {
+        // Once saved, the secrets get redacted again.
+        content: Some(if is_saved { redacted_content } else { form.content }),
+        running,
+        path,
+        error: None,
+        diff,
+        changes: Some(changes),
+        has_other_changes,
+        is_saved,
+        layout,
+    }))
+}
+
+/// Parses the edited settings along with the other files.
+fn parse_with_others(content: &str, other_contents: &[String]) -> Result<Settings, toml::de::Error> {
+    let mut contents = vec![content.to_string()];
+    contents.extend_from_slice(other_contents);
+    settings::parse(&contents)
+}
+
+fn read_edited_file(paths: &SettingsPaths) -> Result<String> {
+    Ok(match paths.0.first() {
+        Some(path) if path.exists() => fs::read_to_string(path)?,
+        _ => String::new(),
+    })
+}
+
+/// Writes into a temporary file first, so that the settings file doesn't get corrupted.
+fn write_atomically(path: &PathBuf, content: &str) -> Result {
+    let temporary_path = path.with_extension("tmp");
+    fs::write(&temporary_path, content)?;
+    fs::rename(&temporary_path, path)?;
+    Ok(())
+}
+
+/// Returns the settings which can't be applied without restarting My IoT.
+/// The web server is bound to them, the rest is replaced when the settings get saved.
+fn startup_settings(settings: &Settings) -> (u16, &[String], Option<&Tls>, &str) {
+    (
+        settings.http_port,
+        &settings.http_addresses,
+        settings.tls.as_ref(),
+        &settings.base_path,
+    )
+}
+
+/// Secret value as it's specified in the line, if the line is inside a `secrets` table.
+/// References to environment variables and files aren't secret themselves.
+struct SecretLine<'a> {
+    table: &'a str,
+    key: &'a str,
+    value: &'a str,
+}
+
+/// Finds the secret values, line by line. It's not a complete TOML parser,
+/// but it covers the `[….secrets]` tables with the `key = value` lines.
+/// The other forms are caught by `redact`.
+fn secret_lines(content: &str) -> Vec<Option<SecretLine>> {
+    let mut table = "";
+    let mut string_delimiter = None;
+    content
+        .lines()
+        .map(|line| {
+            // Lines inside a multi-line string are neither table headers nor keys.
+            let is_inside_string = string_delimiter.is_some();
+            string_delimiter = multiline_string_delimiter(line, string_delimiter);
+            if is_inside_string {
+                return None;
+            }
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') {
+                // The header may be followed by a comment.
+                table = trimmed
+                    .trim_start_matches('[')
+                    .split(']')
+                    .next()
+                    .unwrap_or_default()
+                    .trim();
+                return None;
+            }
+            if table != "secrets" && !table.ends_with(".secrets") {
+                return None;
+            }
+            let mut parts = trimmed.splitn(2, '=');
+            let key = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            if key.is_empty() || key.starts_with('#') || value.starts_with('{') {
+                return None;
+            }
+            Some(SecretLine { table, key, value })
+        })
+        .collect()
+}
+
+/// Returns the delimiter of the multi-line string which continues after the line, if any.
+fn multiline_string_delimiter(line: &str, open_delimiter: Option<&'static str>) -> Option<&'static str> {
+    match open_delimiter {
+        Some(delimiter) if line.matches(delimiter).count() % 2 == 1 => None,
+        Some(delimiter) => Some(delimiter),
+        None => ["\"\"\"", "'''"]
+            .iter()
+            .copied()
+            .find(|delimiter| line.matches(delimiter).count() % 2 == 1),
+    }
+}
+
+/// Replaces the secret values with the placeholder.
+/// Fails if some secrets are left, for example, in an inline table, with a dotted key or in a multi-line string.
+fn redact(content: &str) -> Result<String, SettingsError> {
+    let redacted: String = content
+        .lines()
+        .zip(secret_lines(content))
+        .map(|(line, secret)| match secret {
+            Some(secret) => format!(
+                "{}{} = \"{}\"",
+                &line[..line.len() - line.trim_start().len()],
+                secret.key,
+                REDACTED
+            ),
+            None => line.to_string(),
+        })
+        .map(|line| line + "\n")
+        .collect();
+    match toml::from_str::<toml::Value>(&redacted) {
+        Ok(value) if !has_secrets(&value) => Ok(redacted),
+        _ => Err(SettingsError {
+            message: "Secrets can't be redacted. Put each of them on its own `key = value` line \
+                      in a `[….secrets]` table, and edit the file directly meanwhile."
+                .into(),
+            line: None,
+            line_text: String::new(),
+        }),
+    }
+}
+
+/// Checks whether the parsed settings contain secret values which aren't redacted.
+fn has_secrets(value: &toml::Value) -> bool {
+    match value {
+        toml::Value::Table(table) => table.iter().any(|(key, value)| match (key.as_str(), value) {
+            ("secrets", toml::Value::Table(secrets)) => secrets.values().any(is_revealed),
+            ("secrets", _) => true,
+            _ => has_secrets(value),
+        }),
+        toml::Value::Array(values) => values.iter().any(has_secrets),
+        _ => false,
+    }
+}
+
+/// Checks whether the secret is shown as is.
+/// References to environment variables and files aren't secret themselves.
+fn is_revealed(secret: &toml::Value) -> bool {
+    match secret {
+        toml::Value::String(value) => value != REDACTED,
+        toml::Value::Table(table) => !(table.len() == 1 && (table.contains_key("env") || table.contains_key("file"))),
+        _ => true,
+    }
+}
+
+/// Puts the saved secret values back in place of the placeholders.
+fn unredact(content: &str, saved_content: &str) -> Result<String, SettingsError> {
+    let redacted_value = format!("\"{}\"", REDACTED);
+    let saved_secrets: Vec<SecretLine> = secret_lines(saved_content).into_iter().flatten().collect();
+    content
+        .lines()
+        .zip(secret_lines(content))
+        .map(|(line, secret)| match secret {
+            Some(secret) if secret.value == redacted_value => saved_secrets
+                .iter()
+                .find(|saved| saved.table == secret.table && saved.key == secret.key)
+                .map(|saved| line.replacen(&redacted_value, saved.value, 1))
+                .ok_or_else(|| SettingsError {
+                    message: format!("`{}` in `[{}]` is redacted, enter its value", secret.key, secret.table),
+                    line: None,
+                    line_text: String::new(),
+                }),
+            _ => Ok(line.to_string()),
+        })
+        .map(|line| line.map(|line| line + "\n"))
+        .collect()
+}
+
+fn to_settings_error(error: &toml::de::Error, content: &str) -> SettingsError {
+    // The other files follow the edited one, so the line may point outside of it.
+    let line = error
+        .line_col()
+        .map(|(line, _)| line)
+        .filter(|line| *line < content.lines().count());
+    SettingsError {
+        message: error.to_string(),
+        line: line.map(|line| line + 1),
+        line_text: line
+            .and_then(|line| content.lines().nth(line))
+            .unwrap_or_default()
+            .into(),
+    }
+}
+
+/// Builds the line diff with the [longest common subsequence](https://en.wikipedia.org/wiki/Longest_common_subsequence_problem).
+/// Unchanged lines far from the changes are skipped. Returns nothing if the texts are the same.
+fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // `lengths[i][j]` is the common subsequence length of `old[i..]` and `new[j..]`.
+    let mut lengths = vec![vec![0_usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((" ", old[i]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lengths[i][j + 1] > lengths[i + 1][j]) {
+            lines.push(("+", new[j]));
+            j += 1;
+        } else {
+            lines.push(("-", old[i]));
+            i += 1;
+        }
+    }
+
+    let changes: Vec<usize> = (0..lines.len()).filter(|k| lines[*k].0 != " ").collect();
+    if changes.is_empty() {
+        return Vec::new();
+    }
+    let is_near_change = |k: usize| {
+        changes
+            .iter()
+            .any(|change| k + DIFF_CONTEXT >= *change && k <= change + DIFF_CONTEXT)
+    };
+    let mut diff: Vec<DiffLine> = Vec::new();
+    for (k, (marker, text)) in lines.into_iter().enumerate() {
+        if is_near_change(k) {
+            diff.push(DiffLine {
+                marker,
+                text: text.into(),
+            });
+        } else if diff.last().map_or(true, |line| line.marker != "…") {
+            diff.push(DiffLine {
+                marker: "…",
+                text: String::new(),
+            });
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markers(diff: &[DiffLine]) -> String {
+        diff.iter().map(|line| line.marker).collect()
+    }
+
+    #[test]
+    fn diff_lines_ok() {
+        let diff = diff_lines("a\nb\nc\n", "a\nx\nc\nd\n");
+        assert_eq!(markers(&diff), " -+ +");
+        assert_eq!(diff[1].text, "b");
+        assert_eq!(diff[2].text, "x");
+    }
+
+    #[test]
+    fn diff_lines_skips_unchanged() {
+        let diff = diff_lines("1\n2\n3\n4\n5\n6\n", "1\n2\n3\n4\n5\n7\n");
+        assert_eq!(markers(&diff), "…  -+");
+    }
+
+    #[test]
+    fn diff_lines_same() {
+        assert!(diff_lines("a\nb\n", "a\nb\n").is_empty());
+    }
+
+    #[test]
+    fn redact_ok() {
+        let content =
+            "[tokens.esp.secrets]\ntoken_hash = \"abc\"\n\n[services.tado.secrets]\n  password = { env = \"TADO\" }\n";
+        assert_eq!(
+            redact(content).ok().unwrap(),
+            "[tokens.esp.secrets]\ntoken_hash = \"<redacted>\"\n\n[services.tado.secrets]\n  password = { env = \"TADO\" }\n",
+        );
+    }
+
+    #[test]
+    fn redact_header_comment_ok() {
+        assert_eq!(
+            redact("[services.tg.secrets] # bot\ntoken = \"abc\"\n").ok().unwrap(),
+            "[services.tg.secrets] # bot\ntoken = \"<redacted>\"\n",
+        );
+    }
+
+    #[test]
+    fn redact_keeps_multiline_strings() {
+        let content = "[services.rhai]\nscript = \"\"\"\n[secrets]\nx = 1\n\"\"\"\n";
+        assert_eq!(redact(content).ok().unwrap(), content);
+    }
+
+    #[test]
+    fn redact_inline_table_fails() {
+        assert!(redact("[services.tg]\nsecrets = { token = \"abc\" }\n").is_err());
+    }
+
+    #[test]
+    fn redact_dotted_key_fails() {
+        assert!(redact("[services.tg]\nsecrets.token = \"abc\"\n").is_err());
+    }
+
+    #[test]
+    fn redact_multiline_secret_fails() {
+        assert!(redact("[services.tg.secrets]\ntoken = \"\"\"\nabc\n\"\"\"\n").is_err());
+    }
+
+    #[test]
+    fn unredact_ok() {
+        let saved = "[tokens.esp.secrets]\ntoken_hash = \"abc\"\n";
+        let edited = "http_port = 8082\n[tokens.esp.secrets]\ntoken_hash = \"<redacted>\"\n";
+        assert_eq!(
+            unredact(edited, saved).ok().unwrap(),
+            "http_port = 8082\n[tokens.esp.secrets]\ntoken_hash = \"abc\"\n",
+        );
+    }
+
+    #[test]
+    fn unredact_unknown_fails() {
+        assert!(unredact("[tokens.new.secrets]\ntoken_hash = \"<redacted>\"\n", "").is_err());
+    }
+
+    #[test]
+    fn to_settings_error_ok() {
+        let content = "http_port = 8080\n[services\n";
+        let error = settings::parse(&[content.to_string()]).unwrap_err();
+        let error = to_settings_error(&error, content);
+        assert_eq!(error.line, Some(2));
+        assert_eq!(error.line_text, "[services");
+    }
+}
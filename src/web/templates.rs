@@ -1,12 +1,41 @@
 //! Web interface templates.
 
 use askama::Template;
+use itertools::Itertools;
 use rocket::uri;
 use serde_json::json;
 
+use crate::core::control::Control;
+use crate::core::datetime;
+use crate::core::db::api_token::ApiToken;
+use crate::core::logging::LogRecord;
+use crate::core::staleness;
 use crate::format::human_format;
 use crate::prelude::*;
-use crate::web::{rocket_uri_macro_delete_sensor, rocket_uri_macro_get_sensor_json, rocket_uri_macro_get_settings};
+use crate::services::{ServiceChanges, ServiceState};
+use crate::settings::TileSize;
+use crate::web::auth::{rocket_uri_macro_post_login, rocket_uri_macro_post_logout};
+use crate::web::base_path::BasePath;
+use crate::web::controls::rocket_uri_macro_post_write;
+use crate::web::dashboards::{
+    rocket_uri_macro_delete_dashboard, rocket_uri_macro_get_dashboard, rocket_uri_macro_get_dashboard_editor,
+    rocket_uri_macro_post_dashboard,
+};
+use crate::web::energy::{rocket_uri_macro_get_energy, Bar, HeatmapDay, Resolution};
+use crate::web::layout::Layout;
+use crate::web::logs::rocket_uri_macro_get_logs;
+use crate::web::lttb;
+use crate::web::readings::rocket_uri_macro_delete_reading;
+use crate::web::sensor_filter::SensorFilter;
+use crate::web::service_status::{
+    rocket_uri_macro_get_services, rocket_uri_macro_post_pause, rocket_uri_macro_post_restart,
+    rocket_uri_macro_post_resume, ServiceSummary,
+};
+use crate::web::settings_editor::{
+    rocket_uri_macro_get_settings, rocket_uri_macro_post_settings, DiffLine, SettingsError,
+};
+use crate::web::tokens::{rocket_uri_macro_delete_token, rocket_uri_macro_get_tokens, rocket_uri_macro_post_token};
+use crate::web::{rocket_uri_macro_delete_sensor, rocket_uri_macro_get_all_sensors, rocket_uri_macro_get_sensor_json};
 
 #[derive(Template)]
 #[template(path = "index.html")]
@@ -14,16 +43,45 @@ pub struct IndexTemplate {
     #[allow(clippy::type_complexity)]
     pub actuals: Vec<(String, Vec<(Sensor, Reading)>)>,
 
-    pub message_count: u64,
+    /// Whether the sensors are grouped by location or tag, otherwise there's a single group.
+    pub is_grouped: bool,
+
+    /// Value types to choose from in the filter.
+    pub value_types: Vec<String>,
+
+    /// Tags to choose from in the filter.
+    pub tags: Vec<String>,
+
+    pub filter: SensorFilter,
+    pub layout: Layout,
 }
 
 #[derive(Template)]
 #[template(path = "settings.html")]
 pub struct SettingsTemplate {
-    pub settings: String,
+    pub layout: Layout,
+
+    /// Content of the edited settings file, `None` if its secrets can't be redacted.
+    pub content: Option<String>,
+
+    /// Path of the edited settings file, if any.
+    pub path: Option<String>,
+
+    /// Currently running settings in TOML.
+    pub running: String,
+
+    pub error: Option<SettingsError>,
+
+    /// Diff between the saved file and the edited content.
+    pub diff: Vec<DiffLine>,
+
+    /// Services which are or would be restarted, if the settings have been checked.
+    pub changes: Option<ServiceChanges>,
+
+    /// Whether there are changes which require a restart of My IoT.
+    pub has_other_changes: bool,
 
-    /// Statistics in the footer.
-    pub message_count: u64,
+    pub is_saved: bool,
 }
 
 #[derive(Template)]
@@ -32,33 +90,344 @@ pub struct SensorTemplate {
     pub sensor: Sensor,
     pub reading: Reading,
 
-    /// Stringified sensor chart, may be empty.
-    pub chart: String,
+    /// Stringified sensor history: a chart, a timeline, a wind rose or an event log.
+    pub history: String,
 
-    /// Chart period.
+    pub history_view: HistoryView,
+
+    /// Stringified table of the raw readings.
+    pub readings_table: String,
+
+    /// Stringified control input, empty unless the sensor is controllable.
+    pub control: String,
+
+    /// History period.
     pub minutes: i64,
 
     pub reading_count: u64,
 
-    /// Statistics in the footer.
-    pub message_count: u64,
+    pub layout: Layout,
+}
+
+#[derive(Template)]
+#[template(path = "services.html")]
+pub struct ServicesTemplate {
+    pub layout: Layout,
+    pub services: Vec<ServiceSummary>,
+}
+
+/// Levels to filter the log records by, the most severe first.
+pub const LOG_LEVELS: &[&str] = &["error", "warn", "info", "debug", "trace"];
+
+#[derive(Template)]
+#[template(path = "logs.html")]
+pub struct LogsTemplate {
+    pub layout: Layout,
+
+    /// Matching records, newest first.
+    pub records: Vec<LogRecord>,
+
+    /// Thread names to filter by.
+    pub threads: Vec<String>,
+
+    /// Selected thread name, empty for all the threads.
+    pub service: String,
+
+    /// Selected level in lower case.
+    pub level: String,
+
+    /// ID of the newest shown record, the live tailing starts after it.
+    pub last_id: u64,
+}
+
+impl LogRecord {
+    /// Returns the [Bulma color class](https://bulma.io/documentation/modifiers/color-helpers/) of the level tag.
+    pub fn color_class(&self) -> &'static str {
+        match self.level {
+            LogLevel::Error => "is-danger",
+            LogLevel::Warn => "is-warning",
+            LogLevel::Info => "is-info",
+            LogLevel::Debug | LogLevel::Trace => "is-light",
+        }
+    }
+}
+
+impl ServiceState {
+    /// Returns the [Bulma color class](https://bulma.io/documentation/modifiers/color-helpers/) of the state tag.
+    pub fn color_class(self) -> &'static str {
+        match self {
+            ServiceState::Running => "is-success",
+            ServiceState::Paused => "is-warning",
+            ServiceState::Failed => "is-danger",
+            ServiceState::Disabled => "is-light",
+        }
+    }
+}
+
+/// Sensor history view, it's chosen automatically from the value.
+#[derive(PartialEq, Clone, Copy)]
+pub enum HistoryView {
+    /// Line chart of a numeric sensor.
+    Chart,
+
+    /// Intervals when a sensor had the same state.
+    Timeline,
+
+    /// Distribution of the wind directions.
+    WindRose,
+
+    /// Paginated table of the latest readings.
+    EventLog,
+}
+
+impl HistoryView {
+    pub fn is_chart(self) -> bool {
+        self == HistoryView::Chart
+    }
+
+    /// Whether the view displays a period, otherwise it's paginated.
+    pub fn is_periodic(self) -> bool {
+        self != HistoryView::EventLog
+    }
+}
+
+/// State timeline of a sensor which has discrete values.
+#[derive(Template)]
+#[template(path = "partials/timeline.html")]
+pub struct TimelinePartialTemplate {
+    segments: Vec<TimelineSegment>,
+
+    /// Total duration of each state.
+    totals: Vec<(String, String, String)>,
+}
+
+struct TimelineSegment {
+    /// State label, empty when the state is unknown.
+    label: String,
+
+    /// [Background color](https://bulma.io/documentation/modifiers/color-helpers/) class.
+    background_class: String,
+
+    since: DateTime<Local>,
+    until: DateTime<Local>,
+
+    /// Width in percents of the whole period.
+    width: f64,
+}
+
+impl TimelinePartialTemplate {
+    /// Builds the timeline from the state changes ordered by timestamp and the total duration of each value.
+    pub fn new(
+        readings: &[Reading],
+        durations: &[(Value, chrono::Duration)],
+        since: DateTime<Local>,
+        until: DateTime<Local>,
+    ) -> Self {
+        let mut segments: Vec<TimelineSegment> = Vec::new();
+        let first_timestamp = readings.first().map_or(until, |reading| reading.timestamp);
+        if first_timestamp > since {
+            segments.push(TimelineSegment {
+                label: String::new(),
+                background_class: "has-background-white-ter".into(),
+                since,
+                until: first_timestamp,
+                width: 0.0,
+            });
+        }
+        for (i, reading) in readings.iter().enumerate() {
+            let segment_until = readings.get(i + 1).map_or(until, |next| next.timestamp);
+            let label = reading.value.state_label();
+            match segments.last_mut() {
+                Some(last) if last.label == label => last.until = segment_until,
+                _ => segments.push(TimelineSegment {
+                    label,
+                    background_class: filters::color_class(&reading.value)
+                        .unwrap_or("is-light")
+                        .replace("is-", "has-background-"),
+                    since: reading.timestamp,
+                    until: segment_until,
+                    width: 0.0,
+                }),
+            }
+        }
+
+        let total_millis = (until - since).num_milliseconds().max(1) as f64;
+        for segment in segments.iter_mut() {
+            segment.width = (segment.until - segment.since).num_milliseconds() as f64 / total_millis * 100.0;
+        }
+
+        // Different values may share a label, for example, the numeric ones.
+        let mut totals: Vec<(String, String, chrono::Duration)> = Vec::new();
+        for (value, duration) in durations {
+            let label = value.state_label();
+            match totals.iter_mut().find(|(total_label, _, _)| *total_label == label) {
+                Some(total) => total.2 = total.2 + *duration,
+                None => totals.push((
+                    label,
+                    filters::color_class(value)
+                        .unwrap_or("is-light")
+                        .replace("is-", "has-background-"),
+                    *duration,
+                )),
+            }
+        }
+        totals.sort_by(|(_, _, left), (_, _, right)| right.cmp(left));
+
+        TimelinePartialTemplate {
+            segments,
+            totals: totals
+                .into_iter()
+                .map(|(label, background_class, duration)| (label, background_class, format_duration(duration)))
+                .collect(),
+        }
+    }
+}
+
+/// Formats the duration in hours and minutes, for example: `2 h 5 min`.
+fn format_duration(duration: chrono::Duration) -> String {
+    let minutes = duration.num_minutes();
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{} min", minutes),
+        (hours, 0) => format!("{} h", hours),
+        (hours, minutes) => format!("{} h {} min", hours, minutes),
+    }
+}
+
+/// [Wind rose](https://en.wikipedia.org/wiki/Wind_rose) chart.
+#[derive(Template)]
+#[template(path = "partials/wind_rose.html")]
+pub struct WindRosePartialTemplate {
+    chart: serde_json::Value,
+}
+
+impl WindRosePartialTemplate {
+    /// Builds the chart from the reading counts by value.
+    pub fn new(value_counts: &[(Value, u64)]) -> Self {
+        // The points of the compass are declared clockwise starting from the North.
+        let mut counts = [0_u64; 16];
+        for (value, count) in value_counts {
+            if let Value::WindDirection(point) = value {
+                counts[*point as usize] += count;
+            }
+        }
+        let total = counts.iter().sum::<u64>().max(1) as f64;
+        WindRosePartialTemplate {
+            chart: json!({
+                "type": "polarArea",
+                "options": {
+                    "animation": {"duration": 0},
+                    "maintainAspectRatio": false,
+                    "legend": {"display": false},
+                    // Centers the North sector at the top.
+                    "startAngle": -std::f64::consts::FRAC_PI_2 - std::f64::consts::PI / 16.0,
+                    "scale": {"ticks": {"display": false}},
+                },
+                "data": {
+                    "labels": [
+                        "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE",
+                        "S", "SSW", "SW", "WSW", "W", "WNW", "NW", "NNW",
+                    ],
+                    "datasets": [{
+                        "label": "%",
+                        "backgroundColor": "rgba(32, 156, 238, 0.5)",
+                        "borderColor": "#209CEE",
+                        "data": counts.iter().map(|count| (*count as f64 / total * 1000.0).round() / 10.0).collect::<Vec<f64>>(),
+                    }],
+                },
+            }),
+        }
+    }
+}
+
+/// Paginated table of the latest readings.
+#[derive(Template)]
+#[template(path = "partials/readings_table.html")]
+pub struct ReadingsTablePartialTemplate {
+    pub base_path: BasePath,
+    pub sensor_id: String,
+
+    /// Readings on the current page, the newest first.
+    pub rows: Vec<ReadingRow>,
+
+    /// Selected period as it's submitted by the inputs.
+    pub range_since: String,
+    pub range_until: String,
+
+    /// Selected period to preserve in the pagination and export links.
+    pub range_query: String,
+
+    /// Period of the sensor page history, to preserve in the pagination links.
+    pub minutes: i64,
+
+    pub page: u32,
+    pub has_next_page: bool,
+
+    /// Whether to show the buttons to delete the individual readings.
+    pub can_delete: bool,
+
+    pub csrf_token: String,
+}
+
+/// Paginated table of the latest readings.
+#[derive(Template)]
+#[template(path = "partials/event_log.html")]
+pub struct EventLogPartialTemplate {
+    /// Readings on the current page, the newest first.
+    pub readings: Vec<Reading>,
+
+    pub page: u32,
+    pub has_next_page: bool,
+}
+
+pub struct ReadingRow {
+    pub reading: Reading,
+
+    /// Value serialized into JSON.
+    pub raw_value: String,
+}
+
+#[derive(Template)]
+#[template(path = "login.html")]
+pub struct LoginTemplate {
+    pub layout: Layout,
+
+    /// Where to redirect after logging in.
+    pub next: String,
+
+    /// Whether the previous attempt has failed.
+    pub is_failed: bool,
+}
+
+#[derive(Template)]
+#[template(path = "tokens.html")]
+pub struct TokensTemplate {
+    pub layout: Layout,
+    pub tokens: Vec<ApiToken>,
+
+    pub created_token: Option<CreatedToken>,
+    pub error: Option<String>,
 }
 
-impl SensorTemplate {
-    /// Refresh a sensor view this many times per minute.
-    const AUTO_REFRESH_PER_MINUTE: i64 = 12;
+/// Just created token, which is shown only once.
+pub struct CreatedToken {
+    pub name: String,
+    pub token: String,
 }
 
 /// Navigation bar.
 #[derive(Template)]
 #[template(path = "partials/navbar.html")]
-struct NavbarPartialTemplate<'a> {
+pub struct NavbarPartialTemplate<'a> {
     selected_item: &'a str,
+    layout: &'a Layout,
 }
 
-impl<'a> NavbarPartialTemplate<'a> {
-    fn new(selected_item: &'a str) -> Self {
-        NavbarPartialTemplate { selected_item }
+impl Layout {
+    pub fn navbar<'a>(&'a self, selected_item: &'a str) -> NavbarPartialTemplate<'a> {
+        NavbarPartialTemplate {
+            selected_item,
+            layout: self,
+        }
     }
 }
 
@@ -66,15 +435,371 @@ impl<'a> NavbarPartialTemplate<'a> {
 #[derive(Template)]
 #[template(path = "partials/sensor_tile.html")]
 struct SensorTilePartialTemplate<'a> {
+    base_path: &'a BasePath,
     sensor: &'a Sensor,
 
     /// The latest reading.
     reading: &'a Reading,
+
+    /// [Column size](https://bulma.io/documentation/columns/sizes/).
+    column_width: &'static str,
+
+    /// Stringified mini-chart, may be empty.
+    chart: String,
+
+    /// Stringified control input, empty unless the sensor is controllable.
+    control: String,
+
+    /// Whether the sensor has missed its expected update interval.
+    is_stale: bool,
 }
 
 impl<'a> SensorTilePartialTemplate<'a> {
-    fn new(sensor: &'a Sensor, reading: &'a Reading) -> Self {
-        SensorTilePartialTemplate { sensor, reading }
+    fn new(base_path: &'a BasePath, sensor: &'a Sensor, reading: &'a Reading) -> Self {
+        SensorTilePartialTemplate {
+            base_path,
+            sensor,
+            reading,
+            column_width: reading.value.column_width(),
+            chart: String::new(),
+            control: render_control(base_path, sensor, reading),
+            is_stale: staleness::is_stale(&sensor.id, reading),
+        }
+    }
+}
+
+/// Input which sends `Write` messages to a controllable sensor.
+#[derive(Template)]
+#[template(path = "partials/control.html")]
+struct ControlPartialTemplate<'a> {
+    base_path: &'a BasePath,
+    sensor_id: &'a str,
+
+    /// Input type: `toggle`, `range`, `number` or `select`.
+    input: &'static str,
+
+    min: Option<f64>,
+    max: Option<f64>,
+    step: f64,
+    options: &'a [String],
+
+    /// Whether a toggle is currently on.
+    is_on: bool,
+
+    /// Current value as it's put into a numeric input or a select.
+    current: String,
+}
+
+impl<'a> ControlPartialTemplate<'a> {
+    fn new(base_path: &'a BasePath, sensor_id: &'a str, control: &'a Control, value: &Value) -> Self {
+        let template = ControlPartialTemplate {
+            base_path,
+            sensor_id,
+            input: "toggle",
+            min: None,
+            max: None,
+            step: 1.0,
+            options: &[],
+            is_on: *value == Value::Boolean(true),
+            current: match value {
+                Value::Text(text) => text.clone(),
+                _ => value.to_f64().map_or_else(String::new, |value| value.to_string()),
+            },
+        };
+        match control {
+            Control::Toggle => template,
+            Control::Slider { min, max, step } => ControlPartialTemplate {
+                input: "range",
+                min: Some(*min),
+                max: Some(*max),
+                step: *step,
+                ..template
+            },
+            Control::Number { min, max, step } => ControlPartialTemplate {
+                input: "number",
+                min: *min,
+                max: *max,
+                step: *step,
+                ..template
+            },
+            Control::Select { options } => ControlPartialTemplate {
+                input: "select",
+                options,
+                ..template
+            },
+        }
+    }
+}
+
+/// Renders the control input of the sensor, or an empty string if it isn't controllable.
+pub fn render_control(base_path: &BasePath, sensor: &Sensor, reading: &Reading) -> String {
+    sensor.control.as_ref().map_or_else(String::new, |control| {
+        ControlPartialTemplate::new(base_path, &sensor.id, control, &reading.value).to_string()
+    })
+}
+
+/// Renders a dashboard tile, used to update it in place.
+pub fn render_tile(base_path: &BasePath, sensor: &Sensor, reading: &Reading) -> String {
+    SensorTilePartialTemplate::new(base_path, sensor, reading).to_string()
+}
+
+/// Renders a tile of a user-defined dashboard.
+pub fn render_dashboard_tile(
+    base_path: &BasePath,
+    sensor: &Sensor,
+    reading: &Reading,
+    size: Option<TileSize>,
+    chart: String,
+) -> String {
+    SensorTilePartialTemplate {
+        column_width: size.map_or_else(|| reading.value.column_width(), TileSize::column_width),
+        chart,
+        ..SensorTilePartialTemplate::new(base_path, sensor, reading)
+    }
+    .to_string()
+}
+
+impl TileSize {
+    pub fn column_width(self) -> &'static str {
+        match self {
+            TileSize::Small => "is-2",
+            TileSize::Medium => "is-3",
+            TileSize::Large => "is-4",
+            TileSize::Wide => "is-6",
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "chart.html")]
+pub struct ChartTemplate {
+    pub layout: Layout,
+
+    /// Comma-separated sensor IDs.
+    pub sensor_ids: String,
+
+    pub minutes: i64,
+
+    /// Period-over-period shift, `0` to not compare.
+    pub compare_minutes: i64,
+
+    pub chart_html: String,
+}
+
+impl ChartTemplate {
+    fn period_options(&self) -> String {
+        render_options(
+            &[
+                (60, "1 hour"),
+                (360, "6 hours"),
+                (1440, "1 day"),
+                (10080, "1 week"),
+                (43200, "1 month"),
+            ],
+            self.minutes,
+        )
+    }
+
+    fn compare_options(&self) -> String {
+        render_options(
+            &[
+                (0, "No comparison"),
+                (1440, "Previous day"),
+                (10080, "Previous week"),
+                (43200, "Previous month"),
+            ],
+            self.compare_minutes,
+        )
+    }
+}
+
+/// Renders the `<option>`s of a `<select>`.
+fn render_options(options: &[(i64, &str)], selected: i64) -> String {
+    options
+        .iter()
+        .map(|(value, title)| {
+            // language=HTML
+            format!(
+                r#"<option value="{}"{}>{}</option>"#,
+                value,
+                if *value == selected { " selected" } else { "" },
+                title,
+            )
+        })
+        .collect()
+}
+
+#[derive(Template)]
+#[template(path = "energy.html")]
+pub struct EnergyTemplate {
+    pub layout: Layout,
+
+    /// First day of the period, `YYYY-MM-DD`.
+    pub since: String,
+
+    /// Last day of the period, `YYYY-MM-DD`.
+    pub until: String,
+
+    pub resolution: Resolution,
+
+    /// Totals within the period, in kWh.
+    pub total_consumption: f64,
+    pub total_production: f64,
+
+    /// Whether any of the sensors match the energy patterns.
+    pub has_meters: bool,
+
+    pub bars: Vec<Bar>,
+
+    /// Daily consumption over the last year, starting on Monday.
+    pub heatmap: Vec<HeatmapDay>,
+}
+
+impl EnergyTemplate {
+    fn net(&self) -> f64 {
+        self.total_consumption - self.total_production
+    }
+
+    fn resolution_options(&self) -> String {
+        [Resolution::Day, Resolution::Hour]
+            .iter()
+            .map(|resolution| {
+                // language=HTML
+                format!(
+                    r#"<option value="{}"{}>By {}</option>"#,
+                    resolution.as_str(),
+                    if *resolution == self.resolution {
+                        " selected"
+                    } else {
+                        ""
+                    },
+                    resolution.as_str(),
+                )
+            })
+            .collect()
+    }
+
+    fn chart(&self) -> serde_json::Value {
+        // Three decimals are enough for kWh and keep the page smaller.
+        let round = |kwh: f64| (kwh * 1000.0).round() / 1000.0;
+        json!({
+            "type": "bar",
+            "options": {
+                "animation": {"duration": 0},
+                "maintainAspectRatio": false,
+                "scales": {
+                    "xAxes": [{"ticks": {"autoSkipPadding": 10}}],
+                    "yAxes": [{
+                        "scaleLabel": {"display": true, "labelString": "kWh"},
+                        "ticks": {"beginAtZero": true},
+                    }],
+                },
+                "tooltips": {"intersect": false, "mode": "index"},
+            },
+            "data": {
+                "labels": self.bars.iter().map(|bar| bar.label.as_str()).collect_vec(),
+                "datasets": [
+                    {
+                        "label": "Consumption",
+                        "backgroundColor": CHART_COLORS[1],
+                        "data": self.bars.iter().map(|bar| round(bar.consumption)).collect_vec(),
+                    },
+                    {
+                        "label": "Production",
+                        "backgroundColor": CHART_COLORS[2],
+                        "data": self.bars.iter().map(|bar| round(bar.production)).collect_vec(),
+                    },
+                ],
+            },
+        })
+    }
+
+    /// Formats the heatmap day for its tooltip.
+    fn heatmap_title(&self, day: &HeatmapDay) -> String {
+        format!(
+            "{}: {:.1} kWh",
+            day.date.format(datetime::datetime_format().date_strftime()),
+            day.consumption,
+        )
+    }
+}
+
+#[derive(Template)]
+#[template(path = "dashboard.html")]
+pub struct DashboardTemplate {
+    pub layout: Layout,
+    pub slug: String,
+    pub title: String,
+
+    pub sections: Vec<DashboardSectionPartial>,
+
+    /// Dashboards from the settings can't be edited in the web interface.
+    pub is_editable: bool,
+}
+
+/// Rendered dashboard section.
+pub struct DashboardSectionPartial {
+    pub title: Option<String>,
+    pub tiles: Vec<String>,
+
+    /// Chart URLs and rendered charts.
+    pub charts: Vec<(String, String)>,
+}
+
+#[derive(Template)]
+#[template(path = "dashboard_editor.html")]
+pub struct DashboardEditorTemplate {
+    pub layout: Layout,
+    pub slug: String,
+
+    /// Dashboard in TOML.
+    pub content: String,
+
+    pub error: Option<String>,
+}
+
+/// Tiny chart without axes which is embedded into a dashboard tile.
+#[derive(Template)]
+#[template(path = "partials/sparkline.html")]
+pub struct SparklinePartialTemplate {
+    /// Unique canvas ID on a page.
+    id: String,
+
+    chart: serde_json::Value,
+}
+
+impl SparklinePartialTemplate {
+    /// Renders the chart, the values are downsampled to `max_points` to keep the page size bounded.
+    pub fn new(id: String, values: Vec<(DateTime<Local>, f64)>, multiplier: f64, max_points: usize) -> Self {
+        let values = lttb::downsample(values, max_points);
+        SparklinePartialTemplate {
+            id,
+            chart: json!({
+                "type": "line",
+                "options": {
+                    "animation": {"duration": 0},
+                    "maintainAspectRatio": false,
+                    "legend": {"display": false},
+                    "tooltips": {"enabled": false},
+                    "scales": {
+                        "xAxes": [{"type": "time", "display": false}],
+                        "yAxes": [{"display": false}],
+                    },
+                    "elements": {"point": {"radius": 0}},
+                },
+                "data": {
+                    "datasets": [{
+                        "borderColor": "#209CEE",
+                        "borderWidth": 1,
+                        "fill": false,
+                        "data": values.iter().map(|(timestamp, value)| json!({
+                            "x": timestamp.timestamp_millis(),
+                            "y": value * multiplier,
+                        })).collect::<serde_json::Value>(),
+                    }],
+                },
+            }),
+        }
     }
 }
 
@@ -82,10 +807,15 @@ impl<'a> SensorTilePartialTemplate<'a> {
 #[template(path = "partials/chart.html")]
 pub struct F64ChartPartialTemplate {
     chart: serde_json::Value,
+
+    /// Used to scale the values which are pushed to the chart on a page.
+    multiplier: f64,
 }
 
 impl F64ChartPartialTemplate {
-    pub fn new(sensor_title: &str, values: Vec<(DateTime<Local>, f64)>, multiplier: f64) -> Self {
+    /// Renders the chart, the values are downsampled to `max_points` to keep the page size bounded.
+    pub fn new(sensor_title: &str, values: Vec<(DateTime<Local>, f64)>, multiplier: f64, max_points: usize) -> Self {
+        let values = lttb::downsample(values, max_points);
         F64ChartPartialTemplate {
             chart: json!({
                 "type": "line",
@@ -102,19 +832,100 @@ impl F64ChartPartialTemplate {
                     }],
                 },
             }),
+            multiplier,
+        }
+    }
+}
+
+/// Chart which overlays several sensors.
+#[derive(Template)]
+#[template(path = "partials/overlay_chart.html")]
+pub struct OverlayChartPartialTemplate {
+    /// Unique canvas ID on a page.
+    id: String,
+
+    chart: serde_json::Value,
+}
+
+/// Chart line.
+pub struct Series {
+    pub label: String,
+    pub unit: &'static str,
+    pub values: Vec<(DateTime<Local>, f64)>,
+
+    /// Whether the series is drawn dashed, used for the earlier period.
+    pub is_dashed: bool,
+
+    /// Index in the color palette, the same for a sensor and its earlier period.
+    pub color_index: usize,
+}
+
+/// Line colors, they follow the [Bulma](https://bulma.io/documentation/overview/colors/) palette.
+const CHART_COLORS: &[&str] = &["#209CEE", "#FF3860", "#23D160", "#FFDD57", "#00D1B2", "#363636"];
+
+impl OverlayChartPartialTemplate {
+    /// Renders the chart, each series is downsampled to `max_points`.
+    pub fn new(id: String, series: Vec<Series>, max_points: usize) -> Self {
+        let series = series
+            .into_iter()
+            .map(|series| Series {
+                values: lttb::downsample(series.values, max_points),
+                ..series
+            })
+            .collect_vec();
+        let units = series.iter().map(|series| series.unit).unique().collect_vec();
+        OverlayChartPartialTemplate {
+            id,
+            chart: json!({
+                "type": "line",
+                "options": {
+                    "animation": {"duration": 0},
+                    "maintainAspectRatio": false,
+                    "scales": {
+                        "xAxes": [{
+                            "type": "time",
+                            "display": true,
+                            "time": chart_time_format(),
+                            "ticks": {"autoSkipPadding": 10},
+                        }],
+                        "yAxes": units.iter().enumerate().map(|(i, unit)| json!({
+                            "id": unit,
+                            "display": true,
+                            "position": if i % 2 == 0 { "left" } else { "right" },
+                            "scaleLabel": {"display": !unit.is_empty(), "labelString": unit},
+                            "gridLines": {"drawOnChartArea": i == 0},
+                        })).collect::<serde_json::Value>(),
+                    },
+                    "tooltips": {"intersect": false, "mode": "nearest"},
+                    "elements": {"point": {"radius": 0}},
+                },
+                "data": {
+                    "datasets": series.iter().map(|series| json!({
+                        "label": series.label,
+                        "yAxisID": series.unit,
+                        "borderColor": CHART_COLORS[series.color_index % CHART_COLORS.len()],
+                        "borderDash": if series.is_dashed { vec![5, 5] } else { vec![] },
+                        "fill": false,
+                        "data": series.values.iter().map(|(timestamp, value)| json!({
+                            "x": timestamp.timestamp_millis(),
+                            "y": value,
+                        })).collect::<serde_json::Value>(),
+                    })).collect::<serde_json::Value>(),
+                },
+            }),
         }
     }
 }
 
 fn chart_time_format() -> serde_json::Value {
+    let format = datetime::datetime_format();
     json!({
-        "tooltipFormat": "MMM DD HH:mm:ss.SSS",
-        "displayFormats": {
-            "millisecond": "HH:mm:ss.SSS",
-            "second": "HH:mm:ss",
-            "minute": "HH:mm",
-            "hour": "HH",
-        },
+        "tooltipFormat": format.moment_tooltip_format(),
+        "displayFormats": format
+            .moment_display_formats()
+            .iter()
+            .map(|(unit, display_format)| (unit.to_string(), json!(display_format)))
+            .collect::<serde_json::Map<String, serde_json::Value>>(),
     })
 }
 
@@ -148,6 +959,58 @@ impl Value {
             _ => true,
         }
     }
+
+    pub fn history_view(&self) -> HistoryView {
+        match self {
+            _ if self.is_f64() => HistoryView::Chart,
+            Value::Boolean(_) | Value::Bft(_) => HistoryView::Timeline,
+            Value::WindDirection(_) => HistoryView::WindRose,
+            _ => HistoryView::EventLog,
+        }
+    }
+
+    /// Returns the plain text label of a discrete value.
+    pub fn state_label(&self) -> String {
+        match self {
+            Value::Boolean(true) => "Yes".into(),
+            Value::Boolean(false) => "No".into(),
+            Value::Bft(force) => format!("{} BFT", force),
+            Value::Text(text) => text.clone(),
+            _ => format!("{:?}", self),
+        }
+    }
+
+    /// Returns a [column size](https://bulma.io/documentation/columns/sizes/) suitable to fit the value.
+    pub fn column_width(&self) -> &'static str {
+        match self {
+            Value::ImageUrl(_) => "is-4",
+            _ => "is-3",
+        }
+    }
+
+    /// Returns the multiplier which converts the value into the units displayed on a chart.
+    pub fn chart_multiplier(&self) -> f64 {
+        match self {
+            Value::Energy(_) => WH_IN_JOULE,
+            _ => 1.0,
+        }
+    }
+
+    /// Returns the units displayed on a chart. Sensors with different units are put on separate axes.
+    pub fn chart_unit(&self) -> &'static str {
+        match self {
+            Value::Temperature(_) => "℃",
+            Value::Rh(_) | Value::Cloudiness(_) | Value::BatteryLife(_) | Value::RelativeIntensity(_) => "%",
+            Value::Power(_) => "W",
+            Value::Energy(_) => "Wh",
+            Value::Length(_) => "m",
+            Value::Speed(_) => "m/s",
+            Value::Volume(_) => "m³",
+            Value::Duration(_) => "s",
+            Value::DataSize(_) => "B",
+            _ => "",
+        }
+    }
 }
 
 impl std::fmt::Display for Value {
@@ -168,7 +1031,7 @@ impl std::fmt::Display for Value {
             ),
 
             // language=HTML
-            Value::Text(ref text) => write!(f, r#"<i class="fas fa-quote-left"></i> {}"#, text),
+            Value::Text(ref text) => write!(f, r#"<i class="fas fa-quote-left"></i> {}"#, escape_html(text)),
 
             // language=HTML
             Value::Temperature(celsius) => write!(
@@ -211,7 +1074,7 @@ impl std::fmt::Display for Value {
             Value::Length(meters) => write!(f, r#"<i class="fas fa-ruler"></i> {}"#, human_format(*meters, "m")),
 
             // language=HTML
-            Value::ImageUrl(url) => write!(f, r#"<img src="{}" alt="">"#, url),
+            Value::ImageUrl(url) => write!(f, r#"<img src="{}" alt="">"#, escape_html(url)),
 
             // language=HTML
             Value::Boolean(flag) => write!(
@@ -284,29 +1147,70 @@ impl Sensor {
     }
 }
 
+/// Escapes the text which gets rendered with `|safe` as a part of a value.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for char_ in text.chars() {
+        match char_ {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            _ => escaped.push(char_),
+        }
+    }
+    escaped
+}
+
 /// Wraps `crate_version!` in order to include it in a template.
 fn crate_version() -> &'static str {
     structopt::clap::crate_version!()
 }
 
+/// Lets the page scripts display the timestamps in the display time zone. Empty for the system one.
+fn timezone_name() -> &'static str {
+    datetime::timezone_name().unwrap_or_default()
+}
+
+fn is_hour12() -> bool {
+    datetime::datetime_format().is_hour12()
+}
+
 /// Custom [Askama template filters](https://docs.rs/askama/0.9.0/askama/index.html#filters).
 mod filters {
     use crate::prelude::*;
+    use crate::web::base_path::BasePath;
 
     pub fn slug<S: AsRef<str>>(string: S) -> askama::Result<String> {
         Ok(slug::slugify(string))
     }
 
-    pub fn format_datetime(datetime: &DateTime<Local>) -> askama::Result<String> {
-        Ok(datetime.format("%b %d, %H:%M:%S").to_string())
+    /// Resolves the content-hashed URL of a static asset, for example: `"bulma.min.css"`.
+    pub fn asset<T: std::fmt::Display>(path: T, base_path: &BasePath) -> askama::Result<String> {
+        Ok(base_path.asset(&path.to_string()))
     }
 
-    /// Returns a [column size](https://bulma.io/documentation/columns/sizes/) suitable to fit the value.
-    pub fn column_width(value: &Value) -> askama::Result<&'static str> {
-        Ok(match value {
-            Value::ImageUrl(_) => "is-4",
-            _ => "is-3",
-        })
+    /// Serializes the value into JSON which may be embedded into a `<script>` block.
+    /// Askama's own `json` filter leaves `</script>` as is, so it isn't used.
+    pub fn script_json<T: serde::Serialize>(value: &T) -> askama::Result<String> {
+        Ok(serde_json::to_string(value)
+            .map_err(askama::Error::Json)?
+            .replace('<', "\\u003c")
+            .replace('>', "\\u003e")
+            .replace('&', "\\u0026")
+            .replace('\u{2028}', "\\u2028")
+            .replace('\u{2029}', "\\u2029"))
+    }
+
+    /// Prepends the base path to the absolute path.
+    pub fn prefixed<T: std::fmt::Display>(path: T, base_path: &BasePath) -> askama::Result<String> {
+        Ok(base_path.prefixed(path))
+    }
+
+    /// Formats the timestamp in the display time zone and format.
+    pub fn format_datetime(datetime: &DateTime<Local>) -> askama::Result<String> {
+        Ok(crate::core::datetime::format(datetime))
     }
 
     /// Returns a [color class](https://bulma.io/documentation/modifiers/color-helpers/) to display the value.
@@ -359,3 +1263,76 @@ mod filters {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_value_escaped() {
+        assert_eq!(
+            Value::Text("<img src=x onerror=\"alert('x')\">".into()).to_string(),
+            r#"<i class="fas fa-quote-left"></i> &lt;img src=x onerror=&quot;alert(&#x27;x&#x27;)&quot;&gt;"#
+        );
+    }
+
+    #[test]
+    fn script_json_escaped() -> askama::Result<()> {
+        assert_eq!(
+            filters::script_json(&"</script>&\u{2028}")?,
+            r#""\u003c/script\u003e\u0026\u2028""#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn charts_downsampled() {
+        let values = || {
+            (0..100)
+                .map(|i| (Local.timestamp_millis(i * 1000), (i % 7) as f64))
+                .collect_vec()
+        };
+        let sparkline = SparklinePartialTemplate::new("0".into(), values(), 1.0, 10);
+        assert_eq!(
+            sparkline.chart["data"]["datasets"][0]["data"].as_array().unwrap().len(),
+            10
+        );
+        let overlay = OverlayChartPartialTemplate::new(
+            "0".into(),
+            vec![Series {
+                label: "Test".into(),
+                unit: "",
+                values: values(),
+                is_dashed: false,
+                color_index: 0,
+            }],
+            10,
+        );
+        assert_eq!(
+            overlay.chart["data"]["datasets"][0]["data"].as_array().unwrap().len(),
+            10
+        );
+    }
+
+    #[test]
+    fn timeline_merges_equal_states() {
+        let since = Local.timestamp_millis(0);
+        let reading = |minutes, value| Reading {
+            timestamp: since + chrono::Duration::minutes(minutes),
+            value: Value::Boolean(value),
+        };
+        let timeline = TimelinePartialTemplate::new(
+            &[reading(15, true), reading(30, true), reading(45, false)],
+            &[
+                (Value::Boolean(false), chrono::Duration::minutes(15)),
+                (Value::Boolean(true), chrono::Duration::minutes(30)),
+            ],
+            since,
+            since + chrono::Duration::minutes(60),
+        );
+        let labels: Vec<&str> = timeline.segments.iter().map(|segment| segment.label.as_str()).collect();
+        assert_eq!(labels, vec!["", "Yes", "No"]);
+        assert!((timeline.segments[1].width - 50.0).abs() < 1e-6);
+        assert_eq!(timeline.totals[0].2, "30 min");
+    }
+}
@@ -0,0 +1,194 @@
+//! Serves HTTPS in front of the web server.
+//!
+//! Rocket's `tls` feature depends on an older `ring`, which can't be linked together with the one
+//! My IoT depends on. Instead, the connections get decrypted here
+//! and forwarded to the web server, which then listens on the loopback interface.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Mutex, MutexGuard};
+
+use rustls::internal::pemfile;
+use rustls::{NoClientAuth, PrivateKey, ServerConfig, ServerSession, Session};
+
+use crate::prelude::*;
+use crate::settings::Tls;
+
+const BUFFER_SIZE: usize = 16384;
+
+/// Reads the certificate chain and the private key.
+pub fn make_config(settings: &Tls) -> Result<Arc<ServerConfig>> {
+    let certificates = pemfile::certs(&mut BufReader::new(File::open(&settings.certificate_path)?))
+        .map_err(|_| format!("failed to read the certificates from `{}`", settings.certificate_path))?;
+    if certificates.is_empty() {
+        return Err(format!("no certificates found in `{}`", settings.certificate_path).into());
+    }
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config.set_single_cert(certificates, read_private_key(&settings.private_key_path)?)?;
+    Ok(Arc::new(config))
+}
+
+/// Reads the first PKCS #8 or RSA private key.
+fn read_private_key(path: &str) -> Result<PrivateKey> {
+    let error = || format!("failed to read the private key from `{}`", path);
+    let mut keys = pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(path)?)).map_err(|_| error())?;
+    keys.extend(pemfile::rsa_private_keys(&mut BufReader::new(File::open(path)?)).map_err(|_| error())?);
+    Ok(keys.into_iter().next().ok_or_else(error)?)
+}
+
+/// Returns a loopback address with a port which is free at the moment.
+pub fn unused_loopback_address() -> Result<SocketAddr> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?)
+}
+
+/// Accepts the TLS connections on the address and forwards them to the web server.
+pub fn spawn(address: &str, port: u16, upstream: SocketAddr, config: Arc<ServerConfig>) -> Result {
+    let listener = TcpListener::bind((address, port))?;
+    thread::Builder::new()
+        .name(format!("web::tls::{}", address))
+        .spawn(move || {
+            for client in listener.incoming() {
+                let client = match client {
+                    Ok(client) => client,
+                    Err(error) => {
+                        warn!("Failed to accept a connection: {}.", error);
+                        continue;
+                    }
+                };
+                let config = config.clone();
+                if let Err(error) = thread::Builder::new()
+                    .name("web::tls::connection".into())
+                    .spawn(move || {
+                        if let Err(error) = forward(client, &config, upstream) {
+                            debug!("TLS connection has failed: {}.", error);
+                        }
+                    })
+                {
+                    error!("Failed to spawn a connection thread: {}.", error);
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// TLS session which is shared by the both directions of a connection.
+struct Tunnel {
+    session: Mutex<ServerSession>,
+
+    /// The encrypted records must reach the client in the order the session produces them.
+    client: Mutex<TcpStream>,
+}
+
+impl Tunnel {
+    /// Sends the pending TLS records to the client, without blocking the session meanwhile.
+    fn flush(&self, mut session: MutexGuard<ServerSession>) -> io::Result<()> {
+        let mut records = Vec::new();
+        while session.wants_write() {
+            session.write_tls(&mut records)?;
+        }
+        let mut client = self.client.lock().unwrap();
+        drop(session);
+        client.write_all(&records)
+    }
+}
+
+/// Decrypts the client connection and forwards it to the web server, and the other way around.
+fn forward(client: TcpStream, config: &Arc<ServerConfig>, upstream: SocketAddr) -> Result {
+    let upstream = TcpStream::connect(upstream)?;
+    let tunnel = Arc::new(Tunnel {
+        session: Mutex::new(ServerSession::new(config)),
+        client: Mutex::new(client.try_clone()?),
+    });
+    {
+        let tunnel = tunnel.clone();
+        let upstream = upstream.try_clone()?;
+        thread::Builder::new()
+            .name("web::tls::responses".into())
+            .spawn(move || {
+                if let Err(error) = forward_responses(&tunnel, upstream) {
+                    debug!("Failed to forward the responses: {}.", error);
+                }
+                let _ = tunnel.client.lock().unwrap().shutdown(Shutdown::Both);
+            })?;
+    }
+    let result = forward_requests(&tunnel, client, upstream.try_clone()?);
+    // Let the web server finish the pending response, unless the connection is broken.
+    let _ = upstream.shutdown(if result.is_ok() {
+        Shutdown::Write
+    } else {
+        Shutdown::Both
+    });
+    result
+}
+
+/// Decrypts the client records and sends the plaintext to the web server.
+fn forward_requests(tunnel: &Tunnel, mut client: TcpStream, mut upstream: TcpStream) -> Result {
+    let mut buffer = [0; BUFFER_SIZE];
+    let mut chunk = [0; BUFFER_SIZE];
+    loop {
+        let size = client.read(&mut buffer)?;
+        if size == 0 {
+            return Ok(());
+        }
+        let mut records = &buffer[..size];
+        let mut plaintext = Vec::new();
+        let mut is_closed = false;
+        while !records.is_empty() && !is_closed {
+            let mut session = tunnel.session.lock().unwrap();
+            if session.read_tls(&mut records)? == 0 {
+                break;
+            }
+            let processed = session.process_new_packets();
+            loop {
+                match session.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(size) => plaintext.extend_from_slice(&chunk[..size]),
+                    // The client has sent `close_notify`.
+                    Err(ref error) if error.kind() == io::ErrorKind::ConnectionAborted => {
+                        is_closed = true;
+                        break;
+                    }
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            // Handshake messages and alerts.
+            tunnel.flush(session)?;
+            processed?;
+        }
+        upstream.write_all(&plaintext)?;
+        if is_closed {
+            return Ok(());
+        }
+    }
+}
+
+/// Encrypts the web server responses and sends them to the client.
+fn forward_responses(tunnel: &Tunnel, mut upstream: TcpStream) -> Result {
+    let mut buffer = [0; BUFFER_SIZE];
+    loop {
+        let size = upstream.read(&mut buffer)?;
+        let mut session = tunnel.session.lock().unwrap();
+        if size == 0 {
+            session.send_close_notify();
+            tunnel.flush(session)?;
+            return Ok(());
+        }
+        session.write_all(&buffer[..size])?;
+        tunnel.flush(session)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_certificate_fails() {
+        assert!(make_config(&Tls {
+            certificate_path: "missing.pem".into(),
+            private_key_path: "missing.pem".into(),
+        })
+        .is_err());
+    }
+}
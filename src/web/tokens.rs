@@ -0,0 +1,95 @@
+//! API token management.
+
+use rocket::response::Redirect;
+use rocket::{delete, get, post, uri, FromForm, State};
+
+use crate::core::auth::{generate_token, hash_token};
+use crate::core::db::api_token::Scope;
+use crate::prelude::*;
+use crate::web::auth::{Admin, Csrf, Empty};
+use crate::web::base_path::BasePath;
+use crate::web::layout::Layout;
+use crate::web::templates;
+use crate::web::to_html_string::ToHtmlString;
+
+#[derive(FromForm)]
+pub struct NewTokenForm {
+    name: String,
+
+    /// Whitespace-separated scopes, for example: `read:buienradar::* ingest:esp::`.
+    scopes: String,
+}
+
+#[get("/tokens")]
+pub fn get_tokens(_user: Admin, layout: Layout, db: State<Connection>) -> Result<ToHtmlString<impl ToString>> {
+    render(layout, &db, None, None)
+}
+
+/// Creates the token and shows it just once.
+#[post("/tokens", data = "<form>")]
+pub fn post_token(
+    _user: Admin,
+    layout: Layout,
+    db: State<Connection>,
+    form: Csrf<NewTokenForm>,
+) -> Result<ToHtmlString<impl ToString>> {
+    let Csrf(form) = form;
+    let name = form.name.trim();
+    if name.is_empty() || db.select_api_tokens()?.iter().any(|token| token.name == name) {
+        return render(layout, &db, None, Some("The name must be unique and non-empty".into()));
+    }
+    let scopes = match parse_scopes(&form.scopes) {
+        Ok(scopes) => scopes,
+        Err(error) => return render(layout, &db, None, Some(error)),
+    };
+    let token = generate_token()?;
+    db.insert_api_token(name, &hash_token(&token), &scopes)?;
+    info!("API token `{}` has been created.", name);
+    render(
+        layout,
+        &db,
+        Some(templates::CreatedToken {
+            name: name.to_string(),
+            token,
+        }),
+        None,
+    )
+}
+
+#[delete("/tokens/<name>", data = "<_form>")]
+pub fn delete_token(
+    _user: Admin,
+    base_path: BasePath,
+    db: State<Connection>,
+    name: String,
+    _form: Csrf<Empty>,
+) -> Result<Redirect> {
+    db.delete_api_token(&name)?;
+    info!("API token `{}` has been revoked.", name);
+    Ok(Redirect::to(base_path.prefixed(uri!(get_tokens))))
+}
+
+fn render(
+    layout: Layout,
+    db: &Connection,
+    created_token: Option<templates::CreatedToken>,
+    error: Option<String>,
+) -> Result<ToHtmlString<impl ToString>> {
+    Ok(ToHtmlString(templates::TokensTemplate {
+        layout,
+        tokens: db.select_api_tokens()?,
+        created_token,
+        error,
+    }))
+}
+
+fn parse_scopes(scopes: &str) -> Result<Vec<Scope>, String> {
+    let scopes = scopes
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<Scope>, String>>()?;
+    if scopes.is_empty() {
+        return Err("At least one scope is required".into());
+    }
+    Ok(scopes)
+}